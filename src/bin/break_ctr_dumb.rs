@@ -1,50 +1,47 @@
 extern crate cryptopals;
 
-use std::collections::VecDeque;
+use std::env;
 
 use cryptopals::block_ciphers::AESBlockMode;
+use cryptopals::fixed_nonce::{self, Crib};
+use cryptopals::scoring::ChiSquared;
 
-fn random_key(length: u8) -> Vec<u8> {
-    (0..length)
-        .map(|_| rand::random::<u8>())
-        .collect::<Vec<u8>>()
-}
+// keystream bytes less sure than this show up as '?'
+const CONFIDENT: f64 = 0.9;
 
-fn ascii_guess(nth_byte: usize, data: &Vec<Vec<u8>>) -> Vec<u8> {
-    let mut good_bytes = vec![];
-    for key_guess in 0..255 {
-        let is_good = data
-            .iter()
-            .map(|line| {
-                if line.len() <= nth_byte {
-                    true
-                } else {
-                    let pt_guess = line[nth_byte] ^ key_guess;
-                    pt_guess >= 32 && pt_guess <= 126
-                    //pt_guess >= 97 && pt_guess <= 122
-                }
-            })
-            .all(|x| x);
-        if is_good {
-            good_bytes.push(key_guess);
-        }
+/*
+the guessing is ours: every argument is a crib "line:offset:text", run again
+with more of them until nothing is left as '?'
+*/
+fn parse_crib(arg: &str) -> Crib {
+    let mut parts = arg.splitn(3, ':');
+    let mut number = || {
+        parts
+            .next()
+            .and_then(|part| part.parse().ok())
+            .expect("cribs look like line:offset:text")
+    };
+    let (ciphertext, offset) = (number(), number());
+    Crib {
+        ciphertext,
+        offset,
+        plaintext: parts
+            .next()
+            .expect("cribs look like line:offset:text")
+            .into(),
     }
-    good_bytes
 }
 
 fn main() {
     println!("(s3c19)");
-    println!("######################################################################");
-    println!("THIS DOES NOT DETERMINE THE EXACT CORRECT KEYSTREAM, BUT IT GETS CLOSE");
-    println!("######################################################################");
-    println!();
+    let cribs: Vec<Crib> = env::args().skip(1).map(|arg| parse_crib(&arg)).collect();
 
     let data = cryptopals::load_stdin();
-    let lines = data.lines();
-    let key = random_key(16);
+    let key = cryptopals::random_key(16_u8);
 
     // encrypt all the input lines
-    let cts = lines
+    let cts = data
+        .lines()
         .map(|line| {
             cryptopals::block_ciphers::aes_encrypt(
                 // decode the base64 to raw bytes
@@ -58,33 +55,16 @@ fn main() {
 
     println!("all input lines ({}) encrypted", cts.len(),);
 
-    let mut guessed_key: Vec<u8> = vec![];
-    for x in 0..255 {
-        let guesses = ascii_guess(x, &cts);
-        for g in &guesses {
-            let col_guess_bytes = &cts
-                .iter()
-                .filter_map(|ct| {
-                    if ct.len() > x {
-                        Some((ct[x] ^ g) as u8)
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>();
-            let col_guess_score = cryptopals::english_frequency_score(col_guess_bytes);
-            if col_guess_score >= 0 {
-                guessed_key.push(*g);
-                break;
-            }
-        }
-    }
+    let solution = fixed_nonce::solve(&cts, &ChiSquared::english(), None, &cribs, 1)
+        .expect("cribs have to fit their lines and agree with each other");
 
-    for ct in &cts {
-        let mut decrypted = vec![];
-        for (key_byte, ct_byte) in ct.to_vec().iter().zip(guessed_key.to_vec()) {
-            decrypted.push(key_byte ^ ct_byte);
-        }
-        println!("> {}", String::from_utf8_lossy(&decrypted));
+    for (i, ct) in cts.iter().enumerate() {
+        let marked: String = solution
+            .decrypt(ct)
+            .iter()
+            .zip(solution.confidence.iter())
+            .map(|(&b, &c)| if c < CONFIDENT { '?' } else { b as char })
+            .collect();
+        println!("{:2}> {}", i, marked);
     }
 }
@@ -1,32 +1,47 @@
 extern crate cryptopals;
 
+use cryptopals::repeating_xor::{break_repeating_xor, Pins};
+use cryptopals::scoring::ChiSquared;
 use std::env;
 
+// key sizes tried when none is given
+const DEFAULT_MAX_LEN: usize = 40;
+// from each of the two key size rankings
+const TOP_SIZES: usize = 3;
+
 fn main() {
     eprintln!("(s1c6)");
     let data = cryptopals::base64_to_bytes(&cryptopals::load_stdin());
 
-    let max_len = &env::args().collect::<Vec<_>>()[1]
-        .parse()
-        .expect("first argument has to be number");
+    let max_len = env::args().nth(1).map_or(DEFAULT_MAX_LEN, |arg| {
+        arg.parse().expect("first argument has to be number")
+    });
     eprintln!(
         "trying for keys up to length '{}' inner data first bytes {:x?}",
         max_len,
         &data[0..10]
     );
 
-    let mut best_result = (-0xffff, vec![], vec![]);
-
-    for try_length in 1..=*max_len {
-        let (best_score, best_key, best_output) = cryptopals::search_xor_key(&data, try_length);
-        if best_score > best_result.0 {
-            best_result = (best_score, best_key, best_output);
-        }
+    let candidates = break_repeating_xor(
+        &data,
+        max_len,
+        TOP_SIZES,
+        &Pins::default(),
+        &ChiSquared::english(),
+    );
+    for candidate in candidates.iter().skip(1) {
+        eprintln!(
+            "also tried key {:x?} scoring {:.1}",
+            candidate.key, candidate.score
+        );
     }
 
-    let readable_result = String::from_utf8(best_result.2.clone()).unwrap();
-    println!(
-        "best key {:x?} with output:\n{}",
-        best_result.1, readable_result
-    );
+    match candidates.first() {
+        Some(best) => println!(
+            "best key {:x?} with output:\n{}",
+            best.key,
+            String::from_utf8_lossy(&best.plaintext)
+        ),
+        None => println!("no key up to length {} fits this data", max_len),
+    }
 }
@@ -1,11 +1,11 @@
 extern crate cryptopals;
 
 use cryptopals::get_timestamp;
+use cryptopals::seed_recovery::{recover_seed_from_outputs, time_window};
 
-// this is to speed up the brute force search, let's NOT search
-// all the timestamps since the epoch
-// 1.5B is 2017-07-14T
-const START_SECONDS: u64 = 1_500_000_000;
+// how far back in time the seed search goes, an hour is plenty for a
+// generator seeded "a few seconds ago"
+const WINDOW_SECONDS: u64 = 3600;
 
 fn main() {
     println!("(s3c22)");
@@ -20,19 +20,13 @@ fn main() {
     println!("got random value: {}", random_value);
 
     // obiously the answer is the current value of get_timestamp()
-    for i in START_SECONDS..=get_timestamp().as_secs() as u64 {
-        let mut mt = cryptopals::mt19937::Mt19937::from_seed(i as u32);
-        if i % 1_000_000 == 0 {
-            println!("checking seed {}", i);
-        }
-        if mt.extract_number() == random_value {
-            let now = get_timestamp().as_secs() as u64;
-            println!(
-                "found number to be from seed: {}, this is {}s ago",
-                i,
-                now - i
-            );
-            break;
-        }
+    let now = get_timestamp().as_secs() as u64;
+    match recover_seed_from_outputs(&[random_value], 0, time_window(now, WINDOW_SECONDS), 4) {
+        Some((found, _)) => println!(
+            "found number to be from seed: {}, this is {}s ago",
+            found,
+            now - found as u64
+        ),
+        None => println!("no seed in the last {}s", WINDOW_SECONDS),
     }
 }
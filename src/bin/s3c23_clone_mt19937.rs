@@ -1,7 +1,5 @@
 extern crate cryptopals;
 
-use std::num::Wrapping;
-
 use cryptopals::get_timestamp;
 use cryptopals::mt19937::Mt19937;
 
@@ -11,18 +9,26 @@ fn main() {
     let seed = get_timestamp().as_secs() as u32;
     let mut authentic_rng = Mt19937::from_seed(seed);
 
-    let mut cloned_state = [Wrapping(0); n];
-    for x in cloned_state.iter_mut() {
-        let rng_value = authentic_rng.extract_number();
-        let untempered_value = authentic_rng.untemper(rng_value);
-        *x = Wrapping(untempered_value);
-    }
-
-    let mut cloned_rng = Mt19937::from_state(cloned_state, n as u32);
+    let outputs: Vec<u32> = (0..n).map(|_| authentic_rng.extract_number()).collect();
+    let mut cloned_rng = Mt19937::clone_from_outputs(&outputs).unwrap();
 
     println!(
         "authentic next: {} cloned next: {}",
         authentic_rng.extract_number(),
         cloned_rng.extract_number()
     );
+
+    /*
+    8 bits of every output are enough too, it just takes more of them
+    */
+    let outputs: Vec<u32> = (0..2600)
+        .map(|_| authentic_rng.extract_number() >> 24)
+        .collect();
+    let mut cloned_rng = Mt19937::clone_from_truncated(&outputs, 8).unwrap();
+
+    println!(
+        "authentic next: {} cloned from truncated outputs next: {}",
+        authentic_rng.extract_number(),
+        cloned_rng.extract_number()
+    );
 }
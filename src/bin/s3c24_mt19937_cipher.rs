@@ -1,7 +1,9 @@
 extern crate cryptopals;
 
 use cryptopals::get_timestamp;
-use cryptopals::mt19937::Mt19937;
+use cryptopals::seed_recovery::{
+    mt_crypt, recover_stream_key, time_window, token_seed, TokenIssuer,
+};
 
 const PT: &str = "FOOBAAAAAAAAAAAAAAAAAAAR";
 
@@ -17,37 +19,36 @@ fn main() {
     .concat();
 
     // encrypt the data
-    let mut authentic_rng = Mt19937::from_seed(seed);
-    let ct = pt
-        .iter()
-        .map(|x: &u8| *x ^ authentic_rng.extract_number() as u8)
-        .collect::<Vec<_>>();
+    let ct = mt_crypt(seed as u16, &pt);
     println!(">> ct: {:?}", ct);
 
-    // use new mt19937 to decrypt the data
-    let mut authentic_rng = Mt19937::from_seed(seed);
-    let pt: String = ct
-        .clone()
+    // decrypting is the same operation
+    let pt: String = mt_crypt(seed as u16, &ct)
         .into_iter()
-        .map(|x: u8| x ^ authentic_rng.extract_number() as u8)
         .map(|x: u8| x as char)
         .collect::<String>();
     println!(">> pt: {:?}", pt);
 
-    // try all seeds on the ct and end when candidate pt ends with our known-pt
-    for seed_candidate in 0..u32::MAX {
-        let mut rng = Mt19937::from_seed(seed_candidate);
-        let pt = &((&ct)
-            .iter()
-            .map(|x: &u8| *x ^ rng.extract_number() as u8)
-            .map(|x: u8| x as char)
-            .collect::<String>());
-        if pt.ends_with(PT) {
-            println!("found seed: {}", seed);
-            break;
-        }
+    // try all 16 bit seeds until the known plaintext shows up after the prefix
+    match recover_stream_key(&ct, PT.as_bytes(), 4) {
+        Some(found) => println!("found seed: {} [secret: {}]", found, seed),
+        None => println!("seed not found"),
     }
 
-    // TODO: the "password reset token" part seem to be basically identical to what
-    // we just did. so not going to do this for now, I may come back to this later
+    // password reset tokens from an rng seeded with the current time
+    let now = get_timestamp().as_secs();
+    let mut issuer = TokenIssuer::from_time();
+    let skipped = rand::random::<u8>() % 8;
+    for _ in 0..skipped {
+        issuer.next_token();
+    }
+    let token = issuer.next_token();
+    println!(">> token: {}", token);
+    match token_seed(&token, time_window(now + 1, 3600), 8, 4) {
+        Some((seed, index)) => println!(
+            "token #{} of an rng seeded with the time {} [secret: #{}]",
+            index, seed, skipped
+        ),
+        None => println!("token is not from a time-seeded rng"),
+    }
 }
@@ -1,6 +1,8 @@
 extern crate cryptopals;
 
 use cryptopals::block_ciphers::AESBlockMode;
+use cryptopals::dh::groups;
+use cryptopals::framing::Message;
 use num_bigint::{BigUint, ToBigUint};
 
 /*
@@ -13,21 +15,8 @@ In this attack, in comparison to the previous, we only modify traffic going to B
 */
 
 pub fn main() {
-    let g = 2.to_biguint().unwrap();
-    let p = BigUint::parse_bytes(
-        "ffffffffffffffffc90fdaa22168c234c4c6628b80dc1cd129024\
-    e088a67cc74020bbea63b139b22514a08798e3404ddef9519b3cd\
-    3a431b302b0a6df25f14374fe1356d6d51c245e485b576625e7ec\
-    6f44c42e9a637ed6b0bff5cb6f406b7edee386bfb5a899fa5ae9f\
-    24117c4b1fe649286651ece45b3dc2007cb8a163bf0598da48361\
-    c55d39a69163fa8fd24cf5f83655d23dca3ad961c62f356208552\
-    bb9ed529077096966d670c354e4abc9804f1746c08ca237327fff\
-    fffffffffffff"
-            .to_string()
-            .as_bytes(),
-        16,
-    )
-    .unwrap();
+    let g = groups::MODP_1536.g();
+    let p = groups::MODP_1536.p();
 
     println!("=== Correct KEX");
     process(&g, &p, None);
@@ -47,23 +36,37 @@ fn process(g: &BigUint, p: &BigUint, attack_g: Option<&BigUint>) {
     do the DH KEX
     */
     let alice = cryptopals::dh::DiffieHellmanState::new(&g, &p);
-    let alice_pubkey = alice.pubkey.clone();
-    println!("alice pubkey: {:X}", alice_pubkey);
+    println!("alice pubkey: {:X}", alice.pubkey);
 
     // Attack: sending g==[0|1|p|p-1] makes Bobs pubkey either 0 or 1,
     // which makes Alices sharedkey 0 or 1
-    let bob = match attack_g {
-        None => cryptopals::dh::DiffieHellmanState::new(&g, &p),
-        Some(fake_g) => cryptopals::dh::DiffieHellmanState::new(&fake_g, &p),
+    let alice_hello = Message::new()
+        .with(p)
+        .with(g)
+        .with(&alice.pubkey)
+        .to_bytes();
+    let alice_hello = match attack_g {
+        None => alice_hello,
+        Some(fake_g) => Message::new().with(p).with(fake_g).with(fake_g).to_bytes(),
     };
-    let bob_pubkey = bob.pubkey.clone();
-    println!("bob pubkey: {:X}", bob_pubkey);
+
+    let hello = Message::from_bytes(&alice_hello).unwrap();
+    let mut fields = hello.fields();
+    let bob_p = fields.read::<BigUint>().unwrap();
+    let bob_g = fields.read::<BigUint>().unwrap();
+    let alice_pubkey = fields.read::<BigUint>().unwrap();
+    let bob = cryptopals::dh::DiffieHellmanState::new(&bob_g, &bob_p);
+    println!("bob pubkey: {:X}", bob.pubkey);
+    let bob_reply = Message::new().with(&bob.pubkey).to_bytes();
+
+    let bob_pubkey = Message::from_bytes(&bob_reply)
+        .unwrap()
+        .fields()
+        .read::<BigUint>()
+        .unwrap();
 
     let alice_sharkey = alice.gen_shared_key(&bob_pubkey);
-    let bob_sharkey = match attack_g {
-        None => bob.gen_shared_key(&alice_pubkey),
-        Some(fake_g) => bob.gen_shared_key(&fake_g),
-    };
+    let bob_sharkey = bob.gen_shared_key(&alice_pubkey);
 
     let mut alice_sha1 = cryptopals::sha1::Sha1::new();
     alice_sha1.update(alice_sharkey.to_string().as_bytes());
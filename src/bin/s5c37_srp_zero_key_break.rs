@@ -1,42 +1,28 @@
 extern crate cryptopals;
 
+use cryptopals::dh::groups;
+use cryptopals::srp::{Srp6a, SrpGroup, SrpHash};
 use std::collections::HashMap;
-use std::{thread, time};
 
-use num_bigint::{BigUint, ToBigUint};
+use num_bigint::ToBigUint;
 
 pub fn main() {
-    let N = BigUint::parse_bytes(
-        "ffffffffffffffffc90fdaa22168c234c4c6628b80dc1cd129024\
-    e088a67cc74020bbea63b139b22514a08798e3404ddef9519b3cd\
-    3a431b302b0a6df25f14374fe1356d6d51c245e485b576625e7ec\
-    6f44c42e9a637ed6b0bff5cb6f406b7edee386bfb5a899fa5ae9f\
-    24117c4b1fe649286651ece45b3dc2007cb8a163bf0598da48361\
-    c55d39a69163fa8fd24cf5f83655d23dca3ad961c62f356208552\
-    bb9ed529077096966d670c354e4abc9804f1746c08ca237327fff\
-    fffffffffffff"
-            .to_string()
-            .as_bytes(),
-        16,
-    )
-    .unwrap();
+    let N = groups::MODP_1536.p();
     let username = "admin".to_string();
     let password = "p@55w0rd".to_string();
-    let connstring = "localhost:7878".to_string();
 
     let mut users: HashMap<String, String> = HashMap::new();
     users.insert(username.clone(), password.clone());
 
-    cryptopals::srp::SRPServer::start(
-        connstring.clone(),
+    let server = cryptopals::srp::SRPServer::start(
+        "127.0.0.1:0".to_string(),
         N.clone(),
         2_usize.to_biguint().unwrap(),
         3_usize.to_biguint().unwrap(),
-        users,
-    );
-
-    let sleep_time = time::Duration::from_millis(100);
-    thread::sleep(sleep_time);
+        users.clone(),
+    )
+    .unwrap();
+    let connstring = server.connstring();
 
     println!("=== Normal auth");
     let authenticated = cryptopals::srp::SRPClient::auth(
@@ -45,7 +31,7 @@ pub fn main() {
         2_usize.to_biguint().unwrap(),
         3_usize.to_biguint().unwrap(),
         username.clone(),
-        password,
+        password.clone(),
         &None,
     );
     println!("authed as \"{}\": {}", &username, authenticated);
@@ -85,13 +71,53 @@ pub fn main() {
     let's override A and hardcode S to be 0
     */
     let authenticated = cryptopals::srp::SRPClient::auth(
-        connstring,
+        connstring.clone(),
         N.clone(),
         2_usize.to_biguint().unwrap(),
         3_usize.to_biguint().unwrap(),
         username.clone(),
         "wrongpassword".to_string(),
-        &Some(N * 2.to_biguint().unwrap()),
+        &Some(&N * 2.to_biguint().unwrap()),
     );
     println!("authed as \"{}\" : {}", username, authenticated);
+
+    /*
+    SRP-6a refuses A = 0 mod N, so the same attacks go nowhere
+    */
+    server.shutdown();
+    let srp = Srp6a::new(SrpGroup::rfc5054(1536).unwrap(), SrpHash::Sha256);
+    let server =
+        cryptopals::srp::Srp6aServer::start("127.0.0.1:0".to_string(), srp.clone(), users).unwrap();
+    let connstring = server.connstring();
+
+    println!("=== SRP-6a normal auth");
+    let authenticated = cryptopals::srp::Srp6aClient::auth(
+        connstring.clone(),
+        &srp,
+        username.clone(),
+        password,
+        &None,
+    );
+    println!("authed as \"{}\": {}", &username, authenticated);
+
+    let modulus = &srp.group.N;
+    for (name, fake_a) in [
+        ("0", 0.to_biguint().unwrap()),
+        ("N", modulus.clone()),
+        ("N*2", modulus * 2_u32),
+    ]
+    .iter()
+    {
+        println!("=== SRP-6a attack auth (A={})", name);
+        let authenticated = cryptopals::srp::Srp6aClient::auth(
+            connstring.clone(),
+            &srp,
+            username.clone(),
+            "wrongpassword".to_string(),
+            &Some(fake_a.clone()),
+        );
+        println!("authed as \"{}\" : {}", username, authenticated);
+    }
+
+    server.shutdown();
 }
@@ -1,8 +1,9 @@
 extern crate cryptopals;
 
 use cryptopals::rsa::RSA;
+use cryptopals::rsa_broadcast::broadcast_attack;
 
-use num_bigint::{BigUint, RandBigInt, ToBigUint};
+use num_bigint::BigUint;
 
 pub fn main() {
     /*
@@ -23,19 +24,12 @@ pub fn main() {
 
     let rsas = vec![RSA::new(1024), RSA::new(1024), RSA::new(1024)];
 
-    let cts = rsas.iter().map(|rsa| rsa.encrypt(&pt)).collect::<Vec<_>>();
+    let cts = rsas
+        .iter()
+        .map(|rsa| (rsa.encrypt(&pt), rsa.pubkey.clone()))
+        .collect::<Vec<_>>();
 
-    let sum: BigUint = vec![(0, 1, 2), (1, 0, 2), (2, 0, 1)]
-        .into_iter()
-        .map(|(nth, a_n, b_n)| {
-            let m = &(&rsas[a_n].n * &rsas[b_n].n);
-            &cts[nth] * m * RSA::mod_inv(m, &rsas[nth].n).unwrap()
-        })
-        .sum();
-
-    let n123: BigUint = rsas.iter().map(|x| &x.n).product();
-
-    let result = (sum % n123).cbrt();
+    let result = broadcast_attack(&cts).expect("broadcast attack failed");
 
     assert_eq!(result, pt);
     println!("success!");
@@ -1,8 +1,9 @@
 extern crate cryptopals;
 
 use cryptopals::rsa::RSA;
+use cryptopals::rsa_oracle::unpadded_recovery;
 
-use num_bigint::{BigUint, RandBigInt, ToBigUint};
+use num_bigint::BigUint;
 
 pub fn main() {
     /*
@@ -22,15 +23,15 @@ pub fn main() {
     attack
     */
 
-    // doesn't have to be prime, but it's convenient to call
-    let random_nr = &RSA::gen_big_prime(1024) % &rsa.n;
-
-    // a different representation of the CT
-    let ct_prime = &(random_nr.modpow(&rsa.e, &rsa.n)) * ct % &rsa.n;
-    assert_ne!(ct, &ct_prime);
-
-    let pt_prime = rsa.decrypt(&ct_prime) / random_nr % rsa.n;
-    assert_eq!(pt, pt_prime);
+    // the server refuses to decrypt the exact ciphertext it has seen before
+    let pt_prime = unpadded_recovery(&rsa.pubkey, ct, |c| {
+        if c == ct {
+            None
+        } else {
+            Some(rsa.decrypt(c))
+        }
+    });
+    assert_eq!(Some(pt), pt_prime);
 
     println!("success!");
 }
@@ -5,6 +5,7 @@ use cryptopals::sha1::Sha1;
 use std::io::Read;
 
 use num_bigint::{BigInt, BigUint, RandBigInt, ToBigInt, ToBigUint};
+use num_integer::Integer;
 
 pub fn main() {
     /*
@@ -24,12 +25,13 @@ pub fn main() {
     attack
     */
 
-    let msg_hash = BigInt::from_signed_bytes_be(&Sha1::digest_now(msg));
+    let msg_hash = DSA::hash(&msg_bn);
     let r = sig.0;
     let s = sig.1;
     for k_candidate in 1..0xffff_u32 {
         let k_candidate = k_candidate.to_bigint().unwrap();
-        let x = ((&s * &k_candidate) - &msg_hash) * DSA::mod_inv(&r, &dsa.q).unwrap() % &dsa.q;
+        let x = (((&s * &k_candidate) - &msg_hash) * DSA::mod_inv(&r, &dsa.q).unwrap())
+            .mod_floor(&dsa.q);
         println!("k: {}", k_candidate);
         if dsa.pubkey == dsa.g.modpow(&x, &dsa.p) {
             println!("found k [{}] ! x => {:x}", &k_candidate, &x);
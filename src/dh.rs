@@ -1,20 +1,225 @@
 use num_bigint::{BigUint, RandBigInt};
+use num_traits::{One, Zero};
+use rand::Rng;
+
+pub mod groups;
+
+/*
+a prime order subgroup: g generates the q elements of Z_p* with g^q = 1
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct DHGroup {
+    pub p: BigUint,
+    pub q: BigUint,
+    pub g: BigUint,
+}
+
+const PKCS3_LABEL: &str = "DH PARAMETERS";
+const X942_LABEL: &str = "X9.42 DH PARAMETERS";
+
+/*
+DER and PEM in the two layouts OpenSSL reads and writes:
+  PKCS #3  DHParameter ::= SEQUENCE { p, g, privateValueLength OPTIONAL }
+  X9.42    DomainParameters ::= SEQUENCE { p, g, q, j OPTIONAL, validationParms OPTIONAL }
+PKCS #3 has no q, importing it assumes a safe prime and checks that g^q = 1
+*/
+impl DHGroup {
+    pub fn to_der(&self) -> Vec<u8> {
+        der_sequence(&[
+            der_integer(&self.p),
+            der_integer(&self.g),
+            der_integer(&self.q),
+        ])
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<DHGroup, ()> {
+        let ints = read_der_integers(der)?;
+        if ints.len() < 3 {
+            return Err(());
+        }
+        let group = DHGroup {
+            p: ints[0].clone(),
+            g: ints[1].clone(),
+            q: ints[2].clone(),
+        };
+        group.check_subgroup()?;
+        Ok(group)
+    }
+
+    pub fn to_pkcs3_der(&self) -> Vec<u8> {
+        der_sequence(&[der_integer(&self.p), der_integer(&self.g)])
+    }
+
+    pub fn from_pkcs3_der(der: &[u8]) -> Result<DHGroup, ()> {
+        let ints = read_der_integers(der)?;
+        if ints.len() < 2 || ints.len() > 3 || ints[0].is_zero() {
+            return Err(());
+        }
+        let group = DHGroup {
+            q: (&ints[0] - 1_u32) >> 1,
+            p: ints[0].clone(),
+            g: ints[1].clone(),
+        };
+        group.check_subgroup()?;
+        Ok(group)
+    }
+
+    pub fn to_pem(&self) -> String {
+        pem_encode(X942_LABEL, &self.to_der())
+    }
+
+    pub fn to_pkcs3_pem(&self) -> String {
+        pem_encode(PKCS3_LABEL, &self.to_pkcs3_der())
+    }
+
+    /*
+    takes either layout, the label says which one it is
+    */
+    pub fn from_pem(pem: &str) -> Result<DHGroup, ()> {
+        if let Some(der) = pem_decode(X942_LABEL, pem) {
+            return DHGroup::from_der(&der);
+        }
+        match pem_decode(PKCS3_LABEL, pem) {
+            Some(der) => DHGroup::from_pkcs3_der(&der),
+            None => Err(()),
+        }
+    }
+
+    fn check_subgroup(&self) -> Result<(), ()> {
+        let big_1 = BigUint::one();
+        if self.p <= big_1 || self.g <= big_1 || self.g >= &self.p - &big_1 {
+            return Err(());
+        }
+        if !self.g.modpow(&self.q, &self.p).is_one() {
+            return Err(());
+        }
+        Ok(())
+    }
+}
+
+fn der_length(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        return vec![len as u8];
+    }
+    let bytes = len
+        .to_be_bytes()
+        .iter()
+        .skip_while(|b| **b == 0)
+        .cloned()
+        .collect::<Vec<_>>();
+    [vec![0x80 | bytes.len() as u8], bytes].concat()
+}
+
+fn der_integer(x: &BigUint) -> Vec<u8> {
+    let mut content = x.to_bytes_be();
+    // keep it positive
+    if content[0] & 0x80 != 0 {
+        content.insert(0, 0);
+    }
+    [vec![0x02], der_length(content.len()), content].concat()
+}
+
+fn der_sequence(items: &[Vec<u8>]) -> Vec<u8> {
+    let content = items.concat();
+    [vec![0x30], der_length(content.len()), content].concat()
+}
+
+/*
+(tag, content, rest) of the first element, definite lengths only
+*/
+fn read_der(der: &[u8]) -> Result<(u8, &[u8], &[u8]), ()> {
+    if der.len() < 2 {
+        return Err(());
+    }
+    let tag = der[0];
+    let (len, header) = if der[1] < 0x80 {
+        (der[1] as usize, 2)
+    } else {
+        let n = (der[1] & 0x7f) as usize;
+        if n == 0 || n > 4 || der.len() < 2 + n {
+            return Err(());
+        }
+        let len = der[2..2 + n]
+            .iter()
+            .fold(0_usize, |acc, b| (acc << 8) | *b as usize);
+        (len, 2 + n)
+    };
+    if der.len() < header + len {
+        return Err(());
+    }
+    Ok((tag, &der[header..header + len], &der[header + len..]))
+}
+
+/*
+the leading INTEGERs of a SEQUENCE, stops at the first element that is not one
+*/
+fn read_der_integers(der: &[u8]) -> Result<Vec<BigUint>, ()> {
+    let (tag, mut content, rest) = read_der(der)?;
+    if tag != 0x30 || !rest.is_empty() {
+        return Err(());
+    }
+    let mut ints = vec![];
+    while !content.is_empty() {
+        let (tag, value, rest) = read_der(content)?;
+        if tag != 0x02 {
+            break;
+        }
+        if value.is_empty() || value[0] & 0x80 != 0 {
+            return Err(());
+        }
+        ints.push(BigUint::from_bytes_be(value));
+        content = rest;
+    }
+    Ok(ints)
+}
+
+fn pem_encode(label: &str, der: &[u8]) -> String {
+    let body = crate::bytes_to_base64(der)
+        .as_bytes()
+        .chunks(64)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "-----BEGIN {}-----\n{}\n-----END {}-----\n",
+        label, body, label
+    )
+}
+
+fn pem_decode(label: &str, pem: &str) -> Option<Vec<u8>> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let start = pem.find(&begin)? + begin.len();
+    let stop = start + pem[start..].find(&end)?;
+    Some(crate::base64_to_bytes(&pem[start..stop]))
+}
 
 pub struct DiffieHellmanState {
     pub secret: BigUint,
     p: BigUint,
     g: BigUint,
+    // only known for groups built with from_group
+    q: Option<BigUint>,
     pub pubkey: BigUint,
 }
 
 impl DiffieHellmanState {
     pub fn new(g: &BigUint, p: &BigUint) -> DiffieHellmanState {
-        let secret = rand::thread_rng().gen_biguint_below(&p);
+        DiffieHellmanState::new_with_rng(g, p, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        g: &BigUint,
+        p: &BigUint,
+        rng: &mut R,
+    ) -> DiffieHellmanState {
+        let secret = rng.gen_biguint_below(&p);
         let pubkey = DiffieHellmanState::gen_pubkey(&p, &g, &secret);
         DiffieHellmanState {
             secret,
             p: p.clone(),
             g: g.clone(),
+            q: None,
             pubkey,
         }
     }
@@ -25,14 +230,68 @@ impl DiffieHellmanState {
             secret: secret.clone(),
             p: p.clone(),
             g: g.clone(),
+            q: None,
+            pubkey,
+        }
+    }
+
+    /*
+    the secret is drawn below q, anything larger is wasted exponent
+    */
+    pub fn from_group<R: Rng + ?Sized>(group: &DHGroup, rng: &mut R) -> DiffieHellmanState {
+        let secret = rng.gen_biguint_range(&BigUint::one(), &group.q);
+        DiffieHellmanState::from_group_static(group, &secret)
+    }
+
+    pub fn from_group_static(group: &DHGroup, secret: &BigUint) -> DiffieHellmanState {
+        let pubkey = DiffieHellmanState::gen_pubkey(&group.p, &group.g, secret);
+        DiffieHellmanState {
+            secret: secret.clone(),
+            p: group.p.clone(),
+            g: group.g.clone(),
+            q: Some(group.q.clone()),
             pubkey,
         }
     }
 
-    pub fn gen_shared_key(self, other_pubkey: &BigUint) -> BigUint {
+    /*
+    1 < y < p - 1 rules out the degenerate keys from cryptopals 34/35, and with q
+    known y^q = 1 keeps the peer out of the small subgroups of cryptopals 57
+    */
+    pub fn check_pubkey(&self, other_pubkey: &BigUint) -> bool {
+        let p_minus_1 = &self.p - 1_u32;
+        if other_pubkey <= &BigUint::one() || other_pubkey >= &p_minus_1 {
+            return false;
+        }
+        match &self.q {
+            Some(q) => other_pubkey.modpow(q, &self.p).is_one(),
+            None => true,
+        }
+    }
+
+    pub fn modulus(&self) -> &BigUint {
+        &self.p
+    }
+
+    pub fn generator(&self) -> &BigUint {
+        &self.g
+    }
+
+    pub fn order(&self) -> Option<&BigUint> {
+        self.q.as_ref()
+    }
+
+    pub fn gen_shared_key(&self, other_pubkey: &BigUint) -> BigUint {
         other_pubkey.modpow(&self.secret, &self.p)
     }
 
+    pub fn gen_shared_key_checked(&self, other_pubkey: &BigUint) -> Result<BigUint, ()> {
+        if !self.check_pubkey(other_pubkey) {
+            return Err(());
+        }
+        Ok(self.gen_shared_key(other_pubkey))
+    }
+
     fn gen_pubkey(p: &BigUint, g: &BigUint, secret: &BigUint) -> BigUint {
         g.modpow(secret, p)
     }
@@ -0,0 +1,251 @@
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::{One, Zero};
+use rand::Rng;
+
+use crate::dh::DHGroup;
+use crate::dlog::dlog_with_residue;
+use crate::numtheory::crt;
+use crate::primes::primes_below;
+
+/*
+Small subgroup confinement against a DH peer with a static secret that does not
+validate our public key (cryptopals 57/58). The victim is an oracle that takes
+our public key and answers with a tag keyed by the shared secret, `mac` computes
+the same tag for a candidate shared secret.
+*/
+
+/*
+an element of order r, r must divide p - 1
+*/
+pub fn element_of_order<R: Rng + ?Sized>(p: &BigUint, r: u64, rng: &mut R) -> BigUint {
+    let exponent = (p - 1_u32) / r;
+    loop {
+        let h = rng
+            .gen_biguint_range(&BigUint::from(2_u32), p)
+            .modpow(&exponent, p);
+        if !h.is_one() {
+            return h;
+        }
+    }
+}
+
+/*
+h^x only takes r values, try them all against the tag
+*/
+fn brute_force_residue<M>(p: &BigUint, h: &BigUint, r: u64, tag: &[u8], mac: &M) -> Option<u64>
+where
+    M: Fn(&BigUint) -> Vec<u8>,
+{
+    let mut acc = BigUint::one();
+    for x in 0..r {
+        if mac(&acc) == tag {
+            return Some(x);
+        }
+        acc = acc * h % p;
+    }
+    None
+}
+
+/*
+x mod r for every small prime r of (p - 1) / q, glued together with CRT
+(Pohlig-Hellman without the discrete logs, the oracle hands us each residue).
+Stops once the modulus passes q. Returns (residue, modulus)
+*/
+pub fn small_subgroup_residues<O, M, R>(
+    group: &DHGroup,
+    max_factor: u64,
+    mut oracle: O,
+    mac: M,
+    rng: &mut R,
+) -> (BigUint, BigUint)
+where
+    O: FnMut(&BigUint) -> Vec<u8>,
+    M: Fn(&BigUint) -> Vec<u8>,
+    R: Rng + ?Sized,
+{
+    let cofactor = (&group.p - 1_u32) / &group.q;
+    let mut residues = vec![];
+    let mut moduli = vec![];
+    let mut modulus = BigUint::one();
+    for r in primes_below(max_factor) {
+        if modulus > group.q {
+            break;
+        }
+        if !(&cofactor % r).is_zero() || (&group.q % r).is_zero() {
+            continue;
+        }
+        let h = element_of_order(&group.p, r, rng);
+        let tag = oracle(&h);
+        if let Some(residue) = brute_force_residue(&group.p, &h, r, &tag, &mac) {
+            residues.push(BigUint::from(residue));
+            moduli.push(BigUint::from(r));
+            modulus *= r;
+        }
+    }
+    (
+        crt(&residues, &moduli).unwrap_or_else(BigUint::zero),
+        modulus,
+    )
+}
+
+/*
+cryptopals 57, enough small factors to cover q
+*/
+pub fn small_subgroup_attack<O, M, R>(
+    group: &DHGroup,
+    max_factor: u64,
+    oracle: O,
+    mac: M,
+    rng: &mut R,
+) -> Option<BigUint>
+where
+    O: FnMut(&BigUint) -> Vec<u8>,
+    M: Fn(&BigUint) -> Vec<u8>,
+    R: Rng + ?Sized,
+{
+    let (residue, modulus) = small_subgroup_residues(group, max_factor, oracle, mac, rng);
+    if modulus > group.q {
+        Some(residue % &group.q)
+    } else {
+        None
+    }
+}
+
+/*
+cryptopals 58, the small factors only cover part of q. With x = n mod r known,
+the rest of x is found by a kangaroo walk over the x = n mod r that are left
+*/
+pub fn small_subgroup_kangaroo_attack<O, M, R>(
+    group: &DHGroup,
+    pubkey: &BigUint,
+    max_factor: u64,
+    oracle: O,
+    mac: M,
+    rng: &mut R,
+) -> Option<BigUint>
+where
+    O: FnMut(&BigUint) -> Vec<u8>,
+    M: Fn(&BigUint) -> Vec<u8>,
+    R: Rng + ?Sized,
+{
+    let (residue, modulus) = small_subgroup_residues(group, max_factor, oracle, mac, rng);
+    if modulus > group.q {
+        return Some(residue % &group.q);
+    }
+    dlog_with_residue(
+        &group.g,
+        pubkey,
+        &group.p,
+        &residue,
+        &modulus,
+        &(&group.q - 1_u32),
+    )
+}
+
+#[cfg(test)]
+fn test_mac(key: &BigUint) -> Vec<u8> {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(
+        &[
+            &key.to_bytes_be()[..],
+            b"crazy flamboyant for the rap enjoyment",
+        ]
+        .concat(),
+    )
+    .to_vec()
+}
+
+#[test]
+fn small_subgroup_attack_works() {
+    use crate::dh::DiffieHellmanState;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let group = DHGroup {
+        p: BigUint::parse_bytes(
+            b"7199773997391911030609999317773941274322764333428698921736339643928346453700085358\
+              802973900485592910475480089726140708102474957429903531369589969318716771",
+            10,
+        )
+        .unwrap(),
+        q: BigUint::parse_bytes(b"236234353446506858198510045061214171961", 10).unwrap(),
+        g: BigUint::parse_bytes(
+            b"4565356397095740655436854503483826832136106141639563487732438195343690437606117828\
+              318042418238184896212352329118608100083187535033402010599512641674644143",
+            10,
+        )
+        .unwrap(),
+    };
+    let mut rng = StdRng::seed_from_u64(57);
+    let bob = DiffieHellmanState::from_group(&group, &mut rng);
+
+    let recovered = small_subgroup_attack(
+        &group,
+        1 << 16,
+        |h| test_mac(&bob.gen_shared_key(h)),
+        test_mac,
+        &mut rng,
+    );
+    assert_eq!(recovered, Some(bob.secret.clone()));
+
+    // a validating peer gives nothing away
+    let h = element_of_order(&group.p, 5, &mut rng);
+    assert!(!bob.check_pubkey(&h));
+    assert!(bob.gen_shared_key_checked(&h).is_err());
+    assert!(bob.check_pubkey(&DiffieHellmanState::from_group(&group, &mut rng).pubkey));
+    assert!(!bob.check_pubkey(&BigUint::one()));
+    assert!(!bob.check_pubkey(&(&group.p - 1_u32)));
+}
+
+#[test]
+fn kangaroo_attack_works() {
+    use crate::dh::DiffieHellmanState;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let group = DHGroup {
+        p: BigUint::parse_bytes(
+            b"1147037487492527565811666350723216140208665025845389627453499167689899926264158151\
+              9101074740642369848233294239851519212341844337347119899874391456329785623",
+            10,
+        )
+        .unwrap(),
+        q: BigUint::parse_bytes(b"335062023296420808191071248367701059461", 10).unwrap(),
+        g: BigUint::parse_bytes(
+            b"6229523353339612969781592660847410858898813587384599399782901799360636355667402585\
+              55167783009058567397963466103140082647486611657350811560630587013183357",
+            10,
+        )
+        .unwrap(),
+    };
+
+    // the warm up from the challenge, x in [0, 2^20]
+    let y = BigUint::parse_bytes(
+        b"7760073848032689505395005705677365876654629189298052775754597607446617558600394076\
+          764814236081991643094239886772481052254010323780165093955236429914607119",
+        10,
+    )
+    .unwrap();
+    let x = crate::dlog::kangaroo(
+        &group.g,
+        &y,
+        &group.p,
+        &BigUint::zero(),
+        &BigUint::from(1_u32 << 20),
+    )
+    .unwrap();
+    assert_eq!(group.g.modpow(&x, &group.p), y);
+
+    let mut rng = StdRng::seed_from_u64(58);
+    let bob = DiffieHellmanState::from_group(&group, &mut rng);
+    let recovered = small_subgroup_kangaroo_attack(
+        &group,
+        &bob.pubkey,
+        1 << 16,
+        |h| test_mac(&bob.gen_shared_key(h)),
+        test_mac,
+        &mut rng,
+    );
+    assert_eq!(recovered, Some(bob.secret.clone()));
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+
+use num_bigint::{BigInt, BigUint, Sign};
+use num_integer::Integer;
+use num_traits::{One, ToPrimitive, Zero};
+
+use crate::dh::DiffieHellmanState;
+use crate::dsa::DSA;
+use crate::numtheory::{mod_inv, mod_inv_int};
+
+/*
+Discrete logs in the multiplicative group mod p: x with g^x = h. All solvers
+take the interval or group order they search, nothing here tries the whole of
+Z_p* blindly.
+*/
+
+/*
+a public key y = g^x mod p together with its g and p. group_order is the order
+of g, None for keys over plain Z_p* where nobody told us what it is
+*/
+pub trait DlogTarget {
+    fn generator(&self) -> BigUint;
+    fn modulus(&self) -> BigUint;
+    fn group_order(&self) -> Option<BigUint>;
+    fn public_key(&self) -> BigUint;
+}
+
+impl DlogTarget for DiffieHellmanState {
+    fn generator(&self) -> BigUint {
+        DiffieHellmanState::generator(self).clone()
+    }
+
+    fn modulus(&self) -> BigUint {
+        DiffieHellmanState::modulus(self).clone()
+    }
+
+    fn group_order(&self) -> Option<BigUint> {
+        self.order().cloned()
+    }
+
+    fn public_key(&self) -> BigUint {
+        self.pubkey.clone()
+    }
+}
+
+impl DlogTarget for DSA {
+    fn generator(&self) -> BigUint {
+        self.g.to_biguint().unwrap()
+    }
+
+    fn modulus(&self) -> BigUint {
+        self.p.to_biguint().unwrap()
+    }
+
+    fn group_order(&self) -> Option<BigUint> {
+        self.q.to_biguint()
+    }
+
+    fn public_key(&self) -> BigUint {
+        self.pubkey.to_biguint().unwrap()
+    }
+}
+
+/*
+intervals up to this width go to baby-step giant-step, wider ones to the
+kangaroo which needs no table
+*/
+const BSGS_MAX_WIDTH_BITS: u64 = 36;
+
+/*
+baby-step giant-step on [lower, upper]: a table of g^j for j < m, then walk
+h g^-lower by g^-m until it hits the table. sqrt(width) time and memory
+*/
+pub fn bsgs(
+    g: &BigUint,
+    h: &BigUint,
+    p: &BigUint,
+    lower: &BigUint,
+    upper: &BigUint,
+) -> Option<BigUint> {
+    if upper < lower {
+        return None;
+    }
+    let width = upper - lower + 1_u32;
+    let m = width.sqrt() + 1_u32;
+    let m_small = m.to_u64()?;
+
+    let mut table = HashMap::new();
+    let mut acc = BigUint::one() % p;
+    for j in 0..m_small {
+        table.entry(acc.clone()).or_insert(j);
+        acc = acc * g % p;
+    }
+
+    let giant = mod_inv(&g.modpow(&m, p), p)?;
+    let mut gamma = h * mod_inv(&g.modpow(lower, p), p)? % p;
+    let mut i = BigUint::zero();
+    while &i * &m < width {
+        if let Some(j) = table.get(&gamma) {
+            let x = lower + &i * &m + *j;
+            return if &x <= upper { Some(x) } else { None };
+        }
+        gamma = gamma * &giant % p;
+        i += 1_u32;
+    }
+    None
+}
+
+/*
+Pollard's kangaroo on [lower, upper]. The jumps are 2^(y mod k), a tame kangaroo
+runs from upper and leaves a trap, a wild one starts at h and either lands in it
+or runs past upper + the tame distance. sqrt(width) time, constant memory
+*/
+pub fn kangaroo(
+    g: &BigUint,
+    h: &BigUint,
+    p: &BigUint,
+    lower: &BigUint,
+    upper: &BigUint,
+) -> Option<BigUint> {
+    if upper < lower {
+        return None;
+    }
+    let width = upper - lower;
+    // smallest k that puts the mean jump (2^k - 1) / k at sqrt(width) / 2
+    let target = width.sqrt() >> 1;
+    let mut k = 1_u32;
+    while k < 63 && (BigUint::one() << k) - 1_u32 < &target * k {
+        k += 1;
+    }
+    let jumps = (0..k).map(|i| BigUint::one() << i).collect::<Vec<_>>();
+    let powers = jumps.iter().map(|j| g.modpow(j, p)).collect::<Vec<_>>();
+    let index = |y: &BigUint| (y.to_u32_digits().first().cloned().unwrap_or(0) % k) as usize;
+
+    let mean = jumps.iter().fold(BigUint::zero(), |acc, j| acc + j) / k;
+    let steps = mean * 4_u32;
+
+    let mut x_tame = BigUint::zero();
+    let mut y_tame = g.modpow(upper, p);
+    let mut i = BigUint::zero();
+    while i < steps {
+        let j = index(&y_tame);
+        x_tame += &jumps[j];
+        y_tame = y_tame * &powers[j] % p;
+        i += 1_u32;
+    }
+
+    let limit = &width + &x_tame;
+    let mut x_wild = BigUint::zero();
+    let mut y_wild = h % p;
+    while x_wild <= limit {
+        if y_wild == y_tame {
+            return Some(upper + x_tame - x_wild);
+        }
+        let j = index(&y_wild);
+        x_wild += &jumps[j];
+        y_wild = y_wild * &powers[j] % p;
+    }
+    None
+}
+
+/*
+one step of the rho walk, the group is split in three by y mod 3
+*/
+fn rho_step(
+    y: &mut BigUint,
+    a: &mut BigInt,
+    b: &mut BigInt,
+    g: &BigUint,
+    h: &BigUint,
+    p: &BigUint,
+    n: &BigInt,
+) {
+    match (&*y % 3_u32).to_u32().unwrap_or(0) {
+        0 => {
+            *y = &*y * h % p;
+            *b = (&*b + 1_u32).mod_floor(n);
+        }
+        1 => {
+            *y = &*y * &*y % p;
+            *a = (&*a * 2_u32).mod_floor(n);
+            *b = (&*b * 2_u32).mod_floor(n);
+        }
+        _ => {
+            *y = &*y * g % p;
+            *a = (&*a + 1_u32).mod_floor(n);
+        }
+    }
+}
+
+/*
+Pollard's rho for g of order n, Floyd cycle finding on g^a h^b. A collision
+gives (b1 - b2) x = a2 - a1 mod n, n should be prime or the gcd small
+*/
+pub fn pollard_rho(g: &BigUint, h: &BigUint, p: &BigUint, n: &BigUint) -> Option<BigUint> {
+    let h = h % p;
+    if h.is_one() {
+        return Some(BigUint::zero());
+    }
+    let n_int = BigInt::from_biguint(Sign::Plus, n.clone());
+
+    // restart from g^i h when a walk collides uselessly
+    for start in 1_u32..16 {
+        let mut y1 = g.modpow(&BigUint::from(start), p) * &h % p;
+        let mut a1 = BigInt::from(start);
+        let mut b1 = BigInt::one();
+        let (mut y2, mut a2, mut b2) = (y1.clone(), a1.clone(), b1.clone());
+        loop {
+            rho_step(&mut y1, &mut a1, &mut b1, g, &h, p, &n_int);
+            rho_step(&mut y2, &mut a2, &mut b2, g, &h, p, &n_int);
+            rho_step(&mut y2, &mut a2, &mut b2, g, &h, p, &n_int);
+            if y1 == y2 {
+                break;
+            }
+        }
+
+        let db = (&b1 - &b2).mod_floor(&n_int);
+        let da = (&a2 - &a1).mod_floor(&n_int);
+        if db.is_zero() {
+            continue;
+        }
+        let d = db.gcd(&n_int);
+        if !(&da % &d).is_zero() || d > BigInt::from(1 << 16) {
+            continue;
+        }
+        let n_d = &n_int / &d;
+        let x0 = (&da / &d * mod_inv_int(&(&db / &d), &n_d)?).mod_floor(&n_d);
+        let mut i = BigInt::zero();
+        while i < d {
+            let x = (&x0 + &i * &n_d).to_biguint()?;
+            if g.modpow(&x, p) == h {
+                return Some(x);
+            }
+            i += 1;
+        }
+    }
+    None
+}
+
+/*
+picks the solver by interval width
+*/
+pub fn dlog_in_range(
+    g: &BigUint,
+    h: &BigUint,
+    p: &BigUint,
+    lower: &BigUint,
+    upper: &BigUint,
+) -> Option<BigUint> {
+    if upper < lower {
+        return None;
+    }
+    if (upper - lower).bits() <= BSGS_MAX_WIDTH_BITS {
+        bsgs(g, h, p, lower, upper)
+    } else {
+        kangaroo(g, h, p, lower, upper)
+    }
+}
+
+/*
+x = residue mod modulus and x <= upper. Writing x = residue + m modulus turns
+it into h g^-residue = (g^modulus)^m with m in [0, (upper - residue) / modulus]
+*/
+pub fn dlog_with_residue(
+    g: &BigUint,
+    h: &BigUint,
+    p: &BigUint,
+    residue: &BigUint,
+    modulus: &BigUint,
+    upper: &BigUint,
+) -> Option<BigUint> {
+    if upper < residue {
+        return None;
+    }
+    let shifted = h * mod_inv(&g.modpow(residue, p), p)? % p;
+    let g_m = g.modpow(modulus, p);
+    let m_upper = (upper - residue) / modulus;
+    let m = dlog_in_range(&g_m, &shifted, p, &BigUint::zero(), &m_upper)?;
+    Some(residue + m * modulus)
+}
+
+/*
+the private key when it is known to sit in [lower, upper]
+*/
+pub fn recover_key_in_range<T: DlogTarget>(
+    target: &T,
+    lower: &BigUint,
+    upper: &BigUint,
+) -> Option<BigUint> {
+    let (g, p) = (target.generator(), target.modulus());
+    dlog_in_range(&g, &target.public_key(), &p, lower, upper)
+}
+
+/*
+the private key when it is known mod `modulus` (leaked low bits, or residues from
+a small subgroup attack). Needs the group order to bound the search
+*/
+pub fn recover_key_with_residue<T: DlogTarget>(
+    target: &T,
+    residue: &BigUint,
+    modulus: &BigUint,
+) -> Option<BigUint> {
+    let (g, p) = (target.generator(), target.modulus());
+    let upper = target.group_order()? - 1_u32;
+    dlog_with_residue(&g, &target.public_key(), &p, residue, modulus, &upper)
+}
+
+/*
+the whole key by rho, only feasible for small group orders
+*/
+pub fn recover_key<T: DlogTarget>(target: &T) -> Option<BigUint> {
+    let (g, p) = (target.generator(), target.modulus());
+    pollard_rho(&g, &target.public_key(), &p, &target.group_order()?)
+}
+
+#[test]
+fn dlog_solvers_work() {
+    let big = |x: u64| BigUint::from(x);
+
+    // p = 2q + 1, 4 generates the subgroup of order q
+    let p = big(8_589_935_363);
+    let q = big(4_294_967_681);
+    let g = big(4);
+    let x = big(3_141_592_653);
+    let h = g.modpow(&x, &p);
+
+    assert_eq!(pollard_rho(&g, &h, &p, &q), Some(x.clone()));
+    assert_eq!(pollard_rho(&g, &BigUint::one(), &p, &q), Some(big(0)));
+
+    let lower = big(3_141_000_000);
+    let upper = big(3_142_000_000);
+    assert_eq!(bsgs(&g, &h, &p, &lower, &upper), Some(x.clone()));
+    assert_eq!(kangaroo(&g, &h, &p, &lower, &upper), Some(x.clone()));
+    assert_eq!(bsgs(&g, &h, &p, &big(0), &big(1_000_000)), None);
+    assert_eq!(bsgs(&g, &h, &p, &upper, &lower), None);
+
+    assert_eq!(
+        dlog_with_residue(&g, &h, &p, &(&x % 1000_u32), &big(1000), &(&q - 1_u32)),
+        Some(x)
+    );
+}
+
+#[test]
+fn dlog_recovers_partial_keys() {
+    use crate::dh::DHGroup;
+    use crate::dsa::DSAParams;
+
+    // DSA key with everything but the low 34 bits known
+    let params = DSAParams::cryptopals();
+    let x = BigInt::parse_bytes(b"9d0d0f2c8a7b3e1d4c5b6a79880716253443f2e1", 16).unwrap();
+    let dsa = DSA::from_privkey(&params, &x);
+    let unknown = BigUint::one() << 34;
+    let lower = (x.to_biguint().unwrap() >> 34) << 34;
+    let upper = &lower + &unknown - 1_u32;
+    assert_eq!(recover_key_in_range(&dsa, &lower, &upper), x.to_biguint());
+
+    // wider than BSGS allows, the kangaroo takes over
+    let lower = (x.to_biguint().unwrap() >> 38) << 38;
+    let upper = &lower + (BigUint::one() << 38_u32);
+    assert_eq!(recover_key_in_range(&dsa, &lower, &upper), x.to_biguint());
+
+    // DH key with its low bits leaked, the rest of a 2^33 order group by search
+    let group = DHGroup {
+        p: BigUint::from(8_589_935_363_u64),
+        q: BigUint::from(4_294_967_681_u64),
+        g: BigUint::from(4_u32),
+    };
+    let dh = DiffieHellmanState::from_group_static(&group, &BigUint::from(2_718_281_828_u64));
+    assert_eq!(
+        recover_key_with_residue(&dh, &BigUint::from(1828_u32), &BigUint::from(10_000_u32)),
+        Some(BigUint::from(2_718_281_828_u64))
+    );
+    assert_eq!(recover_key(&dh), Some(BigUint::from(2_718_281_828_u64)));
+}
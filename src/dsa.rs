@@ -1,7 +1,28 @@
-extern crate openssl;
-
+use crate::numtheory;
+use crate::primes::is_probable_prime;
 use crate::sha1::Sha1;
-use num_bigint::{BigInt, BigUint, RandBigInt, ToBigInt, ToBigUint};
+use num_bigint::{BigInt, BigUint, RandBigInt, Sign, ToBigInt, ToBigUint};
+use num_traits::{One, Zero};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+/*
+how sign picks k. Weak16Bit is what cryptopals 43 breaks, Rfc6979 derives k
+from the private key and the message hash so no rng is involved at all
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NonceStrategy {
+    Weak16Bit,
+    UniformRandom,
+    Rfc6979,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DSAParams {
+    pub p: BigInt,
+    pub q: BigInt,
+    pub g: BigInt,
+}
 
 pub struct DSA {
     pub p: BigInt,
@@ -9,6 +30,9 @@ pub struct DSA {
     pub g: BigInt,
     pub pubkey: BigInt,
     pub privkey: BigInt,
+    pub nonce: NonceStrategy,
+    // reject signatures outside 0 < r < q, 0 < s < q
+    pub strict: bool,
 }
 
 const DEFAULT_P: &[u8] = b"800000000000000089e1855218a0e7dac38136ffafa72eda7\
@@ -26,62 +50,188 @@ const DEFAULT_G: &[u8] = b"5958c9d3898b224b12672c0b98e06c60df923cb8bc999d119\
      878480e99041be601a62166ca6894bdd41a7054ec89f756ba\
      9fc95302291";
 
-impl DSA {
-    pub fn new() -> DSA {
-        let p = BigInt::parse_bytes(DEFAULT_P, 16).unwrap();
-        let q = BigInt::parse_bytes(DEFAULT_Q, 16).unwrap();
-        let g = BigInt::parse_bytes(DEFAULT_G, 16).unwrap();
+impl DSAParams {
+    pub fn cryptopals() -> DSAParams {
+        DSAParams {
+            p: BigInt::parse_bytes(DEFAULT_P, 16).unwrap(),
+            q: BigInt::parse_bytes(DEFAULT_Q, 16).unwrap(),
+            g: BigInt::parse_bytes(DEFAULT_G, 16).unwrap(),
+        }
+    }
 
-        let privkey = rand::thread_rng()
-            .gen_biguint_below(&q.to_biguint().unwrap())
-            .to_bigint()
-            .unwrap();
-        let pubkey = g.modpow(&privkey, &p);
+    /*
+    FIPS 186-4 A.1.1.2 probable primes with SHA-256 as the hash, then an
+    unverifiable generator per A.2.1. Only the (L, N) pairs the standard allows
+    */
+    pub fn generate<R: Rng + ?Sized>(l: usize, n: usize, rng: &mut R) -> Result<DSAParams, ()> {
+        if ![(1024, 160), (2048, 224), (2048, 256), (3072, 256)].contains(&(l, n)) {
+            return Err(());
+        }
+        let outlen = 256;
+        let seedlen = n;
+        let blocks = l.div_ceil(outlen) - 1;
+        let b = l - 1 - blocks * outlen;
+        let big_1 = BigUint::one();
+        let seed_mod = &big_1 << seedlen;
+        let hash = |x: &BigUint| {
+            let mut bytes = x.to_bytes_be();
+            // hash the full seedlen bits, leading zeros included
+            while bytes.len() < seedlen / 8 {
+                bytes.insert(0, 0);
+            }
+            BigUint::from_bytes_be(&Sha256::digest(&bytes))
+        };
 
-        DSA {
-            p,
-            q,
-            g,
-            pubkey,
-            privkey,
+        loop {
+            let seed = rng.gen_biguint(seedlen as u64);
+            let u = hash(&seed) % (&big_1 << (n - 1));
+            let q = (&big_1 << (n - 1)) + &u + &big_1 - (&u % 2_u32);
+            if !is_probable_prime(&q) {
+                continue;
+            }
+
+            let two_q = &q << 1;
+            let mut offset = BigUint::one();
+            for _counter in 0..4 * l {
+                let mut w = BigUint::zero();
+                for j in 0..=blocks {
+                    let mut v = hash(&((&seed + &offset + j) % &seed_mod));
+                    if j == blocks {
+                        v %= &big_1 << b;
+                    }
+                    w += v << (j * outlen);
+                }
+                let x = w + (&big_1 << (l - 1));
+                let c = &x % &two_q;
+                let p: BigUint = x - (c - &big_1);
+                if p.bits() as usize == l && is_probable_prime(&p) {
+                    let g = DSAParams::find_generator(&p, &q);
+                    return Ok(DSAParams {
+                        p: p.to_bigint().unwrap(),
+                        q: q.to_bigint().unwrap(),
+                        g: g.to_bigint().unwrap(),
+                    });
+                }
+                offset += blocks + 1;
+            }
         }
     }
 
+    fn find_generator(p: &BigUint, q: &BigUint) -> BigUint {
+        let e = (p - 1_u32) / q;
+        let mut h = BigUint::from(2_u32);
+        loop {
+            let g = h.modpow(&e, p);
+            if !g.is_one() {
+                return g;
+            }
+            h += 1_u32;
+        }
+    }
+
+    /*
+    p and q prime, q | p - 1 and g generates the order q subgroup
+    */
+    pub fn validate(&self) -> bool {
+        let (p, q) = match (self.p.to_biguint(), self.q.to_biguint()) {
+            (Some(p), Some(q)) => (p, q),
+            _ => return false,
+        };
+        let g = match self.g.to_biguint() {
+            Some(g) => g,
+            None => return false,
+        };
+        is_probable_prime(&p)
+            && is_probable_prime(&q)
+            && ((&p - 1_u32) % &q).is_zero()
+            && g > BigUint::one()
+            && g < p
+            && g.modpow(&q, &p).is_one()
+    }
+}
+
+impl DSA {
+    /*
+    cryptopals parameters, weak nonces and no range checks, the challenges
+    depend on all three
+    */
+    pub fn new() -> DSA {
+        DSA::from_params(&DSAParams::cryptopals(), &mut rand::thread_rng())
+    }
+
     pub fn new_with_g(g: BigInt) -> DSA {
-        let p = BigInt::parse_bytes(DEFAULT_P, 16).unwrap();
-        let q = BigInt::parse_bytes(DEFAULT_Q, 16).unwrap();
+        DSA::new_with_g_with_rng(g, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_g_with_rng<R: Rng + ?Sized>(g: BigInt, rng: &mut R) -> DSA {
+        let params = DSAParams {
+            g,
+            ..DSAParams::cryptopals()
+        };
+        DSA::from_params(&params, rng)
+    }
 
-        let privkey = rand::thread_rng()
-            .gen_biguint_below(&q.to_biguint().unwrap())
+    pub fn from_params<R: Rng + ?Sized>(params: &DSAParams, rng: &mut R) -> DSA {
+        let privkey = rng
+            .gen_biguint_below(&params.q.to_biguint().unwrap())
             .to_bigint()
             .unwrap();
-        let pubkey = g.modpow(&privkey, &p);
+        DSA::from_privkey(params, &privkey)
+    }
 
+    pub fn from_privkey(params: &DSAParams, privkey: &BigInt) -> DSA {
+        let pubkey = params.g.modpow(privkey, &params.p);
         DSA {
-            p,
-            q,
-            g,
+            p: params.p.clone(),
+            q: params.q.clone(),
+            g: params.g.clone(),
             pubkey,
-            privkey,
+            privkey: privkey.clone(),
+            nonce: NonceStrategy::Weak16Bit,
+            strict: false,
+        }
+    }
+
+    pub fn params(&self) -> DSAParams {
+        DSAParams {
+            p: self.p.clone(),
+            q: self.q.clone(),
+            g: self.g.clone(),
         }
     }
 
     pub fn sign(&self, m: &BigInt) -> (BigInt, BigInt) {
-        let p = &self.p;
-        let g = &self.g;
-        let q = &self.q;
+        self.sign_with_rng(m, &mut rand::thread_rng())
+    }
 
-        // ! correct
-        // let k = rand::thread_rng().gen_bigint_below(q);
-        // ! weak
-        let k = rand::thread_rng()
-            .gen_biguint_below(&0xffff_u32.to_biguint().unwrap())
-            .to_bigint()
-            .unwrap();
-        let r = self.g.modpow(&k, &p) % q;
-        let s = DSA::mod_inv(&k, q).unwrap() * (DSA::hash(m) + (&self.privkey * &r)) % q;
+    /*
+    the rng picks the nonce, so a predictable one gives the key away
+    */
+    pub fn sign_with_rng<R: Rng + ?Sized>(&self, m: &BigInt, rng: &mut R) -> (BigInt, BigInt) {
+        let q = &self.q;
+        loop {
+            let k = match self.nonce {
+                NonceStrategy::Weak16Bit => rng
+                    .gen_biguint_below(&0xffff_u32.to_biguint().unwrap())
+                    .to_bigint()
+                    .unwrap(),
+                NonceStrategy::UniformRandom => rng.gen_bigint_range(&BigInt::one(), q),
+                NonceStrategy::Rfc6979 => self.rfc6979_nonce(m),
+            };
+            if let Some(sig) = self.sign_with_nonce(m, &k) {
+                return sig;
+            }
+        }
+    }
 
-        (r, s)
+    /*
+    None when k is not invertible mod q
+    */
+    pub fn sign_with_nonce(&self, m: &BigInt, k: &BigInt) -> Option<(BigInt, BigInt)> {
+        let q = &self.q;
+        let r = self.g.modpow(k, &self.p) % q;
+        let s = DSA::mod_inv(k, q).ok()? * (DSA::hash(m) + (&self.privkey * &r)) % q;
+        Some((r, s))
     }
 
     pub fn verify(&self, m: &BigInt, r: &BigInt, s: &BigInt) -> bool {
@@ -89,36 +239,166 @@ impl DSA {
         let g = &self.g;
         let q = &self.q;
 
-        // if not (0 < r < q) && (0 < s < q) => false
-        let w = DSA::mod_inv(s, q).unwrap();
+        let zero = BigInt::zero();
+        if self.strict && !(&zero < r && r < q && &zero < s && s < q) {
+            return false;
+        }
+        let w = match DSA::mod_inv(s, q) {
+            Ok(w) => w,
+            Err(_) => return false,
+        };
         let u1 = (DSA::hash(m) * &w) % q;
         let u2 = (r * w) % q;
-        let v = (g.modpow(&u1, &p) * &self.pubkey.modpow(&u2, &p) % p) % q;
+        let v = (g.modpow(&u1, p) * &self.pubkey.modpow(&u2, p) % p) % q;
         &v == r
     }
 
+    /*
+    the digest is an unsigned number, reading it as two's complement made half
+    of all messages hash to a negative value
+    */
     pub fn hash(m: &BigInt) -> BigInt {
-        BigInt::from_signed_bytes_be(&Sha1::digest_now(&m.to_signed_bytes_be()))
+        BigInt::from_bytes_be(Sign::Plus, &Sha1::digest_now(&m.to_signed_bytes_be()))
     }
 
-    fn egcd(a: &BigInt, b: &BigInt) -> (BigInt, BigInt, BigInt) {
-        if a == &0.to_bigint().unwrap() {
-            return (b.clone(), 0.to_bigint().unwrap(), 1.to_bigint().unwrap());
-        }
-        let (b_div_a, b_mod_a) = (b / a, b % a);
-        let (g, x, y) = DSA::egcd(&b_mod_a, a);
-        (g, y - (b_div_a * &x), x)
+    pub fn mod_inv(a: &BigInt, b: &BigInt) -> Result<BigInt, ()> {
+        numtheory::mod_inv_int(a, b).ok_or(())
     }
 
-    pub fn mod_inv(a: &BigInt, b: &BigInt) -> Result<BigInt, ()> {
-        let (g, x, _) = DSA::egcd(&a, &b);
-        if g != 1.to_bigint().unwrap() {
-            return Err(());
-        }
-        if x < 0.to_bigint().unwrap() {
-            Ok((b + x) % b)
-        } else {
-            Ok(x % b)
+    /*
+    RFC 6979 section 3.2 with HMAC-SHA1, the same hash sign uses
+    */
+    fn rfc6979_nonce(&self, m: &BigInt) -> BigInt {
+        let q = self.q.to_biguint().unwrap();
+        let qlen = q.bits() as usize;
+        let rlen = qlen.div_ceil(8);
+        let bits2int = |bytes: &[u8]| {
+            let v = BigUint::from_bytes_be(bytes);
+            if bytes.len() * 8 > qlen {
+                v >> (bytes.len() * 8 - qlen)
+            } else {
+                v
+            }
+        };
+        let int2octets = |v: &BigUint| {
+            let bytes = v.to_bytes_be();
+            [vec![0; rlen.saturating_sub(bytes.len())], bytes].concat()
+        };
+
+        let h1 = Sha1::digest_now(&m.to_signed_bytes_be());
+        let x = int2octets(&self.privkey.to_biguint().unwrap());
+        let h = int2octets(&(bits2int(&h1) % &q));
+
+        let mut v = vec![0x01_u8; 20];
+        let mut k = vec![0x00_u8; 20];
+        k = hmac_sha1(&k, &[&v[..], &[0x00], &x, &h].concat());
+        v = hmac_sha1(&k, &v);
+        k = hmac_sha1(&k, &[&v[..], &[0x01], &x, &h].concat());
+        v = hmac_sha1(&k, &v);
+        loop {
+            let mut t = vec![];
+            while t.len() < rlen {
+                v = hmac_sha1(&k, &v);
+                t.extend_from_slice(&v);
+            }
+            let candidate = bits2int(&t);
+            if !candidate.is_zero() && candidate < q {
+                return candidate.to_bigint().unwrap();
+            }
+            k = hmac_sha1(&k, &[&v[..], &[0x00]].concat());
+            v = hmac_sha1(&k, &v);
         }
     }
 }
+
+fn hmac_sha1(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut key = if key.len() > 64 {
+        Sha1::digest_now(key)
+    } else {
+        key.to_vec()
+    };
+    key.resize(64, 0);
+    let ipad = key.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>();
+    let opad = key.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>();
+    let inner = Sha1::digest_now(&[&ipad[..], data].concat());
+    Sha1::digest_now(&[&opad[..], &inner[..]].concat())
+}
+
+#[test]
+fn dsa_rfc6979_works() {
+    // RFC 6979 A.2.1, DSA 1024 with SHA-1 and the message "sample"
+    let params = DSAParams {
+        p: BigInt::parse_bytes(
+            b"86F5CA03DCFEB225063FF830A0C769B9DD9D6153AD91D7CE27F787C43278B447\
+              E6533B86B18BED6E8A48B784A14C252C5BE0DBF60B86D6385BD2F12FB763ED88\
+              73ABFD3F5BA2E0A8C0A59082EAC056935E529DAF7C610467899C77ADEDFC846C\
+              881870B7B19B2B58F9BE0521A17002E3BDD6B86685EE90B3D9A1B02B782B1779"
+                .iter()
+                .filter(|c| !c.is_ascii_whitespace())
+                .cloned()
+                .collect::<Vec<u8>>()
+                .as_slice(),
+            16,
+        )
+        .unwrap(),
+        q: BigInt::parse_bytes(b"996F967F6C8E388D9E28D01E205FBA957A5698B1", 16).unwrap(),
+        g: BigInt::parse_bytes(
+            b"07B0F92546150B62514BB771E2A0C0CE387F03BDA6C56B505209FF25FD3C133D\
+              89BBCD97E904E09114D9A7DEFDEADFC9078EA544D2E401AEECC40BB9FBBF78FD\
+              87995A10A1C27CB7789B594BA7EFB5C4326A9FE59A070E136DB77175464ADCA4\
+              17BE5DCE2F40D10A46A3A3943F26AB7FD9C0398FF8C76EE0A56826A8A88F1DBD"
+                .iter()
+                .filter(|c| !c.is_ascii_whitespace())
+                .cloned()
+                .collect::<Vec<u8>>()
+                .as_slice(),
+            16,
+        )
+        .unwrap(),
+    };
+    assert!(params.validate());
+    let x = BigInt::parse_bytes(b"411602CB19A6CCC34494D79D98EF1E7ED5AF25F7", 16).unwrap();
+    let mut dsa = DSA::from_privkey(&params, &x);
+    dsa.nonce = NonceStrategy::Rfc6979;
+    dsa.strict = true;
+
+    let m = BigInt::from_signed_bytes_be(b"sample");
+    let (r, s) = dsa.sign(&m);
+    assert_eq!(
+        r,
+        BigInt::parse_bytes(b"2E1A0C2562B2912CAAF89186FB0F42001585DA55", 16).unwrap()
+    );
+    assert_eq!(
+        s,
+        BigInt::parse_bytes(b"29EFB6B0AFF2D7A68EB70CA313022253B9A88DF5", 16).unwrap()
+    );
+    assert!(dsa.verify(&m, &r, &s));
+    assert_eq!(dsa.sign(&m), (r, s));
+}
+
+#[test]
+fn dsa_params_and_strict_verify_work() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(32);
+    assert!(DSAParams::generate(1024, 128, &mut rng).is_err());
+    let params = DSAParams::generate(1024, 160, &mut rng).unwrap();
+    assert_eq!(params.p.bits(), 1024);
+    assert_eq!(params.q.bits(), 160);
+    assert!(params.validate());
+
+    let mut dsa = DSA::from_params(&params, &mut rng);
+    dsa.nonce = NonceStrategy::UniformRandom;
+    let m = BigInt::from_signed_bytes_be(b"hi mom");
+    let (r, s) = dsa.sign(&m);
+    assert!(dsa.verify(&m, &r, &s));
+    assert!(!dsa.verify(&BigInt::from_signed_bytes_be(b"hi dad"), &r, &s));
+
+    // cryptopals 45: with g = 0 every r is 0 and anything verifies unless r is range checked
+    let mut tampered = DSA::new_with_g_with_rng(BigInt::zero(), &mut rng);
+    let (r, s) = tampered.sign_with_rng(&m, &mut rng);
+    assert!(tampered.verify(&BigInt::from_signed_bytes_be(b"anything"), &r, &s));
+    tampered.strict = true;
+    assert!(!tampered.verify(&BigInt::from_signed_bytes_be(b"anything"), &r, &s));
+}
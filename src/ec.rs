@@ -0,0 +1,575 @@
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::{One, Zero};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::numtheory::{legendre, mod_inv, mod_sub, sqrt_mod};
+use crate::sha1::Sha1;
+
+/*
+Short Weierstrass curves y^2 = x^3 + a x + b over a prime field. Points are
+affine at the api level, scalar multiplication runs in Jacobian coordinates
+(X, Y, Z) ~ (X/Z^2, Y/Z^3) so it needs one inversion instead of one per step.
+*/
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Point {
+    Infinity,
+    Affine(BigUint, BigUint),
+}
+
+/*
+z = 0 is the point at infinity
+*/
+#[derive(Clone, Debug)]
+pub struct JacobianPoint {
+    pub x: BigUint,
+    pub y: BigUint,
+    pub z: BigUint,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve {
+    pub p: BigUint,
+    pub a: BigUint,
+    pub b: BigUint,
+    pub g: Point,
+    // order of g and #E / n
+    pub n: BigUint,
+    pub h: BigUint,
+}
+
+fn hex(s: &str) -> BigUint {
+    BigUint::parse_bytes(s.as_bytes(), 16).unwrap()
+}
+
+impl Curve {
+    pub fn new(p: BigUint, a: BigUint, b: BigUint, g: Point, n: BigUint, h: BigUint) -> Curve {
+        let a = a % &p;
+        let b = b % &p;
+        Curve { p, a, b, g, n, h }
+    }
+
+    pub fn p256() -> Curve {
+        let p = hex("ffffffff00000001000000000000000000000000ffffffffffffffffffffffff");
+        Curve::new(
+            p.clone(),
+            p - 3_u32,
+            hex("5ac635d8aa3a93e7b3ebbd55769886bc651d06b0cc53b0f63bce3c3e27d2604b"),
+            Point::Affine(
+                hex("6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296"),
+                hex("4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5"),
+            ),
+            hex("ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551"),
+            BigUint::one(),
+        )
+    }
+
+    pub fn secp256k1() -> Curve {
+        Curve::new(
+            hex("fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f"),
+            BigUint::zero(),
+            BigUint::from(7_u32),
+            Point::Affine(
+                hex("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+                hex("483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8"),
+            ),
+            hex("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141"),
+            BigUint::one(),
+        )
+    }
+
+    /*
+    the set 8 curve, y^2 = x^3 - 95051 x + 11279326
+    */
+    pub fn cryptopals() -> Curve {
+        let p = BigUint::parse_bytes(b"233970423115425145524320034830162017933", 10).unwrap();
+        Curve::new(
+            p.clone(),
+            p - 95051_u32,
+            BigUint::from(11279326_u32),
+            Point::Affine(
+                BigUint::from(182_u32),
+                BigUint::parse_bytes(b"85518893674295321206118380980485522083", 10).unwrap(),
+            ),
+            BigUint::parse_bytes(b"29246302889428143187362802287225875743", 10).unwrap(),
+            BigUint::from(8_u32),
+        )
+    }
+
+    /*
+    x^3 + a x + b
+    */
+    pub fn rhs(&self, x: &BigUint) -> BigUint {
+        (x * x * x + &self.a * x + &self.b) % &self.p
+    }
+
+    pub fn is_on_curve(&self, point: &Point) -> bool {
+        match point {
+            Point::Infinity => true,
+            Point::Affine(x, y) => x < &self.p && y < &self.p && y * y % &self.p == self.rhs(x),
+        }
+    }
+
+    /*
+    one of the two points with this x, None when x^3 + a x + b is not a square
+    */
+    pub fn lift_x(&self, x: &BigUint) -> Option<Point> {
+        let y = sqrt_mod(&self.rhs(x), &self.p)?;
+        Some(Point::Affine(x % &self.p, y))
+    }
+
+    pub fn neg(&self, point: &Point) -> Point {
+        match point {
+            Point::Infinity => Point::Infinity,
+            Point::Affine(x, y) => Point::Affine(x.clone(), mod_sub(&BigUint::zero(), y, &self.p)),
+        }
+    }
+
+    pub fn add(&self, p1: &Point, p2: &Point) -> Point {
+        let p = &self.p;
+        let (x1, y1, x2, y2) = match (p1, p2) {
+            (Point::Infinity, _) => return p2.clone(),
+            (_, Point::Infinity) => return p1.clone(),
+            (Point::Affine(x1, y1), Point::Affine(x2, y2)) => (x1, y1, x2, y2),
+        };
+        if x1 == x2 && (y1 + y2) % p == BigUint::zero() {
+            return Point::Infinity;
+        }
+        let slope = if x1 == x2 {
+            (BigUint::from(3_u32) * x1 * x1 + &self.a) * mod_inv(&((y1 << 1) % p), p).unwrap()
+        } else {
+            mod_sub(y2, y1, p) * mod_inv(&mod_sub(x2, x1, p), p).unwrap()
+        } % p;
+        let x3 = mod_sub(&(&slope * &slope), &(x1 + x2), p);
+        let y3 = mod_sub(&(slope * mod_sub(x1, &x3, p)), y1, p);
+        Point::Affine(x3, y3)
+    }
+
+    pub fn double(&self, point: &Point) -> Point {
+        self.add(point, point)
+    }
+
+    pub fn to_jacobian(&self, point: &Point) -> JacobianPoint {
+        match point {
+            Point::Infinity => JacobianPoint {
+                x: BigUint::one(),
+                y: BigUint::one(),
+                z: BigUint::zero(),
+            },
+            Point::Affine(x, y) => JacobianPoint {
+                x: x.clone(),
+                y: y.clone(),
+                z: BigUint::one(),
+            },
+        }
+    }
+
+    pub fn to_affine(&self, point: &JacobianPoint) -> Point {
+        if point.z.is_zero() {
+            return Point::Infinity;
+        }
+        let p = &self.p;
+        let z_inv = mod_inv(&point.z, p).unwrap();
+        let z_inv2 = &z_inv * &z_inv % p;
+        let z_inv3 = &z_inv2 * &z_inv % p;
+        Point::Affine(&point.x * z_inv2 % p, &point.y * z_inv3 % p)
+    }
+
+    pub fn jacobian_double(&self, point: &JacobianPoint) -> JacobianPoint {
+        let p = &self.p;
+        if point.z.is_zero() || point.y.is_zero() {
+            return self.to_jacobian(&Point::Infinity);
+        }
+        let (x, y, z) = (&point.x, &point.y, &point.z);
+        let y2 = y * y % p;
+        let s = (x * &y2 % p) << 2;
+        let z2 = z * z % p;
+        let m = (BigUint::from(3_u32) * x * x + &self.a * &z2 * &z2) % p;
+        let x3 = mod_sub(&(&m * &m), &(&s << 1), p);
+        let y3 = mod_sub(&(m * mod_sub(&s, &x3, p)), &((&y2 * &y2) << 3), p);
+        let z3 = ((y * z) << 1) % p;
+        JacobianPoint {
+            x: x3,
+            y: y3,
+            z: z3,
+        }
+    }
+
+    pub fn jacobian_add(&self, p1: &JacobianPoint, p2: &JacobianPoint) -> JacobianPoint {
+        let p = &self.p;
+        if p1.z.is_zero() {
+            return p2.clone();
+        }
+        if p2.z.is_zero() {
+            return p1.clone();
+        }
+        let z1_2 = &p1.z * &p1.z % p;
+        let z2_2 = &p2.z * &p2.z % p;
+        let u1 = &p1.x * &z2_2 % p;
+        let u2 = &p2.x * &z1_2 % p;
+        let s1 = &p1.y * &z2_2 * &p2.z % p;
+        let s2 = &p2.y * &z1_2 * &p1.z % p;
+        if u1 == u2 {
+            if s1 == s2 {
+                return self.jacobian_double(p1);
+            }
+            return self.to_jacobian(&Point::Infinity);
+        }
+        let h = mod_sub(&u2, &u1, p);
+        let r = mod_sub(&s2, &s1, p);
+        let h2 = &h * &h % p;
+        let h3 = &h2 * &h % p;
+        let u1h2 = u1 * h2 % p;
+        let x3 = mod_sub(&mod_sub(&(&r * &r), &h3, p), &(&u1h2 << 1), p);
+        let y3 = mod_sub(&(r * mod_sub(&u1h2, &x3, p)), &(s1 * h3), p);
+        let z3 = h * &p1.z * &p2.z % p;
+        JacobianPoint {
+            x: x3,
+            y: y3,
+            z: z3,
+        }
+    }
+
+    /*
+    double and add, the scalar is not reduced so k = n gives infinity for g
+    */
+    pub fn mul(&self, point: &Point, k: &BigUint) -> Point {
+        let base = self.to_jacobian(point);
+        let mut acc = self.to_jacobian(&Point::Infinity);
+        for i in (0..k.bits()).rev() {
+            acc = self.jacobian_double(&acc);
+            if k.bit(i) {
+                acc = self.jacobian_add(&acc, &base);
+            }
+        }
+        self.to_affine(&acc)
+    }
+
+    pub fn gen_scalar<R: Rng + ?Sized>(&self, rng: &mut R) -> BigUint {
+        rng.gen_biguint_range(&BigUint::one(), &self.n)
+    }
+}
+
+/*
+Montgomery curve B v^2 = u^3 + A u^2 + u, only ever used through u so B does not
+matter. Every u is either on the curve or on its quadratic twist, the ladder
+happily works on both
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct MontgomeryCurve {
+    pub p: BigUint,
+    pub a: BigUint,
+    // base point u, its order, and the order of the twist group
+    pub u: BigUint,
+    pub n: BigUint,
+    pub twist_order: BigUint,
+}
+
+impl MontgomeryCurve {
+    /*
+    cryptopals 60, the same group as Curve::cryptopals() with u = x - 178
+    */
+    pub fn cryptopals() -> MontgomeryCurve {
+        MontgomeryCurve {
+            p: BigUint::parse_bytes(b"233970423115425145524320034830162017933", 10).unwrap(),
+            a: BigUint::from(534_u32),
+            u: BigUint::from(4_u32),
+            n: BigUint::parse_bytes(b"29246302889428143187362802287225875743", 10).unwrap(),
+            twist_order: BigUint::parse_bytes(b"233970423115425145549737651362517029924", 10)
+                .unwrap(),
+        }
+    }
+
+    pub fn rhs(&self, u: &BigUint) -> BigUint {
+        (u * u * u + &self.a * u * u + u) % &self.p
+    }
+
+    pub fn is_on_twist(&self, u: &BigUint) -> bool {
+        legendre(&self.rhs(u), &self.p) == -1
+    }
+
+    /*
+    x-only addition: (X:Z) of P + Q from P, Q and (X:Z) of P - Q
+    */
+    pub fn x_add(
+        &self,
+        p1: &(BigUint, BigUint),
+        p2: &(BigUint, BigUint),
+        diff: &(BigUint, BigUint),
+    ) -> (BigUint, BigUint) {
+        let p = &self.p;
+        let da = mod_sub(&p2.0, &p2.1, p) * (&p1.0 + &p1.1) % p;
+        let cb = (&p2.0 + &p2.1) * mod_sub(&p1.0, &p1.1, p) % p;
+        let sum = (&da + &cb) % p;
+        let sub = mod_sub(&da, &cb, p);
+        (&diff.1 * &sum * &sum % p, &diff.0 * &sub * &sub % p)
+    }
+
+    pub fn x_double(&self, point: &(BigUint, BigUint)) -> (BigUint, BigUint) {
+        let p = &self.p;
+        let a24 =
+            mod_sub(&self.a, &BigUint::from(2_u32), p) * mod_inv(&BigUint::from(4_u32), p).unwrap();
+        let s = (&point.0 + &point.1) % p;
+        let d = mod_sub(&point.0, &point.1, p);
+        let aa = &s * &s % p;
+        let bb = &d * &d % p;
+        let e = mod_sub(&aa, &bb, p);
+        let z = &e * (&aa + a24 * &e) % p;
+        (aa * bb % p, z)
+    }
+
+    /*
+    None when the point at infinity comes out
+    */
+    pub fn to_affine(&self, point: &(BigUint, BigUint)) -> Option<BigUint> {
+        let z_inv = mod_inv(&point.1, &self.p)?;
+        Some(&point.0 * z_inv % &self.p)
+    }
+
+    /*
+    the RFC 7748 ladder, (k u, (k + 1) u) is kept as the loop invariant
+    */
+    pub fn ladder(&self, u: &BigUint, k: &BigUint) -> Option<BigUint> {
+        let base = (u % &self.p, BigUint::one());
+        let mut r0 = (BigUint::one(), BigUint::zero());
+        let mut r1 = base.clone();
+        for i in (0..k.bits()).rev() {
+            if k.bit(i) {
+                r0 = self.x_add(&r0, &r1, &base);
+                r1 = self.x_double(&r1);
+            } else {
+                r1 = self.x_add(&r0, &r1, &base);
+                r0 = self.x_double(&r0);
+            }
+        }
+        self.to_affine(&r0)
+    }
+}
+
+pub struct EcdhState {
+    pub curve: Curve,
+    pub secret: BigUint,
+    pub pubkey: Point,
+}
+
+impl EcdhState {
+    pub fn new(curve: &Curve) -> EcdhState {
+        EcdhState::new_with_rng(curve, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(curve: &Curve, rng: &mut R) -> EcdhState {
+        let secret = curve.gen_scalar(rng);
+        let pubkey = curve.mul(&curve.g, &secret);
+        EcdhState {
+            curve: curve.clone(),
+            secret,
+            pubkey,
+        }
+    }
+
+    /*
+    no validation of the peer point, that is what cryptopals 59 and 60 abuse
+    */
+    pub fn gen_shared_key(&self, other_pubkey: &Point) -> Point {
+        self.curve.mul(other_pubkey, &self.secret)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EcHash {
+    Sha1,
+    Sha256,
+}
+
+pub struct ECDSA {
+    pub curve: Curve,
+    pub pubkey: Point,
+    pub privkey: BigUint,
+    pub hash: EcHash,
+}
+
+impl ECDSA {
+    pub fn new(curve: &Curve) -> ECDSA {
+        ECDSA::new_with_rng(curve, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(curve: &Curve, rng: &mut R) -> ECDSA {
+        ECDSA::from_privkey(curve, &curve.gen_scalar(rng))
+    }
+
+    pub fn from_privkey(curve: &Curve, privkey: &BigUint) -> ECDSA {
+        ECDSA {
+            curve: curve.clone(),
+            pubkey: curve.mul(&curve.g, privkey),
+            privkey: privkey.clone(),
+            hash: EcHash::Sha256,
+        }
+    }
+
+    /*
+    the leftmost n.bits() bits of the digest
+    */
+    pub fn hash_message(&self, m: &[u8]) -> BigUint {
+        let digest = match self.hash {
+            EcHash::Sha1 => Sha1::digest_now(m),
+            EcHash::Sha256 => Sha256::digest(m).to_vec(),
+        };
+        let e = BigUint::from_bytes_be(&digest);
+        let digest_bits = digest.len() * 8;
+        let n_bits = self.curve.n.bits() as usize;
+        if digest_bits > n_bits {
+            e >> (digest_bits - n_bits)
+        } else {
+            e
+        }
+    }
+
+    pub fn sign(&self, m: &[u8]) -> (BigUint, BigUint) {
+        self.sign_with_rng(m, &mut rand::thread_rng())
+    }
+
+    pub fn sign_with_rng<R: Rng + ?Sized>(&self, m: &[u8], rng: &mut R) -> (BigUint, BigUint) {
+        loop {
+            let k = self.curve.gen_scalar(rng);
+            if let Some(sig) = self.sign_with_nonce(m, &k) {
+                return sig;
+            }
+        }
+    }
+
+    /*
+    None for the rare k that gives r = 0 or s = 0
+    */
+    pub fn sign_with_nonce(&self, m: &[u8], k: &BigUint) -> Option<(BigUint, BigUint)> {
+        let n = &self.curve.n;
+        let r = match self.curve.mul(&self.curve.g, k) {
+            Point::Affine(x, _) => x % n,
+            Point::Infinity => return None,
+        };
+        let s = mod_inv(k, n)? * (self.hash_message(m) + &self.privkey * &r) % n;
+        if r.is_zero() || s.is_zero() {
+            return None;
+        }
+        Some((r, s))
+    }
+
+    pub fn verify(&self, m: &[u8], r: &BigUint, s: &BigUint) -> bool {
+        let n = &self.curve.n;
+        if r.is_zero() || r >= n || s.is_zero() || s >= n {
+            return false;
+        }
+        let w = match mod_inv(s, n) {
+            Some(w) => w,
+            None => return false,
+        };
+        let u1 = self.hash_message(m) * &w % n;
+        let u2 = r * w % n;
+        let curve = &self.curve;
+        let point = curve.add(&curve.mul(&curve.g, &u1), &curve.mul(&self.pubkey, &u2));
+        match point {
+            Point::Affine(x, _) => &(x % n) == r,
+            Point::Infinity => false,
+        }
+    }
+}
+
+#[test]
+fn ec_arithmetic_works() {
+    for curve in [Curve::p256(), Curve::secp256k1(), Curve::cryptopals()].iter() {
+        let g = &curve.g;
+        assert!(curve.is_on_curve(g));
+        assert_eq!(curve.mul(g, &curve.n), Point::Infinity);
+        assert_eq!(curve.add(g, &curve.neg(g)), Point::Infinity);
+
+        // affine and jacobian agree
+        let mut acc = Point::Infinity;
+        for k in 1..20_u32 {
+            acc = curve.add(&acc, g);
+            assert_eq!(curve.mul(g, &BigUint::from(k)), acc);
+            assert!(curve.is_on_curve(&acc));
+        }
+        assert_eq!(
+            curve.double(&curve.mul(g, &BigUint::from(7_u32))),
+            curve.mul(g, &BigUint::from(14_u32))
+        );
+
+        if let Point::Affine(x, y) = g {
+            let lifted = curve.lift_x(x).unwrap();
+            assert!(lifted == *g || lifted == Point::Affine(x.clone(), &curve.p - y));
+        }
+    }
+
+    let k1 = Curve::secp256k1();
+    assert_eq!(
+        k1.mul(&k1.g, &BigUint::from(2_u32)),
+        Point::Affine(
+            hex("c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"),
+            hex("1ae168fea63dc339a3c58419466ceaeef7f632653266d0e1236431a950cfe52a"),
+        )
+    );
+    let p256 = Curve::p256();
+    assert_eq!(
+        p256.double(&p256.g),
+        Point::Affine(
+            hex("7cf27b188d034f7e8a52380304b51ac3c08969e277f21b35a60b48fc47669978"),
+            hex("07775510db8ed040293d9ac69f7430dbba7dade63ce982299e04b79d227873d1"),
+        )
+    );
+
+    // a toy curve: y^2 = x^3 + 2x + 3 over F_97 has 100 points, (3, 6) has order 5
+    let toy = Curve::new(
+        BigUint::from(97_u32),
+        BigUint::from(2_u32),
+        BigUint::from(3_u32),
+        Point::Affine(BigUint::from(3_u32), BigUint::from(6_u32)),
+        BigUint::from(5_u32),
+        BigUint::from(20_u32),
+    );
+    assert!(toy.is_on_curve(&toy.g));
+    assert_eq!(toy.mul(&toy.g, &toy.n), Point::Infinity);
+}
+
+#[test]
+fn ecdh_and_ecdsa_work() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(34);
+    for curve in [Curve::p256(), Curve::secp256k1()].iter() {
+        let alice = EcdhState::new_with_rng(curve, &mut rng);
+        let bob = EcdhState::new_with_rng(curve, &mut rng);
+        assert_eq!(
+            alice.gen_shared_key(&bob.pubkey),
+            bob.gen_shared_key(&alice.pubkey)
+        );
+
+        let mut ecdsa = ECDSA::new_with_rng(curve, &mut rng);
+        for hash in [EcHash::Sha256, EcHash::Sha1].iter() {
+            ecdsa.hash = *hash;
+            let (r, s) = ecdsa.sign(b"hi mom");
+            assert!(ecdsa.verify(b"hi mom", &r, &s));
+            assert!(!ecdsa.verify(b"hi dad", &r, &s));
+            assert!(!ecdsa.verify(b"hi mom", &(&r + 1_u32), &s));
+            assert!(!ecdsa.verify(b"hi mom", &r, &curve.n));
+        }
+    }
+}
+
+#[test]
+fn montgomery_ladder_works() {
+    let mont = MontgomeryCurve::cryptopals();
+    let weierstrass = Curve::cryptopals();
+    let shift = BigUint::from(178_u32);
+
+    assert_eq!(mont.ladder(&mont.u, &mont.n), None);
+    assert!(!mont.is_on_twist(&mont.u));
+    for k in [1_u32, 2, 3, 1000, 0xdead_beef].iter() {
+        let k = BigUint::from(*k);
+        let u = mont.ladder(&mont.u, &k).unwrap();
+        match weierstrass.mul(&weierstrass.g, &k) {
+            Point::Affine(x, _) => assert_eq!(u + &shift, x),
+            Point::Infinity => panic!("unexpected infinity"),
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::{One, Zero};
+use rand::Rng;
+
+use crate::ec::{Curve, MontgomeryCurve, Point};
+use crate::numtheory::crt;
+use crate::primes::primes_below;
+
+/*
+Attacks on ECDH peers that do not validate the points they are given, the
+elliptic curve version of feeding bad groups to DH. The victim is an oracle that
+takes our public point and answers with a tag keyed by the shared secret (cryptopals
+uses HMAC over a fixed message), `mac` computes that same tag for a candidate
+shared point so the residues can be brute forced offline.
+*/
+
+/*
+the distinct primes below max_factor that divide order
+*/
+pub fn small_prime_factors(order: &BigUint, max_factor: u64) -> Vec<u64> {
+    primes_below(max_factor)
+        .into_iter()
+        .filter(|q| (order % *q).is_zero())
+        .collect()
+}
+
+/*
+a point of prime order r on y^2 = x^3 + a x + b' where order is the size of that
+curve. The addition formulas never look at b, so the victim computes on it
+without noticing
+*/
+pub fn invalid_curve_point<R: Rng + ?Sized>(
+    curve: &Curve,
+    b: &BigUint,
+    order: &BigUint,
+    r: u64,
+    rng: &mut R,
+) -> Point {
+    let invalid = Curve::new(
+        curve.p.clone(),
+        curve.a.clone(),
+        b.clone(),
+        Point::Infinity,
+        order.clone(),
+        BigUint::one(),
+    );
+    // strip all of r from the order, the r-part need not be cyclic (Z/2 x Z/2 is
+    // common) so multiplying by order / r alone can kill every point
+    let mut cofactor = order.clone();
+    while (&cofactor % r).is_zero() {
+        cofactor /= r;
+    }
+    let r_big = BigUint::from(r);
+    loop {
+        let x = rng.gen_biguint_below(&curve.p);
+        if let Some(point) = invalid.lift_x(&x) {
+            let mut candidate = invalid.mul(&point, &cofactor);
+            if candidate == Point::Infinity {
+                continue;
+            }
+            loop {
+                let next = invalid.mul(&candidate, &r_big);
+                if next == Point::Infinity {
+                    return candidate;
+                }
+                candidate = next;
+            }
+        }
+    }
+}
+
+/*
+walks 0, Q, 2Q, ... until the tag matches, the index is the secret mod r
+*/
+fn brute_force_point_residue<M>(
+    curve: &Curve,
+    point: &Point,
+    r: u64,
+    tag: &[u8],
+    mac: &M,
+) -> Option<u64>
+where
+    M: Fn(&Point) -> Vec<u8>,
+{
+    let mut acc = Point::Infinity;
+    for j in 0..r {
+        if mac(&acc) == tag {
+            return Some(j);
+        }
+        acc = curve.add(&acc, point);
+    }
+    None
+}
+
+/*
+`invalid_curves` are (b, curve order) pairs. Returns (residue, modulus) with
+secret = residue mod modulus, collecting small factors until the modulus passes
+the order of the real base point or the factors run out
+*/
+pub fn invalid_curve_residues<O, M, R>(
+    curve: &Curve,
+    invalid_curves: &[(BigUint, BigUint)],
+    max_factor: u64,
+    mut oracle: O,
+    mac: M,
+    rng: &mut R,
+) -> (BigUint, BigUint)
+where
+    O: FnMut(&Point) -> Vec<u8>,
+    M: Fn(&Point) -> Vec<u8>,
+    R: Rng + ?Sized,
+{
+    let mut residues = vec![];
+    let mut moduli = vec![];
+    let mut modulus = BigUint::one();
+    for (b, order) in invalid_curves.iter() {
+        for r in small_prime_factors(order, max_factor) {
+            if modulus > curve.n {
+                break;
+            }
+            if moduli.contains(&BigUint::from(r)) {
+                continue;
+            }
+            let point = invalid_curve_point(curve, b, order, r, rng);
+            let tag = oracle(&point);
+            if let Some(residue) = brute_force_point_residue(curve, &point, r, &tag, &mac) {
+                residues.push(BigUint::from(residue));
+                moduli.push(BigUint::from(r));
+                modulus *= r;
+            }
+        }
+    }
+    (
+        crt(&residues, &moduli).unwrap_or_else(BigUint::zero),
+        modulus,
+    )
+}
+
+/*
+cryptopals 59, the whole secret when the small factors cover it
+*/
+pub fn invalid_curve_attack<O, M, R>(
+    curve: &Curve,
+    invalid_curves: &[(BigUint, BigUint)],
+    max_factor: u64,
+    oracle: O,
+    mac: M,
+    rng: &mut R,
+) -> Option<BigUint>
+where
+    O: FnMut(&Point) -> Vec<u8>,
+    M: Fn(&Point) -> Vec<u8>,
+    R: Rng + ?Sized,
+{
+    let (residue, modulus) =
+        invalid_curve_residues(curve, invalid_curves, max_factor, oracle, mac, rng);
+    if modulus > curve.n {
+        Some(residue % &curve.n)
+    } else {
+        None
+    }
+}
+
+/*
+a u on the twist whose order is the product of `primes`
+*/
+pub fn twist_point<R: Rng + ?Sized>(
+    curve: &MontgomeryCurve,
+    primes: &[u64],
+    rng: &mut R,
+) -> BigUint {
+    let m = primes.iter().fold(BigUint::one(), |acc, r| acc * *r);
+    let cofactor = &curve.twist_order / &m;
+    loop {
+        let u = rng.gen_biguint_below(&curve.p);
+        if !curve.is_on_twist(&u) {
+            continue;
+        }
+        let candidate = match curve.ladder(&u, &cofactor) {
+            Some(candidate) => candidate,
+            None => continue,
+        };
+        if primes
+            .iter()
+            .all(|r| curve.ladder(&candidate, &(&m / *r)).is_some())
+        {
+            return candidate;
+        }
+    }
+}
+
+/*
+x-only, so j and r - j look the same: walks jQ with differential additions up
+to r / 2 and returns the smaller of the two
+*/
+fn brute_force_twist_residue<M>(
+    curve: &MontgomeryCurve,
+    u: &BigUint,
+    r: u64,
+    tag: &[u8],
+    mac: &M,
+) -> Option<u64>
+where
+    M: Fn(Option<&BigUint>) -> Vec<u8>,
+{
+    if mac(None) == tag {
+        return Some(0);
+    }
+    let base = (u.clone(), BigUint::one());
+    let mut prev = base.clone();
+    let mut cur = curve.x_double(&base);
+    if mac(Some(u)) == tag {
+        return Some(1);
+    }
+    for j in 2..=r / 2 {
+        if mac(curve.to_affine(&cur).as_ref()) == tag {
+            return Some(j);
+        }
+        let next = curve.x_add(&cur, &base, &prev);
+        prev = std::mem::replace(&mut cur, next);
+    }
+    None
+}
+
+/*
+cryptopals 60. Every residue from the twist is only known up to sign, so after
+each new factor r we send one point of order (modulus * r) and test the two
+ways of gluing +-residue to the running result; the other two are their
+negatives. Returns (residue, modulus) with secret = +-residue mod modulus, the
+rest of the secret has to come from a kangaroo walk on the real curve
+*/
+pub fn twist_attack<O, M, R>(
+    curve: &MontgomeryCurve,
+    max_factor: u64,
+    mut oracle: O,
+    mac: M,
+    rng: &mut R,
+) -> (BigUint, BigUint)
+where
+    O: FnMut(&BigUint) -> Vec<u8>,
+    M: Fn(Option<&BigUint>) -> Vec<u8>,
+    R: Rng + ?Sized,
+{
+    // 2 divides everything and u = 0 breaks differential addition, odd primes only
+    let primes = small_prime_factors(&curve.twist_order, max_factor)
+        .into_iter()
+        .filter(|r| r % 2 == 1)
+        .collect::<Vec<_>>();
+
+    let mut residue = BigUint::zero();
+    let mut modulus = BigUint::one();
+    let mut used = vec![];
+    for r in primes {
+        let point = twist_point(curve, &[r], rng);
+        let tag = oracle(&point);
+        let a = match brute_force_twist_residue(curve, &point, r, &tag, &mac) {
+            Some(a) => BigUint::from(a),
+            None => continue,
+        };
+        let r_big = BigUint::from(r);
+        if modulus.is_one() {
+            residue = a;
+        } else {
+            used.push(r);
+            let point = twist_point(curve, &used, rng);
+            used.pop();
+            let tag = oracle(&point);
+            let moduli = [modulus.clone(), r_big.clone()];
+            let candidates = [
+                crt(&[residue.clone(), a.clone()], &moduli).unwrap(),
+                crt(&[residue.clone(), (&r_big - &a) % &r_big], &moduli).unwrap(),
+            ];
+            residue = match candidates
+                .iter()
+                .find(|c| mac(curve.ladder(&point, c).as_ref()) == tag)
+            {
+                Some(c) => c.clone(),
+                None => continue,
+            };
+        }
+        used.push(r);
+        modulus *= r;
+    }
+    (residue, modulus)
+}
+
+#[test]
+fn invalid_curve_attack_works() {
+    use crate::ec::EcdhState;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use sha2::{Digest, Sha256};
+
+    let mut rng = StdRng::seed_from_u64(59);
+    let curve = Curve::cryptopals();
+    let bob = EcdhState::new_with_rng(&curve, &mut rng);
+
+    let mac = |shared: &Point| {
+        let key = match shared {
+            Point::Affine(x, y) => [x.to_bytes_be(), y.to_bytes_be()].concat(),
+            Point::Infinity => vec![],
+        };
+        Sha256::digest(&[&key[..], b"crazy flamboyant for the rap enjoyment"].concat()).to_vec()
+    };
+    let invalid_curves = [
+        ("210", "233970423115425145550826547352470124412"),
+        ("504", "233970423115425145544350131142039591210"),
+        ("727", "233970423115425145545378039958152057148"),
+    ]
+    .iter()
+    .map(|(b, order)| {
+        (
+            BigUint::parse_bytes(b.as_bytes(), 10).unwrap(),
+            BigUint::parse_bytes(order.as_bytes(), 10).unwrap(),
+        )
+    })
+    .collect::<Vec<_>>();
+
+    let recovered = invalid_curve_attack(
+        &curve,
+        &invalid_curves,
+        1 << 16,
+        |point| mac(&bob.gen_shared_key(point)),
+        mac,
+        &mut rng,
+    );
+    assert_eq!(recovered, Some(bob.secret.clone()));
+}
+
+#[test]
+fn twist_attack_works() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use sha2::{Digest, Sha256};
+
+    let mut rng = StdRng::seed_from_u64(60);
+    let curve = MontgomeryCurve::cryptopals();
+    // the factors below 2^17 cover ~46 bits, keep the secret close to that so
+    // the final search stays a short loop instead of a kangaroo walk
+    let secret = rng.gen_biguint(52);
+    let pubkey = curve.ladder(&curve.u, &secret).unwrap();
+
+    let mac = |shared: Option<&BigUint>| {
+        let key = shared.map(|u| u.to_bytes_be()).unwrap_or_default();
+        Sha256::digest(&[&key[..], b"crazy flamboyant for the rap enjoyment"].concat()).to_vec()
+    };
+    let (residue, modulus) = twist_attack(
+        &curve,
+        1 << 17,
+        |u| mac(curve.ladder(u, &secret).as_ref()),
+        mac,
+        &mut rng,
+    );
+    assert!(residue == &secret % &modulus || residue == (&modulus - &secret % &modulus) % &modulus);
+
+    let recovered = (0..1_u32 << 8)
+        .flat_map(|j| {
+            let base = &modulus * j;
+            vec![&base + &residue, &base + &modulus - &residue]
+        })
+        .find(|candidate| curve.ladder(&curve.u, candidate).as_ref() == Some(&pubkey));
+    assert_eq!(recovered, Some(secret));
+}
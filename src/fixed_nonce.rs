@@ -0,0 +1,352 @@
+use crate::scoring::{score_or_worst, NgramModel, Scorer};
+
+/*
+Many ciphertexts under the same keystream, CTR with a fixed nonce or a reused
+one-time pad. Byte j of the keystream meets byte j of every line, so each
+column is single byte xor and a column scorer narrows every keystream byte
+down to a few values. An n-gram model over the lines then picks between them:
+a beam search from left to right, and a few passes that look at the right hand
+context too. Cribs, known plaintext of a line at an offset, pin bytes outright.
+*/
+
+// keystream values kept per column after the column scoring
+const CANDIDATES: usize = 32;
+// passes over the keystream after the beam search
+const MAX_ROUNDS: usize = 3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Crib {
+    pub ciphertext: usize,
+    pub offset: usize,
+    pub plaintext: Vec<u8>,
+}
+
+/*
+confidence is the weight the best value got among the candidates of its byte,
+1.0 for bytes a crib gave away
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution {
+    pub keystream: Vec<u8>,
+    pub confidence: Vec<f64>,
+}
+
+impl Solution {
+    // as much of the ciphertext as the keystream reaches
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Vec<u8> {
+        ciphertext
+            .iter()
+            .zip(self.keystream.iter())
+            .map(|(c, k)| c ^ k)
+            .collect()
+    }
+}
+
+fn column(ciphertexts: &[Vec<u8>], j: usize) -> Vec<u8> {
+    ciphertexts
+        .iter()
+        .filter_map(|ct| ct.get(j).cloned())
+        .collect()
+}
+
+fn pinned_keystream(
+    ciphertexts: &[Vec<u8>],
+    cribs: &[Crib],
+    len: usize,
+) -> Result<Vec<Option<u8>>, ()> {
+    let mut keystream = vec![None; len];
+    for crib in cribs {
+        let ct = ciphertexts.get(crib.ciphertext).ok_or(())?;
+        for (i, &p) in crib.plaintext.iter().enumerate() {
+            let j = crib.offset + i;
+            let k = ct.get(j).ok_or(())? ^ p;
+            match keystream[j] {
+                Some(pinned) if pinned != k => return Err(()),
+                _ => keystream[j] = Some(k),
+            }
+        }
+    }
+    Ok(keystream)
+}
+
+/*
+the model's log-probabilities of every line's n-gram that ends at j, the
+keystream has to reach j. Lines start after a line break, which is what tells
+the first letter's case.
+*/
+fn ngrams_ending_at(
+    ciphertexts: &[Vec<u8>],
+    keystream: &[u8],
+    j: usize,
+    model: &NgramModel,
+) -> f64 {
+    let n = model.n();
+    let padding = (n - 1).saturating_sub(j);
+    ciphertexts
+        .iter()
+        .filter(|ct| ct.len() > j)
+        .map(|ct| {
+            let mut gram = vec![b'\n'; padding];
+            gram.extend((j + 1 + padding - n..=j).map(|i| ct[i] ^ keystream[i]));
+            model.score(&gram)
+        })
+        .sum()
+}
+
+// all the n-grams byte j is part of
+fn ngrams_around(ciphertexts: &[Vec<u8>], keystream: &[u8], j: usize, model: &NgramModel) -> f64 {
+    (j..(j + model.n()).min(keystream.len()))
+        .map(|end| ngrams_ending_at(ciphertexts, keystream, end, model))
+        .sum()
+}
+
+// how much of the weight the first score gets, treating scores as log-odds
+fn softmax_first(scores: &[f64]) -> f64 {
+    let max = scores.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        // nothing to tell them apart
+        return 1.0 / scores.len() as f64;
+    }
+    let total: f64 = scores.iter().map(|s| (s - max).exp()).sum();
+    (scores[0] - max).exp() / total
+}
+
+/*
+The keystream for the longest line. Without a model every byte is simply the
+column scorer's favourite and that is also what the confidence comes from.
+*/
+pub fn solve<S: Scorer + ?Sized>(
+    ciphertexts: &[Vec<u8>],
+    column_scorer: &S,
+    model: Option<&NgramModel>,
+    cribs: &[Crib],
+    beam_width: usize,
+) -> Result<Solution, ()> {
+    let len = ciphertexts.iter().map(|ct| ct.len()).max().ok_or(())?;
+    let pinned = pinned_keystream(ciphertexts, cribs, len)?;
+
+    // best first, with their column scores
+    let candidates: Vec<Vec<(u8, f64)>> = (0..len)
+        .map(|j| {
+            if let Some(k) = pinned[j] {
+                return vec![(k, 0.0)];
+            }
+            let column = column(ciphertexts, j);
+            let mut scored: Vec<(u8, f64)> = (0..=255_u8)
+                .map(|k| {
+                    let plaintext: Vec<u8> = column.iter().map(|c| c ^ k).collect();
+                    (k, score_or_worst(column_scorer.score(&plaintext)))
+                })
+                .collect();
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+            scored.truncate(CANDIDATES);
+            scored
+        })
+        .collect();
+
+    let model = match model {
+        Some(model) => model,
+        None => {
+            return Ok(Solution {
+                keystream: candidates.iter().map(|c| c[0].0).collect(),
+                confidence: candidates
+                    .iter()
+                    .map(|c| {
+                        let scores: Vec<f64> = c.iter().map(|&(_, s)| s).collect();
+                        softmax_first(&scores)
+                    })
+                    .collect(),
+            })
+        }
+    };
+
+    let mut beam: Vec<(Vec<u8>, f64)> = vec![(vec![], 0.0)];
+    for (j, column) in candidates.iter().enumerate() {
+        let mut next = vec![];
+        for (prefix, score) in beam.iter() {
+            for &(k, _) in column {
+                let mut keystream = prefix.clone();
+                keystream.push(k);
+                let score = score + ngrams_ending_at(ciphertexts, &keystream, j, model);
+                next.push((keystream, score));
+            }
+        }
+        next.sort_by(|a, b| b.1.total_cmp(&a.1));
+        next.truncate(beam_width.max(1));
+        beam = next;
+    }
+    let mut keystream = beam.swap_remove(0).0;
+
+    let choices = |keystream: &mut Vec<u8>, j: usize| -> Vec<(u8, f64)> {
+        let kept = keystream[j];
+        let mut scored: Vec<(u8, f64)> = candidates[j]
+            .iter()
+            .map(|&(k, _)| {
+                keystream[j] = k;
+                (k, ngrams_around(ciphertexts, keystream, j, model))
+            })
+            .collect();
+        keystream[j] = kept;
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+    };
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+        for j in 0..len {
+            let best = choices(&mut keystream, j)[0].0;
+            if best != keystream[j] {
+                keystream[j] = best;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    let confidence = (0..len)
+        .map(|j| {
+            let scores: Vec<f64> = choices(&mut keystream, j).iter().map(|&(_, s)| s).collect();
+            softmax_first(&scores)
+        })
+        .collect();
+    Ok(Solution {
+        keystream,
+        confidence,
+    })
+}
+
+/*
+Slides a guess of some plaintext along one of the lines. Each offset implies
+keystream bytes, the offsets under which the other lines read best come
+first, scored by the model's average per n-gram.
+*/
+pub fn drag_crib(
+    ciphertexts: &[Vec<u8>],
+    ciphertext: usize,
+    crib: &[u8],
+    model: &NgramModel,
+) -> Vec<(usize, f64)> {
+    let target = match ciphertexts.get(ciphertext) {
+        Some(target) if target.len() >= crib.len() => target,
+        _ => return vec![],
+    };
+    let mut placements: Vec<(usize, f64)> = (0..=target.len() - crib.len())
+        .map(|offset| {
+            let keystream: Vec<u8> = crib
+                .iter()
+                .zip(target[offset..].iter())
+                .map(|(p, c)| p ^ c)
+                .collect();
+            let scores: Vec<f64> = ciphertexts
+                .iter()
+                .enumerate()
+                .filter(|&(i, ct)| i != ciphertext && ct.len() >= offset + model.n())
+                .map(|(_, ct)| {
+                    let plaintext: Vec<u8> = ct[offset..]
+                        .iter()
+                        .zip(keystream.iter())
+                        .map(|(c, k)| c ^ k)
+                        .collect();
+                    model.score(&plaintext)
+                })
+                .collect();
+            let score = if scores.is_empty() {
+                f64::NEG_INFINITY
+            } else {
+                scores.iter().sum::<f64>() / scores.len() as f64
+            };
+            (offset, score)
+        })
+        .collect();
+    placements.sort_by(|a, b| b.1.total_cmp(&a.1));
+    placements
+}
+
+#[test]
+fn fixed_nonce_works() {
+    use crate::scoring::ChiSquared;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let corpus = b"It was the best of times, it was the worst of times, it was the age of \
+        wisdom, it was the age of foolishness, it was the epoch of belief, it was the epoch \
+        of incredulity, it was the season of Light, it was the season of Darkness, it was the \
+        spring of hope, it was the winter of despair, we had everything before us, we had \
+        nothing before us, we were all going direct to Heaven, we were all going direct the \
+        other way - in short, the period was so far like the present period, that some of its \
+        noisiest authorities insisted on its being received, for good or for evil, in the \
+        superlative degree of comparison only. There were a king with a large jaw and a queen \
+        with a plain face, on the throne of England; there were a king with a large jaw and a \
+        queen with a fair face, on the throne of France. In both countries it was clearer than \
+        crystal to the lords of the State preserves of loaves and fishes, that things in \
+        general were settled for ever.";
+    let lines: [&[u8]; 12] = [
+        b"It was the year of Our Lord one thousand seven hundred and seventy-five.",
+        b"Spiritual revelations were conceded to England at that favoured period,",
+        b"as at this. Mrs. Southcott had recently attained her five-and-twentieth",
+        b"blessed birthday, of whom a prophetic private in the Life Guards had",
+        b"heralded the sublime appearance by announcing that arrangements were",
+        b"made for the swallowing up of London and Westminster.",
+        b"Even the Cock-lane ghost had been laid only a round dozen of years,",
+        b"after rapping out its messages, as the spirits of this very year",
+        b"last past rapped out theirs.",
+        b"Mere messages in the earthly order of events had lately come to the",
+        b"English Crown and People, from a congress of British subjects",
+        b"in America: which, strange to relate, have proved more important",
+    ];
+    let mut rng = StdRng::seed_from_u64(50);
+    let keystream: Vec<u8> = (0..80).map(|_| rng.gen()).collect();
+    let cts: Vec<Vec<u8>> = lines
+        .iter()
+        .map(|line| {
+            line.iter()
+                .zip(keystream.iter())
+                .map(|(p, k)| p ^ k)
+                .collect()
+        })
+        .collect();
+    let right = |solution: &Solution| {
+        solution
+            .keystream
+            .iter()
+            .zip(keystream.iter())
+            .filter(|(a, b)| a == b)
+            .count()
+    };
+    let longest = lines.iter().map(|line| line.len()).max().unwrap();
+
+    let english = ChiSquared::english();
+    let columns = solve(&cts, &english, None, &[], 1).unwrap();
+    assert_eq!(columns.keystream.len(), longest);
+    let trigrams = NgramModel::train(corpus, 3);
+    let solution = solve(&cts, &english, Some(&trigrams), &[], 64).unwrap();
+    assert!(right(&solution) > right(&columns));
+    assert_eq!(solution.decrypt(&cts[1]), lines[1]);
+    // only the first line is long enough for the last byte
+    assert!(solution.confidence[longest - 1] < 0.5);
+
+    let crib = b"enty-five.";
+    assert_eq!(drag_crib(&cts, 0, crib, &trigrams)[0].0, 62);
+    let cribs = [Crib {
+        ciphertext: 0,
+        offset: 62,
+        plaintext: crib.to_vec(),
+    }];
+    let cribbed = solve(&cts, &english, Some(&trigrams), &cribs, 64).unwrap();
+    assert_eq!(cribbed.keystream, keystream[..longest]);
+    assert_eq!(cribbed.confidence[longest - 1], 1.0);
+    assert_eq!(cribbed.decrypt(&cts[0]), lines[0]);
+
+    let conflicting = Crib {
+        plaintext: b"x".to_vec(),
+        ..cribs[0].clone()
+    };
+    assert!(solve(&cts, &english, None, &[cribs[0].clone(), conflicting], 1).is_err());
+
+    let nan = |_: &[u8]| f64::NAN;
+    assert!(solve(&cts, &nan, Some(&trigrams), &[], 4).is_ok());
+    let clueless = solve(&cts, &nan, None, &[], 1).unwrap();
+    assert!(clueless
+        .confidence
+        .iter()
+        .all(|&c| c == 1.0 / CANDIDATES as f64));
+}
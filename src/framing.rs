@@ -0,0 +1,268 @@
+use std::io::{Read, Write};
+
+use num_bigint::BigUint;
+
+/*
+Length-prefixed wire format shared by the SRP and DH demos. A frame is a
+big-endian u32 body length followed by the body, the body is a list of typed
+fields, each a tag byte, a big-endian u32 payload length and the payload.
+Reads go through read_exact, so a peer may split a frame over any number of
+TCP segments.
+*/
+
+// anything larger is a broken or hostile peer
+pub const MAX_FRAME_LEN: usize = 1 << 20;
+
+const TAG_BYTES: u8 = 0x01;
+const TAG_STRING: u8 = 0x02;
+const TAG_BIGUINT: u8 = 0x03;
+
+const ERROR: &str = "ERROR";
+
+pub trait Encode {
+    const TAG: u8;
+    fn encode(&self) -> Vec<u8>;
+}
+
+pub trait Decode: Sized {
+    const TAG: u8;
+    fn decode(payload: &[u8]) -> Result<Self, ()>;
+}
+
+impl Encode for [u8] {
+    const TAG: u8 = TAG_BYTES;
+    fn encode(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl Encode for Vec<u8> {
+    const TAG: u8 = TAG_BYTES;
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl Decode for Vec<u8> {
+    const TAG: u8 = TAG_BYTES;
+    fn decode(payload: &[u8]) -> Result<Self, ()> {
+        Ok(payload.to_vec())
+    }
+}
+
+impl Encode for str {
+    const TAG: u8 = TAG_STRING;
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl Encode for String {
+    const TAG: u8 = TAG_STRING;
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl Decode for String {
+    const TAG: u8 = TAG_STRING;
+    fn decode(payload: &[u8]) -> Result<Self, ()> {
+        String::from_utf8(payload.to_vec()).map_err(|_| ())
+    }
+}
+
+impl Encode for BigUint {
+    const TAG: u8 = TAG_BIGUINT;
+    fn encode(&self) -> Vec<u8> {
+        self.to_bytes_be()
+    }
+}
+
+impl Decode for BigUint {
+    const TAG: u8 = TAG_BIGUINT;
+    fn decode(payload: &[u8]) -> Result<Self, ()> {
+        if payload.is_empty() {
+            return Err(());
+        }
+        Ok(BigUint::from_bytes_be(payload))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Message {
+    body: Vec<u8>,
+}
+
+impl Message {
+    pub fn new() -> Message {
+        Message { body: vec![] }
+    }
+
+    pub fn with<T: Encode + ?Sized>(mut self, value: &T) -> Message {
+        let payload = value.encode();
+        self.body.push(T::TAG);
+        self.body
+            .extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        self.body.extend_from_slice(&payload);
+        self
+    }
+
+    /*
+    how a server tells the peer why it gave up
+    */
+    pub fn error(reason: &str) -> Message {
+        Message::new().with(ERROR).with(reason)
+    }
+
+    pub fn error_reason(&self) -> Option<String> {
+        let mut fields = self.fields();
+        match fields.read::<String>() {
+            Ok(tag) if tag == ERROR => fields.read::<String>().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn fields(&self) -> FieldReader<'_> {
+        FieldReader { rest: &self.body }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&(self.body.len() as u32).to_be_bytes()[..], &self.body].concat()
+    }
+
+    /*
+    exactly one frame, nothing may trail it
+    */
+    pub fn from_bytes(data: &[u8]) -> Result<Message, ()> {
+        let mut data = data;
+        let message = Message::recv(&mut data)?;
+        if !data.is_empty() {
+            return Err(());
+        }
+        Ok(message)
+    }
+
+    pub fn send<W: Write>(&self, stream: &mut W) -> Result<(), ()> {
+        if self.body.len() > MAX_FRAME_LEN {
+            return Err(());
+        }
+        stream.write_all(&self.to_bytes()).map_err(|_| ())?;
+        stream.flush().map_err(|_| ())
+    }
+
+    pub fn recv<R: Read>(stream: &mut R) -> Result<Message, ()> {
+        let mut len = [0_u8; 4];
+        stream.read_exact(&mut len).map_err(|_| ())?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(());
+        }
+        let mut body = vec![0_u8; len];
+        stream.read_exact(&mut body).map_err(|_| ())?;
+        // reject malformed field layouts up front
+        let mut fields = FieldReader { rest: &body };
+        while !fields.rest.is_empty() {
+            fields.raw()?;
+        }
+        Ok(Message { body })
+    }
+}
+
+pub struct FieldReader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> FieldReader<'a> {
+    fn raw(&mut self) -> Result<(u8, &'a [u8]), ()> {
+        if self.rest.len() < 5 {
+            return Err(());
+        }
+        let tag = self.rest[0];
+        let mut len = [0_u8; 4];
+        len.copy_from_slice(&self.rest[1..5]);
+        let len = u32::from_be_bytes(len) as usize;
+        if self.rest.len() < 5 + len {
+            return Err(());
+        }
+        let payload = &self.rest[5..5 + len];
+        self.rest = &self.rest[5 + len..];
+        Ok((tag, payload))
+    }
+
+    /*
+    the next field, which has to be a T
+    */
+    pub fn read<T: Decode>(&mut self) -> Result<T, ()> {
+        let (tag, payload) = self.raw()?;
+        if tag != T::TAG {
+            return Err(());
+        }
+        T::decode(payload)
+    }
+
+    /*
+    fails if the peer sent more than we read
+    */
+    pub fn finish(self) -> Result<(), ()> {
+        if self.rest.is_empty() {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+}
+
+#[test]
+fn framing_works() {
+    use crate::dh::groups::MODP_8192;
+
+    // hands out at most 3 bytes per read, like a slow TCP peer
+    struct Trickle<'a>(&'a [u8]);
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(3).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let p = MODP_8192.p();
+    let message = Message::new()
+        .with("admin")
+        .with(&p)
+        .with(&[0_u8, 0, 1][..])
+        .with(&BigUint::from(0_u32));
+
+    let mut wire = vec![];
+    message.send(&mut wire).unwrap();
+    message.send(&mut wire).unwrap();
+    let mut trickle = Trickle(&wire);
+    for _ in 0..2 {
+        let received = Message::recv(&mut trickle).unwrap();
+        assert_eq!(received, message);
+        let mut fields = received.fields();
+        assert_eq!(fields.read::<String>(), Ok("admin".to_string()));
+        assert_eq!(fields.read::<BigUint>(), Ok(p.clone()));
+        assert_eq!(fields.read::<Vec<u8>>(), Ok(vec![0, 0, 1]));
+        assert_eq!(fields.read::<BigUint>(), Ok(BigUint::from(0_u32)));
+        assert!(fields.finish().is_ok());
+    }
+    assert!(Message::recv(&mut trickle).is_err());
+
+    // wrong type, truncation, trailing bytes, oversized frames
+    let bytes = message.to_bytes();
+    assert!(message.fields().read::<BigUint>().is_err());
+    assert!(Message::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Message::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+    assert!(Message::from_bytes(&[0x7f, 0xff, 0xff, 0xff]).is_err());
+    assert!(Message::from_bytes(&[0, 0, 0, 3, TAG_BYTES, 0, 0]).is_err());
+    let error = Message::from_bytes(&Message::error("unknown user").to_bytes()).unwrap();
+    assert_eq!(error.error_reason(), Some("unknown user".to_string()));
+    assert_eq!(message.error_reason(), None);
+
+    let two = Message::new().with("x").with("y");
+    let mut fields = two.fields();
+    fields.read::<String>().unwrap();
+    assert!(fields.finish().is_err());
+}
@@ -0,0 +1,192 @@
+use std::ops::BitXorAssign;
+
+/*
+Linear algebra over GF(2), for recovering the state of generators whose
+outputs are linear functions of their state bits. A row packs one equation's
+coefficients into u64 words, bit i of the row belongs to variable i.
+*/
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitRow {
+    words: Vec<u64>,
+}
+
+impl BitRow {
+    pub fn zero(len: usize) -> BitRow {
+        BitRow {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    pub fn unit(len: usize, i: usize) -> BitRow {
+        let mut row = BitRow::zero(len);
+        row.set(i, true);
+        row
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        self.words[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    pub fn set(&mut self, i: usize, value: bool) {
+        if value {
+            self.words[i / 64] |= 1 << (i % 64);
+        } else {
+            self.words[i / 64] &= !(1 << (i % 64));
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+
+    /*
+    parity of the bits both rows have set, i.e. the row applied to a solution
+    */
+    pub fn dot(&self, other: &BitRow) -> bool {
+        self.words
+            .iter()
+            .zip(other.words.iter())
+            .fold(0, |acc, (a, b)| acc ^ (a & b).count_ones())
+            & 1
+            == 1
+    }
+
+    // lowest set bit at or above `from`
+    fn next_one(&self, from: usize) -> Option<usize> {
+        let mut w = from / 64;
+        if w >= self.words.len() {
+            return None;
+        }
+        let mut word = self.words[w] & (!0_u64 << (from % 64));
+        loop {
+            if word != 0 {
+                return Some(w * 64 + word.trailing_zeros() as usize);
+            }
+            w += 1;
+            if w == self.words.len() {
+                return None;
+            }
+            word = self.words[w];
+        }
+    }
+}
+
+impl BitXorAssign<&BitRow> for BitRow {
+    fn bitxor_assign(&mut self, other: &BitRow) {
+        for (a, b) in self.words.iter_mut().zip(other.words.iter()) {
+            *a ^= b;
+        }
+    }
+}
+
+/*
+Equations go in one at a time and are reduced against the rows kept so far,
+each kept row is filed under its lowest variable. Once every variable has a
+row, solve() substitutes back from the top.
+*/
+pub struct Solver {
+    vars: usize,
+    pivots: Vec<Option<(BitRow, bool)>>,
+    rank: usize,
+    inconsistent: bool,
+}
+
+impl Solver {
+    pub fn new(vars: usize) -> Solver {
+        Solver {
+            vars,
+            pivots: vec![None; vars],
+            rank: 0,
+            inconsistent: false,
+        }
+    }
+
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    pub fn is_determined(&self) -> bool {
+        self.rank == self.vars
+    }
+
+    /*
+    row · x = rhs, returns whether the equation told us anything new
+    */
+    pub fn add(&mut self, mut row: BitRow, mut rhs: bool) -> bool {
+        let mut from = 0;
+        while let Some(p) = row.next_one(from) {
+            match &self.pivots[p] {
+                Some((pivot, pivot_rhs)) => {
+                    row ^= pivot;
+                    rhs ^= pivot_rhs;
+                    from = p + 1;
+                }
+                None => {
+                    self.pivots[p] = Some((row, rhs));
+                    self.rank += 1;
+                    return true;
+                }
+            }
+        }
+        // 0 = rhs
+        self.inconsistent |= rhs;
+        false
+    }
+
+    /*
+    the unique solution, None while variables are free or if the equations
+    contradict each other
+    */
+    pub fn solve(&self) -> Option<BitRow> {
+        if self.inconsistent || !self.is_determined() {
+            return None;
+        }
+        let mut x = BitRow::zero(self.vars);
+        for p in (0..self.vars).rev() {
+            let (row, rhs) = self.pivots[p].as_ref()?;
+            // row has bit p set and x bit p still clear
+            x.set(p, row.dot(&x) ^ rhs);
+        }
+        Some(x)
+    }
+}
+
+#[test]
+fn gf2_solver_works() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(43);
+    let vars = 150;
+    let secret: Vec<bool> = (0..vars).map(|_| rng.gen()).collect();
+
+    let mut solver = Solver::new(vars);
+    let mut useless = 0;
+    while !solver.is_determined() {
+        let mut row = BitRow::zero(vars);
+        let mut rhs = false;
+        for (i, &bit) in secret.iter().enumerate() {
+            if rng.gen_bool(0.3) {
+                row.set(i, true);
+                rhs ^= bit;
+            }
+        }
+        if !solver.add(row, rhs) {
+            useless += 1;
+        }
+        assert!(solver.rank() < vars || solver.solve().is_some());
+    }
+    assert!(useless < 20);
+    let x = solver.solve().unwrap();
+    assert!((0..vars).all(|i| x.get(i) == secret[i]));
+
+    // a dependent equation with the wrong right-hand side
+    let mut row = BitRow::unit(vars, 3);
+    row ^= &BitRow::unit(vars, 140);
+    assert!(!solver.add(row, !(secret[3] ^ secret[140])));
+    assert!(solver.solve().is_none());
+
+    let mut solver = Solver::new(2);
+    solver.add(BitRow::unit(2, 1), true);
+    assert!(solver.solve().is_none());
+}
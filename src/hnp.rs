@@ -0,0 +1,262 @@
+use num_bigint::{BigInt, Sign};
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
+
+use crate::dsa::DSA;
+use crate::ec::{Point, ECDSA};
+use crate::lll::lll_fp;
+use crate::numtheory::mod_inv_int;
+
+/*
+Hidden number problem: recover the private key x from signatures whose nonces
+are partially known. DSA and ECDSA both sign with s = k^-1 (h + r x) mod q, so
+every signature gives k = s^-1 r x + s^-1 h mod q, a linear relation in x. If a
+chunk of k is small, enough of those relations pin x down and lattice reduction
+finds it (Boneh-Venkatesan, Nguyen-Shparlinski).
+*/
+
+/*
+q, the modulus of the linear relations, and a check for a guessed x. The
+reduced basis holds several short vectors and only the check tells which of
+them carries the real key.
+*/
+pub trait HnpTarget {
+    fn group_order(&self) -> BigInt;
+    fn is_private_key(&self, x: &BigInt) -> bool;
+}
+
+impl HnpTarget for DSA {
+    fn group_order(&self) -> BigInt {
+        self.q.clone()
+    }
+
+    fn is_private_key(&self, x: &BigInt) -> bool {
+        self.g.modpow(x, &self.p) == self.pubkey
+    }
+}
+
+impl HnpTarget for ECDSA {
+    fn group_order(&self) -> BigInt {
+        BigInt::from_biguint(Sign::Plus, self.curve.n.clone())
+    }
+
+    fn is_private_key(&self, x: &BigInt) -> bool {
+        match x.to_biguint() {
+            Some(x) => {
+                self.pubkey != Point::Infinity && self.curve.mul(&self.curve.g, &x) == self.pubkey
+            }
+            None => false,
+        }
+    }
+}
+
+/*
+what is known about one nonce k. Bounded covers weak rngs (0 <= k < bound),
+Msb and Lsb are `bits` known top or bottom bits of a q.bits() wide nonce
+*/
+#[derive(Clone, Debug)]
+pub enum NonceLeak {
+    Bounded(BigInt),
+    Msb { bits: usize, value: BigInt },
+    Lsb { bits: usize, value: BigInt },
+}
+
+#[derive(Clone, Debug)]
+pub struct LeakySignature {
+    pub r: BigInt,
+    pub s: BigInt,
+    // the message digest as the signer used it
+    pub hash: BigInt,
+    pub leak: NonceLeak,
+}
+
+/*
+one HNP instance b = t x + u mod q with 0 <= b < bound
+*/
+#[derive(Clone, Debug)]
+pub struct HnpSample {
+    pub t: BigInt,
+    pub u: BigInt,
+    pub bound: BigInt,
+}
+
+impl LeakySignature {
+    pub fn to_sample(&self, q: &BigInt) -> Option<HnpSample> {
+        let s_inv = mod_inv_int(&self.s, q)?;
+        // k = t x + u mod q
+        let t = (&s_inv * &self.r).mod_floor(q);
+        let u = (&s_inv * &self.hash).mod_floor(q);
+        let width = q.bits() as usize;
+        match &self.leak {
+            NonceLeak::Bounded(bound) => Some(HnpSample {
+                t,
+                u,
+                bound: bound.clone(),
+            }),
+            // k = value * 2^(width - bits) + b
+            NonceLeak::Msb { bits, value } => {
+                let shift = width.checked_sub(*bits)?;
+                Some(HnpSample {
+                    t,
+                    u: (u - (value << shift)).mod_floor(q),
+                    bound: BigInt::one() << shift,
+                })
+            }
+            // k = b * 2^bits + value
+            NonceLeak::Lsb { bits, value } => {
+                let shift_inv = mod_inv_int(&(BigInt::one() << *bits), q)?;
+                Some(HnpSample {
+                    t: (t * &shift_inv).mod_floor(q),
+                    u: ((u - value) * shift_inv).mod_floor(q),
+                    bound: BigInt::one() << width.saturating_sub(*bits),
+                })
+            }
+        }
+    }
+}
+
+/*
+embeds the samples in the usual (m + 2)-dimensional lattice. Every coordinate is
+scaled so that the hidden vector (b_i - bound_i/2, x, embedding) has entries of
+the same size, then any reduced row ending in +-embedding gives x back. Everything
+is multiplied by q to stay integral
+*/
+pub fn solve_hnp<F>(q: &BigInt, samples: &[HnpSample], mut is_key: F) -> Option<BigInt>
+where
+    F: FnMut(&BigInt) -> bool,
+{
+    let m = samples.len();
+    if m == 0 {
+        return None;
+    }
+    let max_bound = samples.iter().map(|s| &s.bound).max()?.clone();
+    let weights = samples
+        .iter()
+        .map(|s| (&max_bound / &s.bound).max(BigInt::one()))
+        .collect::<Vec<_>>();
+    let embedding = q * (&max_bound >> 1_u32).max(BigInt::one());
+
+    let mut basis = vec![vec![BigInt::zero(); m + 2]; m + 2];
+    for (i, (sample, w)) in samples.iter().zip(weights.iter()).enumerate() {
+        basis[i][i] = q * q * w;
+        basis[m][i] = q * &sample.t * w;
+        let centered = &sample.u - (&sample.bound >> 1_u32);
+        basis[m + 1][i] = q * centered * w;
+    }
+    basis[m][m] = max_bound.clone();
+    basis[m + 1][m + 1] = embedding.clone();
+
+    for row in lll_fp(&basis).iter() {
+        if row[m + 1].abs() != embedding {
+            continue;
+        }
+        let x = if row[m + 1].is_negative() {
+            -&row[m]
+        } else {
+            row[m].clone()
+        };
+        let (x, rem) = x.div_rem(&max_bound);
+        if !rem.is_zero() {
+            continue;
+        }
+        for candidate in [x.mod_floor(q), (-x).mod_floor(q)].iter() {
+            if is_key(candidate) {
+                return Some(candidate.clone());
+            }
+        }
+    }
+    None
+}
+
+pub fn recover_private_key<T: HnpTarget>(target: &T, sigs: &[LeakySignature]) -> Option<BigInt> {
+    let q = target.group_order();
+    let samples = sigs
+        .iter()
+        .map(|sig| sig.to_sample(&q))
+        .collect::<Option<Vec<_>>>()?;
+    solve_hnp(&q, &samples, |x| target.is_private_key(x))
+}
+
+#[test]
+fn hnp_recovers_dsa_keys() {
+    use num_bigint::RandBigInt;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(33);
+    let dsa = DSA::from_params(&crate::dsa::DSAParams::cryptopals(), &mut rng);
+    let q = dsa.q.clone();
+    let width = q.bits() as usize;
+
+    let mut sign_leaking = |leak: &dyn Fn(&BigInt) -> NonceLeak, k_bits: usize| {
+        (0..12)
+            .map(|i| {
+                let m = BigInt::from(i);
+                let k = BigInt::from_biguint(Sign::Plus, rng.gen_biguint(k_bits as u64));
+                let (r, s) = dsa.sign_with_nonce(&m, &k).unwrap();
+                LeakySignature {
+                    r,
+                    s,
+                    hash: DSA::hash(&m),
+                    leak: leak(&k),
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // weak rng, k is only 128 bits wide
+    let sigs = sign_leaking(&|_| NonceLeak::Bounded(BigInt::one() << 128), 128);
+    assert_eq!(recover_private_key(&dsa, &sigs), Some(dsa.privkey.clone()));
+
+    // top 32 bits of a full width nonce leak
+    let sigs = sign_leaking(
+        &|k| NonceLeak::Msb {
+            bits: 32,
+            value: k >> (width - 32),
+        },
+        width - 1,
+    );
+    assert_eq!(recover_private_key(&dsa, &sigs), Some(dsa.privkey.clone()));
+
+    // bottom 32 bits leak
+    let sigs = sign_leaking(
+        &|k| NonceLeak::Lsb {
+            bits: 32,
+            value: k % (BigInt::one() << 32),
+        },
+        width - 1,
+    );
+    assert_eq!(recover_private_key(&dsa, &sigs), Some(dsa.privkey.clone()));
+
+    // not enough leaked to pin x down
+    let sigs = sign_leaking(&|_| NonceLeak::Bounded(BigInt::one() << 159), 159);
+    assert_eq!(recover_private_key(&dsa, &sigs[..2]), None);
+}
+
+#[test]
+fn hnp_recovers_ecdsa_keys() {
+    use crate::ec::Curve;
+    use num_bigint::RandBigInt;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(34);
+    let ecdsa = ECDSA::new_with_rng(&Curve::p256(), &mut rng);
+
+    // nonces from a 192 bit rng on a 256 bit curve
+    let sigs = (0..8)
+        .map(|i| {
+            let m = format!("message {}", i);
+            let k = rng.gen_biguint(192);
+            let (r, s) = ecdsa.sign_with_nonce(m.as_bytes(), &k).unwrap();
+            LeakySignature {
+                r: BigInt::from_biguint(Sign::Plus, r),
+                s: BigInt::from_biguint(Sign::Plus, s),
+                hash: BigInt::from_biguint(Sign::Plus, ecdsa.hash_message(m.as_bytes())),
+                leak: NonceLeak::Bounded(BigInt::one() << 192),
+            }
+        })
+        .collect::<Vec<_>>();
+    let x = recover_private_key(&ecdsa, &sigs).unwrap();
+    assert_eq!(x.to_biguint(), Some(ecdsa.privkey.clone()));
+}
@@ -0,0 +1,454 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use rand::{Error, RngCore, SeedableRng};
+
+use crate::numtheory::mod_inv_int;
+
+/*
+Linear congruential generators, state' = a * state + c mod m, showing
+`bits` bits of the state from bit `shift` up. m = 0 stands for 2^64, the
+modulus of plain wrapping u64 arithmetic.
+*/
+
+// brute forcing more unseen state bits than this is the caller's business
+const MAX_HIDDEN_BITS: u32 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LcgParams {
+    pub a: u64,
+    pub c: u64,
+    pub m: u64,
+    pub shift: u32,
+    pub bits: u32,
+}
+
+// Park and Miller's minimal standard, C++ std::minstd_rand
+pub const MINSTD: LcgParams = LcgParams {
+    a: 48271,
+    c: 0,
+    m: 0x7FFF_FFFF,
+    shift: 0,
+    bits: 31,
+};
+
+// glibc random() with an 8 byte TYPE_0 state
+pub const GLIBC_TYPE_0: LcgParams = LcgParams {
+    a: 1103515245,
+    c: 12345,
+    m: 1 << 31,
+    shift: 0,
+    bits: 31,
+};
+
+// the Microsoft C runtime's rand()
+pub const MSVC: LcgParams = LcgParams {
+    a: 214013,
+    c: 2531011,
+    m: 1 << 32,
+    shift: 16,
+    bits: 15,
+};
+
+// java.util.Random.nextInt()
+pub const JAVA: LcgParams = LcgParams {
+    a: 0x5_DEEC_E66D,
+    c: 0xB,
+    m: 1 << 48,
+    shift: 16,
+    bits: 32,
+};
+
+// Knuth's MMIX
+pub const MMIX: LcgParams = LcgParams {
+    a: 6364136223846793005,
+    c: 1442695040888963407,
+    m: 0,
+    shift: 0,
+    bits: 64,
+};
+
+fn mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        !0
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+impl LcgParams {
+    fn modulus(&self) -> u128 {
+        if self.m == 0 {
+            1 << 64
+        } else {
+            self.m as u128
+        }
+    }
+
+    // how many bits a state can have
+    fn state_bits(&self) -> u32 {
+        128 - (self.modulus() - 1).leading_zeros()
+    }
+
+    pub fn output(&self, state: u64) -> u64 {
+        state.checked_shr(self.shift).unwrap_or(0) & mask(self.bits)
+    }
+
+    pub fn step(&self, state: u64) -> u64 {
+        ((self.a as u128 * state as u128 + self.c as u128) % self.modulus()) as u64
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Lcg {
+    params: LcgParams,
+    state: u64,
+}
+
+impl Lcg {
+    pub fn new(params: LcgParams, seed: u64) -> Lcg {
+        Lcg {
+            params,
+            state: (seed as u128 % params.modulus()) as u64,
+        }
+    }
+
+    pub fn params(&self) -> LcgParams {
+        self.params
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_state(&mut self) -> u64 {
+        self.state = self.params.step(self.state);
+        self.state
+    }
+
+    pub fn extract_number(&mut self) -> u64 {
+        let state = self.next_state();
+        self.params.output(state)
+    }
+
+    /*
+    A generator that continues after the given outputs when neither a, c nor
+    m are known, the outputs have to be whole states. With t_i = s_i+1 - s_i,
+    t_i+2 t_i - t_i+1^2 is a multiple of m, the gcd of a few of them is m.
+    */
+    pub fn clone_from_outputs(outputs: &[u64]) -> Result<Lcg, ()> {
+        if outputs.len() < 6 {
+            return Err(());
+        }
+        let s: Vec<BigInt> = outputs.iter().map(|&output| BigInt::from(output)).collect();
+        let t: Vec<BigInt> = s.windows(2).map(|w| &w[1] - &w[0]).collect();
+        let m = t
+            .windows(3)
+            .map(|w| (&w[2] * &w[0] - &w[1] * &w[1]).abs())
+            .fold(BigInt::zero(), |m, u| m.gcd(&u));
+        let max = s.iter().max().unwrap();
+        let two_64 = BigInt::one() << 64_u32;
+        if m <= *max || m > two_64 {
+            return Err(());
+        }
+        // a = t_i+1 / t_i for any t_i that is invertible
+        let a = t
+            .windows(2)
+            .find_map(|w| mod_inv_int(&w[0], &m).map(|inv| (&w[1] * inv).mod_floor(&m)))
+            .ok_or(())?;
+        let c = (&s[1] - &a * &s[0]).mod_floor(&m);
+
+        let params = LcgParams {
+            a: a.to_u64().ok_or(())?,
+            c: c.to_u64().ok_or(())?,
+            m: (&m % two_64).to_u64().ok_or(())?,
+            shift: 0,
+            bits: 64,
+        };
+        let mut lcg = Lcg::new(params, outputs[0]);
+        for &output in outputs[1..].iter() {
+            if lcg.extract_number() != output {
+                return Err(());
+            }
+        }
+        Ok(lcg)
+    }
+
+    /*
+    The same for known parameters from truncated outputs, by trying every
+    value of the state bits the first output doesn't show. There have to be
+    enough outputs to leave a single candidate.
+    */
+    pub fn clone_from_truncated(params: LcgParams, outputs: &[u64]) -> Result<Lcg, ()> {
+        let low = params.shift;
+        // mod a power of two, bits never reach down and those above the output don't matter
+        let high = if params.modulus().is_power_of_two() {
+            0
+        } else {
+            params
+                .state_bits()
+                .saturating_sub(params.shift + params.bits)
+        };
+        if outputs.len() < 2 || low + high > MAX_HIDDEN_BITS {
+            return Err(());
+        }
+        let shown = (outputs[0] as u128) << low;
+        let mut found = None;
+        for guess in 0..1_u64 << (low + high) {
+            let state = ((guess >> low) as u128) << (params.shift + params.bits)
+                | shown
+                | (guess & mask(low)) as u128;
+            if state >= params.modulus() || params.output(state as u64) != outputs[0] {
+                continue;
+            }
+            let mut lcg = Lcg::new(params, state as u64);
+            if outputs[1..]
+                .iter()
+                .all(|&output| lcg.extract_number() == output)
+            {
+                if found.is_some() {
+                    return Err(());
+                }
+                found = Some(lcg);
+            }
+        }
+        found.ok_or(())
+    }
+}
+
+impl Lcg {
+    /*
+    `width` random bits out of as many outputs as it takes, the first one ends
+    up highest. Outputs wider than that give their top bits, the low bits of
+    an LCG being the weak ones.
+    */
+    fn next_bits(&mut self, width: u32) -> u64 {
+        let bits = self.params.bits.min(64);
+        let mut gathered = 0_u64;
+        let mut have = 0;
+        while have < width {
+            let take = bits.min(width - have);
+            let output = self.extract_number() >> (bits - take);
+            gathered = gathered.checked_shl(take).unwrap_or(0) | output;
+            have += take;
+        }
+        gathered
+    }
+}
+
+/*
+Every word is filled with output bits, a 15 bit MSVC rand() takes three calls
+for a u32.
+*/
+impl RngCore for Lcg {
+    fn next_u32(&mut self) -> u32 {
+        self.next_bits(32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next_bits(64)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/*
+java.util.Random, the JAVA generator with Java's seed scrambling and its
+ways of turning next(bits) into ints, longs and doubles.
+*/
+#[derive(Clone, Debug)]
+pub struct JavaRandom {
+    lcg: Lcg,
+}
+
+impl JavaRandom {
+    pub fn new(seed: i64) -> JavaRandom {
+        JavaRandom::from_state(seed as u64 ^ JAVA.a)
+    }
+
+    pub fn from_state(state: u64) -> JavaRandom {
+        JavaRandom {
+            lcg: Lcg::new(JAVA, state),
+        }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.lcg.state()
+    }
+
+    fn next(&mut self, bits: u32) -> i32 {
+        (self.lcg.next_state() >> (48 - bits)) as u32 as i32
+    }
+
+    pub fn next_int(&mut self) -> i32 {
+        self.next(32)
+    }
+
+    /*
+    uniform in 0..bound, bound has to be positive
+    */
+    pub fn next_int_bound(&mut self, bound: i32) -> i32 {
+        assert!(bound > 0);
+        let mut r = self.next(31);
+        let m = bound - 1;
+        if bound & m == 0 {
+            return ((bound as i64 * r as i64) >> 31) as i32;
+        }
+        // rejects the last partial stretch of bound-sized buckets
+        let mut u = r;
+        loop {
+            r = u % bound;
+            if u.wrapping_sub(r).wrapping_add(m) >= 0 {
+                return r;
+            }
+            u = self.next(31);
+        }
+    }
+
+    pub fn next_long(&mut self) -> i64 {
+        ((self.next(32) as i64) << 32).wrapping_add(self.next(32) as i64)
+    }
+
+    pub fn next_boolean(&mut self) -> bool {
+        self.next(1) != 0
+    }
+
+    pub fn next_double(&mut self) -> f64 {
+        (((self.next(26) as i64) << 27) + self.next(27) as i64) as f64 / (1_u64 << 53) as f64
+    }
+
+    /*
+    continues after consecutive nextInt() values, two are enough
+    */
+    pub fn clone_from_ints(ints: &[i32]) -> Result<JavaRandom, ()> {
+        let outputs: Vec<u64> = ints.iter().map(|&int| int as u32 as u64).collect();
+        Ok(JavaRandom {
+            lcg: Lcg::clone_from_truncated(JAVA, &outputs)?,
+        })
+    }
+
+    /*
+    continues after consecutive nextLong() values, a single one will do
+    */
+    pub fn clone_from_longs(longs: &[i64]) -> Result<JavaRandom, ()> {
+        let mut ints = vec![];
+        for &long in longs {
+            // the low int was added as a signed value
+            let low = long as i32;
+            ints.push((long.wrapping_sub(low as i64) >> 32) as i32);
+            ints.push(low);
+        }
+        JavaRandom::clone_from_ints(&ints)
+    }
+}
+
+impl RngCore for JavaRandom {
+    fn next_u32(&mut self) -> u32 {
+        self.next_int() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next_long() as u64
+    }
+
+    // what nextBytes() does
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_int().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for JavaRandom {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: [u8; 8]) -> JavaRandom {
+        JavaRandom::new(i64::from_le_bytes(seed))
+    }
+
+    // new Random(seed)
+    fn seed_from_u64(seed: u64) -> JavaRandom {
+        JavaRandom::new(seed as i64)
+    }
+}
+
+#[test]
+fn lcg_works() {
+    use rand::Rng;
+
+    // srand(1) and Java's own numbers
+    let mut msvc = Lcg::new(MSVC, 1);
+    let outputs: Vec<u64> = (0..4).map(|_| msvc.extract_number()).collect();
+    assert_eq!(outputs, vec![41, 18467, 6334, 26500]);
+    let mut minstd = Lcg::new(MINSTD, 1);
+    assert_eq!(minstd.extract_number(), 48271);
+    assert_eq!(JavaRandom::new(0).next_int(), -1155484576);
+    assert_eq!(JavaRandom::new(0).next_double(), 0.730967787376657);
+    let mut java = JavaRandom::seed_from_u64(42);
+    assert_eq!(java.next_int(), -1170105035);
+    let mut java = JavaRandom::new(42);
+    let bounded: Vec<i32> = (0..5).map(|_| java.next_int_bound(10)).collect();
+    assert_eq!(bounded, vec![0, 3, 8, 4, 0]);
+    assert_eq!(java.next_int_bound(1000000007), 595021505);
+    assert_eq!(java.next_long(), -5391787952107853122);
+    assert!(!java.next_boolean());
+    assert_eq!(java.next_int_bound(64), 57);
+
+    // unknown parameters, whole states
+    for &params in [MMIX, GLIBC_TYPE_0, MINSTD].iter() {
+        let mut lcg = Lcg::new(params, 0xdead_beef);
+        let outputs: Vec<u64> = (0..12).map(|_| lcg.extract_number()).collect();
+        let mut clone = Lcg::clone_from_outputs(&outputs).unwrap();
+        assert_eq!((clone.params().a, clone.params().m), (params.a, params.m));
+        for _ in 0..10 {
+            assert_eq!(clone.extract_number(), lcg.extract_number());
+        }
+    }
+    assert!(Lcg::clone_from_outputs(&[1, 2, 3]).is_err());
+
+    // known parameters, truncated outputs
+    let mut clone = Lcg::clone_from_truncated(MSVC, &outputs).unwrap();
+    for _ in 0..10 {
+        assert_eq!(clone.extract_number(), msvc.extract_number());
+    }
+    let mut java = JavaRandom::new(-7);
+    let ints = [java.next_int(), java.next_int()];
+    let mut clone = JavaRandom::clone_from_ints(&ints).unwrap();
+    assert_eq!(clone.next_long(), java.next_long());
+    let long = java.next_long();
+    let mut clone = JavaRandom::clone_from_longs(&[long]).unwrap();
+    for _ in 0..10 {
+        assert_eq!(clone.next_int(), java.next_int());
+    }
+    let wide = LcgParams { shift: 40, ..MMIX };
+    assert!(Lcg::clone_from_truncated(wide, &[1, 2]).is_err());
+    assert!(Lcg::clone_from_truncated(JAVA, &[ints[0] as u32 as u64]).is_err());
+
+    // handed to code that wants whole random words
+    let mut msvc = Lcg::new(MSVC, 1);
+    let key = crate::random_key_with_rng(64_u8, &mut msvc);
+    for column in 0..4 {
+        assert!(key.iter().skip(column).step_by(4).any(|&b| b >= 0x80));
+    }
+    assert!((0..100).any(|_| msvc.gen_range(0, 1_u32 << 20) >= 1 << 15));
+    let mut msvc = Lcg::new(MSVC, 1);
+    assert_eq!(msvc.next_u32(), 41 << 17 | 18467 << 2 | 6334 >> 13);
+    let mut mmix = Lcg::new(MMIX, 1);
+    assert_eq!(mmix.next_u64(), Lcg::new(MMIX, 1).extract_number());
+}
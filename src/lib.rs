@@ -1,13 +1,35 @@
 // reexport block_cipher stuff for cryptopals crate users
 pub mod block_ciphers;
 pub mod dh;
+pub mod dh_attacks;
+pub mod dlog;
 pub mod dsa;
+pub mod ec;
+pub mod ecdh_attacks;
+pub mod fixed_nonce;
+pub mod framing;
+pub mod gf2;
+pub mod hnp;
+pub mod lcg;
+pub mod lll;
 pub mod md4;
 pub mod mt19937;
+pub mod numtheory;
+pub mod offline_cracker;
+pub mod primes;
+pub mod rc4;
+pub mod repeating_xor;
 pub mod rsa;
+pub mod rsa_broadcast;
+pub mod rsa_oracle;
+pub mod scoring;
+pub mod seed_recovery;
+pub mod server;
 pub mod sha1;
 pub mod srp;
+pub mod weak_rsa;
 pub mod weakened_srp;
+pub mod xorshift;
 
 use rand::Rng;
 use std::io::{self, Read};
@@ -27,10 +49,13 @@ pub fn load_stdin() -> String {
 }
 
 pub fn random_key<T: Into<usize>>(length: T) -> Vec<u8> {
+    random_key_with_rng(length, &mut rand::thread_rng())
+}
+
+pub fn random_key_with_rng<T: Into<usize>, R: Rng + ?Sized>(length: T, rng: &mut R) -> Vec<u8> {
     let length = length.into() as usize;
     let mut output: Vec<u8> = Vec::with_capacity(length);
 
-    let mut rng = rand::thread_rng();
     for i in 0..length {
         output.push(rng.gen());
     }
@@ -329,16 +354,42 @@ generation of knowledge, exceeds the short vehemence of any carnal pleasure.";
 }
 
 pub fn find_xor_key_eng(data: &[u8]) -> (isize, u8, Vec<u8>) {
-    let mut best = (-0xffff, 0x00, vec![]);
-    for b in 1..=255u8 {
+    let worst = (-0xffff as f64, 0x00, vec![]);
+    let (score, key, output) = best_xor_key(data, 1..=255, &scoring::EnglishHeuristic, worst);
+    (score as isize, key, output)
+}
+
+/*
+the single byte key whose output the scorer likes best, 0 included
+*/
+pub fn find_xor_key_with<S: scoring::Scorer + ?Sized>(
+    data: &[u8],
+    scorer: &S,
+) -> (f64, u8, Vec<u8>) {
+    let worst = (f64::NEG_INFINITY, 0x00, data.to_vec());
+    best_xor_key(data, 0..=255, scorer, worst)
+}
+
+/*
+the best of `keys`, a key has to beat `worst` strictly to be picked at all
+*/
+fn best_xor_key<S: scoring::Scorer + ?Sized>(
+    data: &[u8],
+    keys: std::ops::RangeInclusive<u8>,
+    scorer: &S,
+    worst: (f64, u8, Vec<u8>),
+) -> (f64, u8, Vec<u8>) {
+    let mut best = worst;
+    for b in keys {
         let decrypted = xor_arrays(data, &[b]);
-        let score = english_frequency_score(&decrypted);
+        let score = scoring::score_or_worst(scorer.score(&decrypted));
         if score > best.0 {
             best = (score, b, decrypted);
         }
     }
     best
 }
+
 #[test]
 fn find_xor_key_eng_works() {
     let text = "1b37373331363f78151b7f2b783431333d78397828372d363c78373e783a393b3736";
@@ -350,11 +401,33 @@ fn find_xor_key_eng_works() {
 }
 
 pub fn search_xor_key(data: &[u8], key_len: usize) -> (isize, Vec<u8>, Vec<u8>) {
+    let (score, key, output) = search_columns(data, key_len, |column| {
+        let (score, key, output) = find_xor_key_eng(column);
+        (score as f64, key, output)
+    });
+    (score as isize, key, output)
+}
+
+/*
+every key_len-th byte is single byte xor, the score is the sum over those columns
+*/
+pub fn search_xor_key_with<S: scoring::Scorer + ?Sized>(
+    data: &[u8],
+    key_len: usize,
+    scorer: &S,
+) -> (f64, Vec<u8>, Vec<u8>) {
+    search_columns(data, key_len, |column| find_xor_key_with(column, scorer))
+}
+
+fn search_columns<F>(data: &[u8], key_len: usize, find_key: F) -> (f64, Vec<u8>, Vec<u8>)
+where
+    F: Fn(&[u8]) -> (f64, u8, Vec<u8>),
+{
     use std::cmp::max;
     let mut best_guesses = vec![];
     let mut best_key = vec![];
     let mut longest = 0;
-    let mut sum_of_scores = 0;
+    let mut sum_of_scores = 0.0;
 
     for offset in 0..key_len {
         let nth_bytes = data
@@ -364,7 +437,7 @@ pub fn search_xor_key(data: &[u8], key_len: usize) -> (isize, Vec<u8>, Vec<u8>)
             .map(|(_, b)| *b)
             .collect::<Vec<_>>();
 
-        let best_guess = find_xor_key_eng(&nth_bytes);
+        let best_guess = find_key(&nth_bytes);
         longest = max(best_guess.2.len(), longest);
         best_guesses.push(best_guess.2.clone());
         best_key.push(best_guess.1);
@@ -0,0 +1,586 @@
+use num_bigint::{BigInt, BigUint, ToBigInt};
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use crate::numtheory::mod_inv;
+
+/*
+Lattice reduction. Lattices are given as a list of rows, every row is one basis
+vector and the rows must be linearly independent.
+*/
+
+pub fn dot(a: &[BigInt], b: &[BigInt]) -> BigInt {
+    a.iter()
+        .zip(b.iter())
+        .fold(BigInt::zero(), |acc, (x, y)| acc + x * y)
+}
+
+/*
+classic Gram-Schmidt over the rationals: mu[i][j] = <b_i, b*_j> / <b*_j, b*_j>
+(mu[i][i] = 1) and the squared norms <b*_i, b*_i>
+*/
+pub fn gram_schmidt(basis: &[Vec<BigInt>]) -> (Vec<Vec<BigRational>>, Vec<BigRational>) {
+    let (d, lambda) = integral_gram_schmidt(basis);
+    let n = basis.len();
+
+    let mut mu = vec![vec![BigRational::zero(); n]; n];
+    let mut norms = vec![];
+    for i in 0..n {
+        for j in 0..i {
+            mu[i][j] = BigRational::new(lambda[i][j].clone(), d[j + 1].clone());
+        }
+        mu[i][i] = BigRational::one();
+        norms.push(BigRational::new(d[i + 1].clone(), d[i].clone()));
+    }
+    (mu, norms)
+}
+
+/*
+the same information in integers only: d[i] is the Gram determinant of the first
+i rows (d[0] = 1, so |b*_i|^2 = d[i+1] / d[i]) and lambda[i][j] = d[j+1] * mu[i][j]
+*/
+pub fn integral_gram_schmidt(basis: &[Vec<BigInt>]) -> (Vec<BigInt>, Vec<Vec<BigInt>>) {
+    let n = basis.len();
+    let mut d = vec![BigInt::one(); n + 1];
+    let mut lambda = vec![vec![BigInt::zero(); n]; n];
+    for k in 0..n {
+        for j in 0..=k {
+            let mut u = dot(&basis[k], &basis[j]);
+            for i in 0..j {
+                u = (&d[i + 1] * u - &lambda[k][i] * &lambda[j][i]) / &d[i];
+            }
+            if j < k {
+                lambda[k][j] = u;
+            } else {
+                assert!(!u.is_zero(), "basis vectors are linearly dependent");
+                d[k + 1] = u;
+            }
+        }
+    }
+    (d, lambda)
+}
+
+/*
+a basis is LLL reduced when |mu[i][j]| <= 1/2 and the Lovasz condition
+|b*_i|^2 >= (delta - mu[i][i-1]^2) |b*_i-1|^2 holds everywhere
+*/
+pub fn is_lll_reduced(basis: &[Vec<BigInt>], delta: &BigRational) -> bool {
+    let (mu, norms) = gram_schmidt(basis);
+    let half = BigRational::new(1.into(), 2.into());
+    for i in 1..basis.len() {
+        if mu[i].iter().take(i).any(|m| m.abs() > half) {
+            return false;
+        }
+        let m2 = &mu[i][i - 1] * &mu[i][i - 1];
+        if norms[i] < (delta - m2) * &norms[i - 1] {
+            return false;
+        }
+    }
+    true
+}
+
+pub fn lll(basis: &[Vec<BigInt>]) -> Vec<Vec<BigInt>> {
+    lll_with_delta(basis, &BigRational::new(3.into(), 4.into()))
+}
+
+/*
+Exact LLL for any 1/4 < delta <= 1.
+
+This is Cohen's integral LLL (algorithm 2.6.7): instead of the rational
+Gram-Schmidt coefficients mu[k][j] it keeps lambda[k][j] = d[j+1] * mu[k][j]
+and the Gram determinants d, which are all integers, so no fraction ever has
+to be normalised. Indices below follow the book, d[0] = 1 and b_k is basis[k-1].
+*/
+pub fn lll_with_delta(basis: &[Vec<BigInt>], delta: &BigRational) -> Vec<Vec<BigInt>> {
+    let (delta_num, delta_den) = (delta.numer(), delta.denom());
+    let mut b = basis.to_vec();
+    let n = b.len();
+    if n < 2 {
+        return b;
+    }
+
+    let mut d = vec![BigInt::zero(); n + 1];
+    let mut lambda = vec![vec![BigInt::zero(); n + 1]; n + 1];
+    d[0] = BigInt::one();
+    d[1] = dot(&b[0], &b[0]);
+
+    let reduce = |b: &mut Vec<Vec<BigInt>>,
+                  lambda: &mut Vec<Vec<BigInt>>,
+                  d: &[BigInt],
+                  k: usize,
+                  l: usize| {
+        if (&lambda[k][l] << 1_u32).abs() <= d[l] {
+            return;
+        }
+        // nearest integer to lambda / d
+        let q = ((&lambda[k][l] << 1_u32) + &d[l]).div_floor(&(&d[l] << 1_u32));
+        let row_l = b[l - 1].clone();
+        for (x, y) in b[k - 1].iter_mut().zip(row_l.iter()) {
+            *x -= &q * y;
+        }
+        lambda[k][l] -= &q * &d[l];
+        let (upper, lower) = lambda.split_at_mut(k);
+        for (x, y) in lower[0].iter_mut().zip(upper[l].iter()).take(l).skip(1) {
+            *x -= &q * y;
+        }
+    };
+
+    let mut k = 2;
+    let mut k_max = 1;
+    while k <= n {
+        if k > k_max {
+            k_max = k;
+            for j in 1..=k {
+                let mut u = dot(&b[k - 1], &b[j - 1]);
+                for i in 1..j {
+                    u = (&d[i] * u - &lambda[k][i] * &lambda[j][i]) / &d[i - 1];
+                }
+                if j < k {
+                    lambda[k][j] = u;
+                } else {
+                    assert!(!u.is_zero(), "basis vectors are linearly dependent");
+                    d[k] = u;
+                }
+            }
+        }
+
+        loop {
+            reduce(&mut b, &mut lambda, &d, k, k - 1);
+            // d_k d_k-2 >= delta d_k-1^2 - lambda^2, times the denominator of delta
+            let lhs: BigInt = &d[k] * &d[k - 2] * delta_den;
+            let rhs: BigInt = &d[k - 1] * &d[k - 1] * delta_num
+                - (&lambda[k][k - 1] * &lambda[k][k - 1] * delta_den);
+            if lhs >= rhs {
+                break;
+            }
+
+            // swap b_k and b_k-1
+            b.swap(k - 1, k - 2);
+            let (upper, lower) = lambda.split_at_mut(k);
+            for (x, y) in upper[k - 1]
+                .iter_mut()
+                .zip(lower[0].iter_mut())
+                .take(k - 1)
+                .skip(1)
+            {
+                std::mem::swap(x, y);
+            }
+            let lam = lambda[k][k - 1].clone();
+            let big_b = (&d[k - 2] * &d[k] + &lam * &lam) / &d[k - 1];
+            for row in lambda.iter_mut().take(k_max + 1).skip(k + 1) {
+                let t = row[k].clone();
+                row[k] = (&d[k] * &row[k - 1] - &lam * &t) / &d[k - 1];
+                row[k - 1] = (&big_b * &t + &lam * &row[k]) / &d[k];
+            }
+            d[k - 1] = big_b;
+            k = std::cmp::max(2, k - 1);
+        }
+
+        for l in (1..k - 1).rev() {
+            reduce(&mut b, &mut lambda, &d, k, l);
+        }
+        k += 1;
+    }
+    b
+}
+
+/*
+Floating point LLL in the spirit of Schnorr-Euchner: the basis stays exact but
+the Gram-Schmidt data is kept in f64 and recomputed for row k from exact inner
+products whenever k is visited. Much faster for small entries, but it needs the
+inner products to fit in an f64, so when they overflow or the float arithmetic
+stops making progress this falls back to the exact `lll`.
+*/
+pub fn lll_fp(basis: &[Vec<BigInt>]) -> Vec<Vec<BigInt>> {
+    lll_fp_inner(basis).unwrap_or_else(|| lll(basis))
+}
+
+fn lll_fp_inner(basis: &[Vec<BigInt>]) -> Option<Vec<Vec<BigInt>>> {
+    const DELTA: f64 = 0.75;
+    // slightly above 1/2 so rounding noise does not loop forever
+    const ETA: f64 = 0.51;
+
+    let mut b = basis.to_vec();
+    let n = b.len();
+    if n < 2 {
+        return Some(b);
+    }
+
+    let mut mu = vec![vec![0.0_f64; n]; n];
+    let mut r = vec![vec![0.0_f64; n]; n];
+    let mut norms = vec![0.0_f64; n];
+
+    let gso_row = |b: &[Vec<BigInt>],
+                   mu: &mut Vec<Vec<f64>>,
+                   r: &mut Vec<Vec<f64>>,
+                   norms: &mut Vec<f64>,
+                   k: usize|
+     -> Option<()> {
+        for j in 0..=k {
+            let mut v = dot(&b[k], &b[j]).to_f64()?;
+            for i in 0..j {
+                v -= mu[j][i] * r[k][i];
+            }
+            if !v.is_finite() {
+                return None;
+            }
+            if j < k {
+                r[k][j] = v;
+                mu[k][j] = v / norms[j];
+            } else {
+                norms[k] = v;
+            }
+        }
+        Some(())
+    };
+
+    gso_row(&b, &mut mu, &mut r, &mut norms, 0)?;
+    let max_steps = 1000 * n * n;
+    let mut k = 1;
+    let mut steps = 0;
+    while k < n {
+        steps += 1;
+        if steps > max_steps {
+            return None;
+        }
+
+        // size reduce row k until every |mu[k][j]| <= eta
+        let mut rounds = 0;
+        loop {
+            gso_row(&b, &mut mu, &mut r, &mut norms, k)?;
+            if mu[k].iter().take(k).all(|m| m.abs() <= ETA) {
+                break;
+            }
+            rounds += 1;
+            if rounds > 100 {
+                return None;
+            }
+            for j in (0..k).rev() {
+                let q = mu[k][j].round();
+                if q == 0.0 {
+                    continue;
+                }
+                if q.abs() > 1e15 {
+                    return None;
+                }
+                let q_int = BigInt::from(q as i64);
+                let row_j = b[j].clone();
+                for (x, y) in b[k].iter_mut().zip(row_j.iter()) {
+                    *x -= &q_int * y;
+                }
+                let (upper, lower) = mu.split_at_mut(k);
+                for (x, y) in lower[0].iter_mut().zip(upper[j].iter()).take(j) {
+                    *x -= q * y;
+                }
+                mu[k][j] -= q;
+            }
+        }
+
+        let m = mu[k][k - 1];
+        if norms[k] < (DELTA - m * m) * norms[k - 1] {
+            b.swap(k, k - 1);
+            k = std::cmp::max(1, k - 1);
+            if k == 1 {
+                gso_row(&b, &mut mu, &mut r, &mut norms, 0)?;
+            }
+        } else {
+            k += 1;
+        }
+    }
+    Some(b)
+}
+
+/*
+polynomials are coefficient vectors, lowest degree first
+*/
+fn poly_mul(a: &[BigInt], b: &[BigInt]) -> Vec<BigInt> {
+    let mut out = vec![BigInt::zero(); a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            out[i + j] += x * y;
+        }
+    }
+    out
+}
+
+pub fn poly_eval(f: &[BigInt], x: &BigInt) -> BigInt {
+    f.iter().rev().fold(BigInt::zero(), |acc, c| acc * x + c)
+}
+
+fn poly_derivative(f: &[BigInt]) -> Vec<BigInt> {
+    f.iter().enumerate().skip(1).map(|(i, c)| c * i).collect()
+}
+
+fn poly_trim(f: &mut Vec<BigInt>) {
+    while f.len() > 1 && f.last().unwrap().is_zero() {
+        f.pop();
+    }
+}
+
+/*
+integers a in [lo, hi] such that f has a real root in [a, a+1]. Between two
+neighbouring roots of f' the polynomial is monotone, so a bisection per
+monotone piece finds every sign change
+*/
+fn bracket_roots(f: &[BigInt], lo: &BigInt, hi: &BigInt) -> Vec<BigInt> {
+    let mut f = f.to_vec();
+    poly_trim(&mut f);
+    if f.len() < 2 {
+        return vec![];
+    }
+
+    let mut points = vec![lo.clone()];
+    for c in bracket_roots(&poly_derivative(&f), lo, hi) {
+        let next = &c + 1;
+        points.push(c);
+        if &next <= hi {
+            points.push(next);
+        }
+    }
+    points.push(hi.clone());
+    points.sort();
+    points.dedup();
+
+    let mut out = vec![];
+    for pair in points.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let fa = poly_eval(&f, a);
+        let fb = poly_eval(&f, b);
+        if fa.is_zero() {
+            out.push(a.clone());
+            continue;
+        }
+        if fb.is_zero() || fa.signum() == fb.signum() {
+            continue;
+        }
+        // invariant: sign(f(lo)) == sign(fa) != sign(f(hi))
+        let (mut lo, mut hi) = (a.clone(), b.clone());
+        while &hi - &lo > BigInt::one() {
+            let mid: BigInt = (&lo + &hi) >> 1;
+            if poly_eval(&f, &mid).signum() == fa.signum() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        out.push(lo);
+    }
+    if poly_eval(&f, hi).is_zero() {
+        out.push(hi.clone());
+    }
+    out.sort();
+    out.dedup();
+    out
+}
+
+fn integer_roots(f: &[BigInt], lo: &BigInt, hi: &BigInt) -> Vec<BigInt> {
+    let mut roots = vec![];
+    for a in bracket_roots(f, lo, hi) {
+        for r in [a.clone(), a + 1].iter() {
+            if r <= hi && poly_eval(f, r).is_zero() && !roots.contains(r) {
+                roots.push(r.clone());
+            }
+        }
+    }
+    roots
+}
+
+/*
+f reduced mod n and scaled so that the leading coefficient is 1, None when the
+leading coefficient is not invertible mod n
+*/
+pub fn make_monic(f: &[BigInt], n: &BigUint) -> Option<Vec<BigInt>> {
+    let n_int = n.to_bigint().unwrap();
+    let mut reduced = f.iter().map(|c| c.mod_floor(&n_int)).collect::<Vec<_>>();
+    poly_trim(&mut reduced);
+    let lead = reduced.last()?.to_biguint()?;
+    let lead_inv = mod_inv(&lead, n)?.to_bigint().unwrap();
+    Some(
+        reduced
+            .iter()
+            .map(|c| (c * &lead_inv).mod_floor(&n_int))
+            .collect(),
+    )
+}
+
+/*
+Coppersmith / Howgrave-Graham: roots |x| <= x_bound of f mod n.
+The lattice is spanned by x^j n^(m-i) f^i (i < m, j < deg f) and x^j f^m (j < t)
+*/
+pub fn small_roots_with(
+    f: &[BigInt],
+    n: &BigUint,
+    x_bound: &BigUint,
+    m: usize,
+    t: usize,
+) -> Vec<BigInt> {
+    let f = &match make_monic(f, n) {
+        Some(f) => f,
+        None => return vec![],
+    };
+    let d = f.len() - 1;
+    // everything above the constant vanished mod n, there is nothing to solve
+    if d == 0 {
+        return vec![];
+    }
+
+    let n_int = n.to_bigint().unwrap();
+    let x = x_bound.to_bigint().unwrap();
+
+    let mut f_pows = vec![vec![BigInt::one()]];
+    for _ in 0..m {
+        let next = poly_mul(f_pows.last().unwrap(), f);
+        f_pows.push(next);
+    }
+
+    let mut polys = vec![];
+    for (i, f_pow) in f_pows.iter().enumerate().take(m) {
+        let scale = n_int.pow((m - i) as u32);
+        for j in 0..d {
+            let mut g = vec![BigInt::zero(); j];
+            g.extend(f_pow.iter().map(|c| c * &scale));
+            polys.push(g);
+        }
+    }
+    for j in 0..t {
+        let mut g = vec![BigInt::zero(); j];
+        g.extend(f_pows[m].iter().cloned());
+        polys.push(g);
+    }
+
+    // row i holds g_i(x * X)
+    let dim = polys.len();
+    let basis = polys
+        .iter()
+        .map(|g| {
+            let mut row = vec![BigInt::zero(); dim];
+            let mut x_pow = BigInt::one();
+            for (k, c) in g.iter().enumerate() {
+                row[k] = c * &x_pow;
+                x_pow *= &x;
+            }
+            row
+        })
+        .collect::<Vec<_>>();
+
+    let reduced = lll(&basis);
+
+    // undo the scaling of the shortest vector, its polynomial has the root over Z
+    let mut h = vec![];
+    let mut x_pow = BigInt::one();
+    for c in reduced[0].iter() {
+        h.push(c / &x_pow);
+        x_pow *= &x;
+    }
+
+    integer_roots(&h, &-&x, &x)
+        .into_iter()
+        .filter(|r| (poly_eval(f, r) % &n_int).is_zero())
+        .collect()
+}
+
+/*
+raises the multiplicity until roots show up, the lattice grows quadratically so
+this gives up fairly early
+*/
+pub fn small_roots(f: &[BigInt], n: &BigUint, x_bound: &BigUint) -> Vec<BigInt> {
+    for m in 1..=6 {
+        let roots = small_roots_with(f, n, x_bound, m, 1);
+        if !roots.is_empty() {
+            return roots;
+        }
+    }
+    vec![]
+}
+
+#[cfg(test)]
+fn to_lattice(rows: &[&[i64]]) -> Vec<Vec<BigInt>> {
+    rows.iter()
+        .map(|row| row.iter().map(|x| BigInt::from(*x)).collect())
+        .collect()
+}
+
+#[test]
+fn lll_works() {
+    // https://en.wikipedia.org/wiki/Lenstra%E2%80%93Lenstra%E2%80%93Lov%C3%A1sz_lattice_basis_reduction_algorithm#Example
+    let basis = to_lattice(&[&[1, 1, 1], &[-1, 0, 2], &[3, 5, 6]]);
+    let expected = to_lattice(&[&[0, 1, 0], &[1, 0, 1], &[-1, 0, 2]]);
+    assert_eq!(lll(&basis), expected);
+    assert_eq!(lll_fp(&basis), expected);
+
+    // knapsack style lattice with a planted short vector (1, 0, 1, 1, 0, 0)
+    let weights = [366_124_i64, 891_233, 210_712, 755_002, 1_002_113, 432_117];
+    let target = weights[0] + weights[2] + weights[3];
+    let mut rows = vec![];
+    for (i, w) in weights.iter().enumerate() {
+        let mut row = vec![0_i64; weights.len() + 1];
+        row[i] = 2;
+        row[weights.len()] = *w * 1000;
+        rows.push(row);
+    }
+    let mut last = vec![1_i64; weights.len() + 1];
+    last[weights.len()] = target * 1000;
+    rows.push(last);
+    let basis = rows
+        .iter()
+        .map(|row| row.iter().map(|x| BigInt::from(*x)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let delta = BigRational::new(3.into(), 4.into());
+    let reduced = lll(&basis);
+    assert!(is_lll_reduced(&reduced, &delta));
+    let planted = reduced.iter().any(|row| {
+        let bits = row.iter().map(|x| x.abs()).collect::<Vec<_>>();
+        bits == to_lattice(&[&[1, 1, 1, 1, 1, 1, 0]])[0]
+    });
+    assert!(planted);
+
+    let reduced_fp = lll_fp(&basis);
+    assert!(is_lll_reduced(
+        &reduced_fp,
+        &BigRational::new(1.into(), 2.into())
+    ));
+}
+
+#[test]
+fn gram_schmidt_works() {
+    let basis = to_lattice(&[&[3, 1], &[2, 2]]);
+    let (mu, norms) = gram_schmidt(&basis);
+    // b*_1 = (2, 2) - 8/10 (3, 1) = (-2/5, 6/5)
+    assert_eq!(mu[1][0], BigRational::new(4.into(), 5.into()));
+    assert_eq!(norms[0], BigRational::from_integer(10.into()));
+    assert_eq!(norms[1], BigRational::new(8.into(), 5.into()));
+
+    let (d, lambda) = integral_gram_schmidt(&basis);
+    assert_eq!(d, vec![1.into(), 10.into(), 16.into()]);
+    assert_eq!(lambda[1][0], 8.into());
+}
+
+#[test]
+fn small_roots_works() {
+    // stereotyped message: the top of m is known, the low 40 bits are not
+    use crate::primes::{gen_prime_seeded, PrimeKind};
+
+    let p = gen_prime_seeded(128, PrimeKind::Random, 1);
+    let q = gen_prime_seeded(128, PrimeKind::Random, 2);
+    let n = &p * &q;
+    let known = BigUint::from_bytes_be(b"the password is ") << 40_u32;
+    let secret = BigUint::from(0xc0ffee_1337_u64);
+    let c = (&known + &secret).modpow(&3_u32.into(), &n);
+
+    // f(x) = (known + x)^3 - c, not reduced mod n on purpose
+    let k = known.to_bigint().unwrap();
+    let f = vec![
+        &k * &k * &k - c.to_bigint().unwrap(),
+        &k * &k * 3,
+        &k * 3,
+        BigInt::one(),
+    ];
+    let roots = small_roots(&f, &n, &(BigUint::one() << 40_u32));
+    assert_eq!(roots, vec![secret.to_bigint().unwrap()]);
+
+    // 5 + 2n x is just 5 mod n
+    let f = vec![BigInt::from(5), n.to_bigint().unwrap() * 2];
+    assert!(small_roots(&f, &n, &(BigUint::one() << 40_u32)).is_empty());
+}
@@ -1,9 +1,84 @@
 //extern crate cryptopals;
 use std::num::Wrapping;
 
+use rand::{Error, RngCore, SeedableRng};
+
+use crate::gf2::{BitRow, Solver};
+
 const N: u32 = 624;
 const F: Wrapping<u32> = Wrapping(1812433253);
 
+const NN: usize = 312;
+const MM: usize = 156;
+const F_64: u64 = 6364136223846793005;
+const MATRIX_A_64: u64 = 0xB502_6F5A_A966_19E9;
+const UPPER_MASK_64: u64 = 0xFFFF_FFFF_8000_0000;
+const LOWER_MASK_64: u64 = 0x7FFF_FFFF;
+
+/*
+The tempering both variants put on their outputs,
+  y ^= (y >> u) & d, y ^= (y << s) & b, y ^= (y << t) & c, y ^= y >> l
+Every step is undone by repeating it until the shifted-in bits are all known.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Tempering {
+    pub width: u32,
+    pub u: u32,
+    pub d: u64,
+    pub s: u32,
+    pub b: u64,
+    pub t: u32,
+    pub c: u64,
+    pub l: u32,
+}
+
+pub const TEMPERING_32: Tempering = Tempering {
+    width: 32,
+    u: 11,
+    d: 0xFFFF_FFFF,
+    s: 7,
+    b: 0x9D2C_5680,
+    t: 15,
+    c: 0xEFC6_0000,
+    l: 18,
+};
+
+pub const TEMPERING_64: Tempering = Tempering {
+    width: 64,
+    u: 29,
+    d: 0x5555_5555_5555_5555,
+    s: 17,
+    b: 0x71D6_7FFF_EDA6_0000,
+    t: 37,
+    c: 0xFFF7_EEE0_0000_0000,
+    l: 43,
+};
+
+impl Tempering {
+    pub fn temper(&self, y: u64) -> u64 {
+        let mut y = y ^ (y >> self.u) & self.d;
+        y ^= (y << self.s) & self.b;
+        y ^= (y << self.t) & self.c;
+        y ^ (y >> self.l)
+    }
+
+    pub fn untemper(&self, y: u64) -> u64 {
+        let y = self.undo(y, |x| x >> self.l);
+        let y = self.undo(y, |x| (x << self.t) & self.c);
+        let y = self.undo(y, |x| (x << self.s) & self.b);
+        self.undo(y, |x| (x >> self.u) & self.d)
+    }
+
+    // solves x ^ step(x) = y, each round fixes at least one more bit
+    fn undo<S: Fn(u64) -> u64>(&self, y: u64, step: S) -> u64 {
+        let mut x = y;
+        for _ in 0..self.width {
+            x = y ^ step(x);
+        }
+        x
+    }
+}
+
 pub struct Mt19937 {
     state: [Wrapping<u32>; N as usize],
     index: u32,
@@ -36,31 +111,52 @@ impl Mt19937 {
     }
 
     fn temper(&self, num: u32) -> u32 {
-        let mut num = num ^ num >> 11;
-        num ^= num << 7 & 0x9D2C_5680;
-        num ^= num << 15 & 0xEFC6_0000;
-        num ^= num >> 18;
-        num
+        TEMPERING_32.temper(num as u64) as u32
     }
 
     pub fn untemper(&self, num: u32) -> u32 {
-        let mut num = num ^ (num >> 18);
-
-        // num ^= num << 15 & 0xEFC6_0000
-        num ^= num << 15 & 0x2FC6_0000;
-        num ^= num << 15 & 0xC000_0000;
-
-        // num ^= num << 7 & 0x9D2C_5680
-        num ^= num << 7 & 0x0000_1680;
-        num ^= num << 7 & 0x000C_4000;
-        num ^= num << 7 & 0x0D20_0000;
-        num ^= num << 7 & 0x9000_0000;
+        TEMPERING_32.untemper(num as u64) as u32
+    }
 
-        // num ^= num >> 11
-        num ^= num >> 11;
-        num ^= num >> 22;
+    /*
+    a generator that continues after the given outputs, which may start
+    anywhere in the stream. Any beyond the first 624 have to agree with the clone.
+    */
+    pub fn clone_from_outputs(outputs: &[u32]) -> Result<Mt19937, ()> {
+        if outputs.len() < N as usize {
+            return Err(());
+        }
+        let mut state = [Wrapping(0); N as usize];
+        for (x, &output) in state.iter_mut().zip(outputs.iter()) {
+            *x = Wrapping(TEMPERING_32.untemper(output as u64) as u32);
+        }
+        let mut mt = Mt19937::from_state(state, N);
+        for &output in outputs[N as usize..].iter() {
+            if mt.extract_number() != output {
+                return Err(());
+            }
+        }
+        Ok(mt)
+    }
 
-        num
+    /*
+    the same from only the top `bits` bits of each output, as in `output >> (32 - bits)`.
+    Needs a little over 19937 / bits outputs.
+    */
+    pub fn clone_from_truncated(outputs: &[u32], bits: u32) -> Result<Mt19937, ()> {
+        let outputs: Vec<u64> = outputs.iter().map(|&output| output as u64).collect();
+        let window = MT_32.recover_window(&outputs, bits)?;
+        let mut state = [Wrapping(0); N as usize];
+        for (x, &word) in state.iter_mut().zip(window.iter()) {
+            *x = Wrapping(word as u32);
+        }
+        let mut mt = Mt19937::from_state(state, N);
+        for &output in outputs[N as usize..].iter() {
+            if (mt.extract_number() >> (32 - bits)) as u64 != output {
+                return Err(());
+            }
+        }
+        Ok(mt)
     }
 
     fn twist(&mut self) {
@@ -78,6 +174,290 @@ impl Mt19937 {
     }
 }
 
+pub struct Mt19937_64 {
+    state: [u64; NN],
+    index: usize,
+}
+
+impl Mt19937_64 {
+    pub fn from_seed(seed: u64) -> Mt19937_64 {
+        let mut state = [0; NN];
+        state[0] = seed;
+        for i in 1..NN {
+            state[i] = F_64
+                .wrapping_mul(state[i - 1] ^ (state[i - 1] >> 62))
+                .wrapping_add(i as u64);
+        }
+        Mt19937_64 { state, index: NN }
+    }
+
+    pub fn from_state(state: [u64; NN], index: usize) -> Mt19937_64 {
+        Mt19937_64 { state, index }
+    }
+
+    pub fn extract_number(&mut self) -> u64 {
+        if self.index >= NN {
+            self.twist();
+        }
+        let y = self.state[self.index];
+        self.index += 1;
+        TEMPERING_64.temper(y)
+    }
+
+    pub fn untemper(&self, num: u64) -> u64 {
+        TEMPERING_64.untemper(num)
+    }
+
+    /*
+    like Mt19937::clone_from_outputs, with 312 outputs
+    */
+    pub fn clone_from_outputs(outputs: &[u64]) -> Result<Mt19937_64, ()> {
+        if outputs.len() < NN {
+            return Err(());
+        }
+        let mut state = [0; NN];
+        for (x, &output) in state.iter_mut().zip(outputs.iter()) {
+            *x = TEMPERING_64.untemper(output);
+        }
+        let mut mt = Mt19937_64::from_state(state, NN);
+        for &output in outputs[NN..].iter() {
+            if mt.extract_number() != output {
+                return Err(());
+            }
+        }
+        Ok(mt)
+    }
+
+    /*
+    outputs are `output >> (64 - bits)`
+    */
+    pub fn clone_from_truncated(outputs: &[u64], bits: u32) -> Result<Mt19937_64, ()> {
+        let window = MT_64.recover_window(outputs, bits)?;
+        let mut state = [0; NN];
+        state.copy_from_slice(&window);
+        let mut mt = Mt19937_64::from_state(state, NN);
+        for &output in outputs[NN..].iter() {
+            if mt.extract_number() >> (64 - bits) != output {
+                return Err(());
+            }
+        }
+        Ok(mt)
+    }
+
+    fn twist(&mut self) {
+        for i in 0..NN {
+            let x = (self.state[i] & UPPER_MASK_64) | (self.state[(i + 1) % NN] & LOWER_MASK_64);
+            let mut x_a = x >> 1;
+            if x & 1 != 0 {
+                x_a ^= MATRIX_A_64;
+            }
+            self.state[i] = self.state[(i + MM) % NN] ^ x_a;
+        }
+        self.index = 0;
+    }
+}
+
+/*
+So the twisters can stand in for any rng in the crate. Wider values are put
+together from consecutive outputs, low bits first, and bytes come out
+little-endian, the way rand_core does it.
+*/
+impl RngCore for Mt19937 {
+    fn next_u32(&mut self) -> u32 {
+        self.extract_number()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = self.extract_number() as u64;
+        (self.extract_number() as u64) << 32 | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.extract_number().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for Mt19937 {
+    type Seed = [u8; 4];
+
+    fn from_seed(seed: [u8; 4]) -> Mt19937 {
+        Mt19937::from_seed(u32::from_le_bytes(seed))
+    }
+
+    // the plain 32 bit seed, so seed_from_u64(t) is from_seed(t as u32)
+    fn seed_from_u64(seed: u64) -> Mt19937 {
+        Mt19937::from_seed(seed as u32)
+    }
+}
+
+impl RngCore for Mt19937_64 {
+    fn next_u32(&mut self) -> u32 {
+        // the high half
+        (self.extract_number() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.extract_number()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.extract_number().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for Mt19937_64 {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: [u8; 8]) -> Mt19937_64 {
+        Mt19937_64::from_seed(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(seed: u64) -> Mt19937_64 {
+        Mt19937_64::from_seed(seed)
+    }
+}
+
+/*
+What the truncated-output recovery needs to know about a twister. Every state
+word and every output bit is a linear function of the first n words x_0..x_n-1
+of the stream, of which x_0 only ever contributes its upper width - r bits.
+*/
+struct Twister {
+    width: u32,
+    n: usize,
+    m: usize,
+    r: u32,
+    a: u64,
+    tempering: Tempering,
+}
+
+const MT_32: Twister = Twister {
+    width: 32,
+    n: N as usize,
+    m: 397,
+    r: 31,
+    a: 0x9908_B0DF,
+    tempering: TEMPERING_32,
+};
+
+const MT_64: Twister = Twister {
+    width: 64,
+    n: NN,
+    m: MM,
+    r: 31,
+    a: MATRIX_A_64,
+    tempering: TEMPERING_64,
+};
+
+impl Twister {
+    fn vars(&self) -> usize {
+        (self.n * self.width as usize) - self.r as usize
+    }
+
+    // the symbolic word x_k for k < n, bit j of word 0 is a variable only above r
+    fn initial_word(&self, k: usize) -> Vec<BitRow> {
+        let vars = self.vars();
+        (0..self.width as usize)
+            .map(|j| {
+                if k == 0 && j < self.r as usize {
+                    BitRow::zero(vars)
+                } else {
+                    BitRow::unit(vars, k * self.width as usize + j - self.r as usize)
+                }
+            })
+            .collect()
+    }
+
+    // x_k+n from x_k, x_k+1 and x_k+m
+    fn next_word(&self, x_k: &[BitRow], x_k1: &[BitRow], x_km: &[BitRow]) -> Vec<BitRow> {
+        let r = self.r as usize;
+        let y = |j: usize| if j < r { &x_k1[j] } else { &x_k[j] };
+        (0..self.width as usize)
+            .map(|j| {
+                let mut bit = x_km[j].clone();
+                if j + 1 < self.width as usize {
+                    bit ^= y(j + 1);
+                }
+                if self.a >> j & 1 == 1 {
+                    bit ^= y(0);
+                }
+                bit
+            })
+            .collect()
+    }
+
+    /*
+    Feeds the known output bits to a GF(2) solver until the n initial words
+    are pinned down, then hands them back (x_0 with its lower bits zeroed).
+    */
+    fn recover_window(&self, outputs: &[u64], bits: u32) -> Result<Vec<u64>, ()> {
+        if bits == 0 || bits > self.width || outputs.len() < self.n {
+            return Err(());
+        }
+        let width = self.width as usize;
+        // column i of the tempering matrix is temper(1 << i)
+        let columns: Vec<u64> = (0..width).map(|i| self.tempering.temper(1 << i)).collect();
+
+        let mut solver = Solver::new(self.vars());
+        let mut words: Vec<Vec<BitRow>> = Vec::with_capacity(self.n);
+        for (k, &output) in outputs.iter().enumerate() {
+            if solver.is_determined() {
+                break;
+            }
+            let word = if k < self.n {
+                self.initial_word(k)
+            } else {
+                // words[] is a ring holding x_k-n..x_k-1
+                let at = |i: usize| &words[i % self.n];
+                self.next_word(at(k), at(k + 1), at(k + self.m))
+            };
+            // tempering mixes the unknown lower bits of x_0 into output 0
+            let observed = if k == 0 { width } else { width - bits as usize };
+            for j in observed..width {
+                let mut row = BitRow::zero(self.vars());
+                for (i, column) in columns.iter().enumerate() {
+                    if column >> j & 1 == 1 {
+                        row ^= &word[i];
+                    }
+                }
+                let rhs = output >> (j + bits as usize - width) & 1 == 1;
+                solver.add(row, rhs);
+            }
+            if k < self.n {
+                words.push(word);
+            } else {
+                words[k % self.n] = word;
+            }
+        }
+
+        let x = solver.solve().ok_or(())?;
+        Ok((0..self.n)
+            .map(|k| {
+                self.initial_word(k)
+                    .iter()
+                    .enumerate()
+                    .fold(0, |word, (j, bit)| word | (bit.dot(&x) as u64) << j)
+            })
+            .collect())
+    }
+}
+
 #[test]
 fn test_mt19937() {
     use std::collections::HashMap;
@@ -122,3 +502,106 @@ fn test_mt19937() {
     let tempered_2 = mt.temper(0xff112233);
     assert_eq!(0xff112233, mt.untemper(tempered_2));
 }
+
+#[test]
+fn mt19937_64_works() {
+    // std::mt19937_64 with its default seed, the 10000th value is in the C++ standard
+    let mut mt = Mt19937_64::from_seed(5489);
+    assert_eq!(mt.extract_number(), 14514284786278117030);
+    assert_eq!(mt.extract_number(), 4620546740167642908);
+    assert_eq!(mt.extract_number(), 13109570281517897720);
+    let mut last = 0;
+    for _ in 3..10000 {
+        last = mt.extract_number();
+    }
+    assert_eq!(last, 9981545732273789042);
+
+    let mut mt = Mt19937_64::from_seed(0x12345678);
+    assert_eq!(mt.extract_number(), 18108439076987754022);
+    assert_eq!(mt.extract_number(), 221445571366219232);
+    assert_eq!(mt.extract_number(), 13890546767951379290);
+
+    for &y in [0, 42, 0xff11_2233_4455_6677, u64::MAX].iter() {
+        assert_eq!(mt.untemper(TEMPERING_64.temper(y)), y);
+    }
+}
+
+#[test]
+fn clone_from_outputs_works() {
+    // from somewhere in the middle, across a twist
+    let mut mt = Mt19937::from_seed(5489);
+    let outputs: Vec<u32> = (0..2000).map(|_| mt.extract_number()).collect();
+    let mut clone = Mt19937::clone_from_outputs(&outputs[1000..]).unwrap();
+    for _ in 0..1000 {
+        assert_eq!(clone.extract_number(), mt.extract_number());
+    }
+    let mut tampered = outputs[1000..].to_vec();
+    tampered[700] ^= 1;
+    assert!(Mt19937::clone_from_outputs(&tampered).is_err());
+    assert!(Mt19937::clone_from_outputs(&outputs[..623]).is_err());
+
+    let mut mt = Mt19937_64::from_seed(5489);
+    let outputs: Vec<u64> = (0..500).map(|_| mt.extract_number()).collect();
+    let mut clone = Mt19937_64::clone_from_outputs(&outputs[100..]).unwrap();
+    for _ in 0..500 {
+        assert_eq!(clone.extract_number(), mt.extract_number());
+    }
+}
+
+#[test]
+fn clone_from_truncated_works() {
+    let mut mt = Mt19937::from_seed(0x1234_5678);
+    for _ in 0..100 {
+        mt.extract_number();
+    }
+    let outputs: Vec<u32> = (0..2600).map(|_| mt.extract_number() >> 24).collect();
+    let mut clone = Mt19937::clone_from_truncated(&outputs, 8).unwrap();
+    for _ in 0..1000 {
+        assert_eq!(clone.extract_number(), mt.extract_number());
+    }
+    assert!(Mt19937::clone_from_truncated(&outputs[..624], 8).is_err());
+
+    let mut mt = Mt19937_64::from_seed(0x1234_5678);
+    let outputs: Vec<u64> = (0..700).map(|_| mt.extract_number() >> 32).collect();
+    let mut clone = Mt19937_64::clone_from_truncated(&outputs, 32).unwrap();
+    for _ in 0..1000 {
+        assert_eq!(clone.extract_number(), mt.extract_number());
+    }
+}
+
+#[test]
+fn mt19937_rng_works() {
+    use crate::dsa::{DSAParams, NonceStrategy, DSA};
+    use num_bigint::{BigInt, RandBigInt};
+    use num_traits::One;
+
+    let mut mt = Mt19937::from_seed(5489);
+    let mut rng = <Mt19937 as SeedableRng>::seed_from_u64(5489);
+    assert_eq!(rng.next_u32(), mt.extract_number());
+    let low = mt.extract_number() as u64;
+    assert_eq!(rng.next_u64(), (mt.extract_number() as u64) << 32 | low);
+    let mut bytes = [0_u8; 6];
+    rng.fill_bytes(&mut bytes);
+    let first = mt.extract_number().to_le_bytes();
+    let second = mt.extract_number().to_le_bytes();
+    assert_eq!(bytes[..4], first);
+    assert_eq!(bytes[4..], second[..2]);
+    let mut rng = <Mt19937_64 as SeedableRng>::from_seed(5489_u64.to_le_bytes());
+    assert_eq!(rng.next_u64(), 14514284786278117030);
+
+    // a signer that seeds its nonce rng with the time gives its key away
+    let params = DSAParams::cryptopals();
+    let mut dsa = DSA::from_params(&params, &mut rand::thread_rng());
+    dsa.nonce = NonceStrategy::UniformRandom;
+    let now = 1_600_000_000_u64;
+    let m = BigInt::from(0xdead_beef_u32);
+    let (r, s) = dsa.sign_with_rng(&m, &mut Mt19937::seed_from_u64(now - 17));
+
+    let k = (now - 60..=now)
+        .map(|seed| Mt19937::seed_from_u64(seed).gen_bigint_range(&BigInt::one(), &params.q))
+        .find(|k| params.g.modpow(k, &params.p) % &params.q == r)
+        .unwrap();
+    let r_inv = DSA::mod_inv(&r, &params.q).unwrap();
+    let x = ((s * k - DSA::hash(&m)) * r_inv % &params.q + &params.q) % &params.q;
+    assert_eq!(x, dsa.privkey);
+}
@@ -0,0 +1,313 @@
+use num_bigint::{BigInt, BigUint, ToBigInt};
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
+
+/*
+Shared number theory helpers. Everything is iterative, the recursive versions
+that used to live in rsa.rs and dsa.rs ran out of stack on large inputs.
+*/
+
+/*
+(g, x, y) with a*x + b*y = g = gcd(a, b)
+*/
+pub fn egcd(a: &BigInt, b: &BigInt) -> (BigInt, BigInt, BigInt) {
+    let (mut old_r, mut r) = (a.clone(), b.clone());
+    let (mut old_x, mut x) = (BigInt::one(), BigInt::zero());
+    let (mut old_y, mut y) = (BigInt::zero(), BigInt::one());
+    while !r.is_zero() {
+        let q = old_r.div_floor(&r);
+        let next_r = &old_r - &q * &r;
+        old_r = std::mem::replace(&mut r, next_r);
+        let next_x = &old_x - &q * &x;
+        old_x = std::mem::replace(&mut x, next_x);
+        let next_y = &old_y - &q * &y;
+        old_y = std::mem::replace(&mut y, next_y);
+    }
+    if old_r.is_negative() {
+        (-old_r, -old_x, -old_y)
+    } else {
+        (old_r, old_x, old_y)
+    }
+}
+
+/*
+inverse of a mod m for signed values, a may be negative or larger than m
+*/
+pub fn mod_inv_int(a: &BigInt, m: &BigInt) -> Option<BigInt> {
+    let (g, x, _) = egcd(&a.mod_floor(m), m);
+    if !g.is_one() {
+        return None;
+    }
+    Some(x.mod_floor(m))
+}
+
+pub fn mod_inv(a: &BigUint, m: &BigUint) -> Option<BigUint> {
+    let a = a.to_bigint().unwrap();
+    let m = m.to_bigint().unwrap();
+    mod_inv_int(&a, &m).map(|x| x.to_biguint().unwrap())
+}
+
+/*
+(a - b) mod m without underflowing BigUint
+*/
+pub fn mod_sub(a: &BigUint, b: &BigUint, m: &BigUint) -> BigUint {
+    let a = a % m;
+    let b = b % m;
+    if a >= b {
+        a - b
+    } else {
+        m - b + a
+    }
+}
+
+/*
+x such that x = residues[i] mod moduli[i], moduli must be pairwise coprime
+*/
+pub fn crt(residues: &[BigUint], moduli: &[BigUint]) -> Option<BigUint> {
+    let product: BigUint = moduli.iter().product();
+    let mut sum = BigUint::zero();
+    for (r, n) in residues.iter().zip(moduli.iter()) {
+        let m = &product / n;
+        sum += r * &m * mod_inv(&m, n)?;
+    }
+    Some(sum % product)
+}
+
+/*
+floor(n^(1/k)) with Newton's method, starts above the root and walks down
+*/
+pub fn nth_root(n: &BigUint, k: u32) -> BigUint {
+    assert!(k > 0);
+    if n.is_zero() || k == 1 {
+        return n.clone();
+    }
+    let k_big = BigUint::from(k);
+    let bits = n.bits();
+    let mut x = BigUint::one() << bits.div_ceil(k as u64);
+    loop {
+        let y = (&x * (&k_big - 1_u32) + n / x.pow(k - 1)) / &k_big;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/*
+Jacobi symbol (a/n) for odd n, the Legendre symbol when n is prime
+*/
+pub fn jacobi(a: &BigUint, n: &BigUint) -> i8 {
+    assert!(n.is_odd(), "jacobi symbol needs an odd modulus");
+    let mut a = a % n;
+    let mut n = n.clone();
+    let mut result = 1;
+    while !a.is_zero() {
+        let twos = a.trailing_zeros().unwrap_or(0);
+        a >>= twos;
+        let n_mod_8 = (&n % 8_u32).to_u32_digits().first().cloned().unwrap_or(0);
+        if twos % 2 == 1 && (n_mod_8 == 3 || n_mod_8 == 5) {
+            result = -result;
+        }
+        // quadratic reciprocity
+        let a_mod_4 = (&a % 4_u32).to_u32_digits().first().cloned().unwrap_or(0);
+        if a_mod_4 == 3 && n_mod_8 % 4 == 3 {
+            result = -result;
+        }
+        std::mem::swap(&mut a, &mut n);
+        a %= &n;
+    }
+    if n.is_one() {
+        result
+    } else {
+        0
+    }
+}
+
+pub fn legendre(a: &BigUint, p: &BigUint) -> i8 {
+    jacobi(a, p)
+}
+
+/*
+Tonelli-Shanks, x with x^2 = a mod p for an odd prime p
+*/
+pub fn sqrt_mod(a: &BigUint, p: &BigUint) -> Option<BigUint> {
+    let a = a % p;
+    if a.is_zero() {
+        return Some(a);
+    }
+    if legendre(&a, p) != 1 {
+        return None;
+    }
+    let big_1 = BigUint::one();
+    let p_minus_1 = p - &big_1;
+
+    // p - 1 = q * 2^s
+    let s = p_minus_1.trailing_zeros().unwrap_or(0);
+    let q = &p_minus_1 >> s;
+    if s == 1 {
+        return Some(a.modpow(&((p + &big_1) >> 2), p));
+    }
+
+    // any quadratic non-residue
+    let mut z = BigUint::from(2_u32);
+    while legendre(&z, p) != -1 {
+        z += 1_u32;
+    }
+
+    let mut m = s;
+    let mut c = z.modpow(&q, p);
+    let mut t = a.modpow(&q, p);
+    let mut r = a.modpow(&((&q + &big_1) >> 1), p);
+    while !t.is_one() {
+        // least i with t^(2^i) = 1
+        let mut i = 0;
+        let mut t2 = t.clone();
+        while !t2.is_one() {
+            t2 = &t2 * &t2 % p;
+            i += 1;
+            if i == m {
+                return None;
+            }
+        }
+        let b = c.modpow(&(BigUint::one() << (m - i - 1)), p);
+        m = i;
+        c = &b * &b % p;
+        t = t * &c % p;
+        r = r * b % p;
+    }
+    Some(r)
+}
+
+/*
+smallest x <= bound with g^x = h mod modulus, fine for tiny ranges only
+*/
+pub fn discrete_log_brute(
+    g: &BigUint,
+    h: &BigUint,
+    modulus: &BigUint,
+    bound: u64,
+) -> Option<BigUint> {
+    let h = h % modulus;
+    let mut acc = BigUint::one() % modulus;
+    for x in 0..=bound {
+        if acc == h {
+            return Some(BigUint::from(x));
+        }
+        acc = acc * g % modulus;
+    }
+    None
+}
+
+/*
+order of g given the factorisation of a multiple of it (usually the group order)
+as (prime, exponent) pairs
+*/
+pub fn multiplicative_order(
+    g: &BigUint,
+    modulus: &BigUint,
+    order_factors: &[(BigUint, u32)],
+) -> BigUint {
+    let mut order: BigUint = order_factors.iter().map(|(p, e)| p.pow(*e)).product();
+    for (p, e) in order_factors.iter() {
+        for _ in 0..*e {
+            let smaller = &order / p;
+            if g.modpow(&smaller, modulus).is_one() {
+                order = smaller;
+            } else {
+                break;
+            }
+        }
+    }
+    order
+}
+
+#[test]
+fn egcd_and_mod_inv_work() {
+    let a = BigInt::from(240);
+    let b = BigInt::from(46);
+    let (g, x, y) = egcd(&a, &b);
+    assert_eq!(g, BigInt::from(2));
+    assert_eq!(&a * x + &b * y, g);
+
+    assert_eq!(
+        mod_inv(&BigUint::from(17_u32), &BigUint::from(3120_u32)),
+        Some(BigUint::from(2753_u32))
+    );
+    assert_eq!(mod_inv(&BigUint::from(6_u32), &BigUint::from(9_u32)), None);
+    assert_eq!(
+        mod_inv_int(&BigInt::from(-3), &BigInt::from(7)),
+        Some(BigInt::from(2))
+    );
+
+    // deep enough that the old recursive version blew the stack
+    let fib = (0..20_000).fold((BigUint::zero(), BigUint::one()), |(a, b), _| {
+        let next = &a + &b;
+        (b, next)
+    });
+    let inv = mod_inv(&fib.0, &fib.1).unwrap();
+    assert!((inv * &fib.0 % &fib.1).is_one());
+}
+
+#[test]
+fn nth_root_and_crt_work() {
+    let x = BigUint::parse_bytes(b"123456789012345678901234567890", 10).unwrap();
+    for k in 1..8 {
+        assert_eq!(nth_root(&x.pow(k), k), x);
+        assert_eq!(nth_root(&(x.pow(k) - 1_u32), k), &x - 1_u32);
+    }
+
+    let moduli = [3_u32, 5, 7]
+        .iter()
+        .map(|m| BigUint::from(*m))
+        .collect::<Vec<_>>();
+    let residues = [2_u32, 3, 2]
+        .iter()
+        .map(|r| BigUint::from(*r))
+        .collect::<Vec<_>>();
+    assert_eq!(crt(&residues, &moduli), Some(BigUint::from(23_u32)));
+}
+
+#[test]
+fn jacobi_and_sqrt_mod_work() {
+    let big = |x: u64| BigUint::from(x);
+    assert_eq!(jacobi(&big(1001), &big(9907)), -1);
+    assert_eq!(jacobi(&big(19), &big(45)), 1);
+    assert_eq!(jacobi(&big(8), &big(21)), -1);
+    assert_eq!(jacobi(&big(5), &big(21)), 1);
+    assert_eq!(jacobi(&big(3), &big(9)), 0);
+
+    // p = 1 mod 8 goes through the full Tonelli-Shanks loop
+    let p = big(1_000_000_009);
+    for a in [4_u64, 10, 56, 1030].iter() {
+        match sqrt_mod(&big(*a), &p) {
+            Some(r) => assert_eq!(&r * &r % &p, big(*a)),
+            None => assert_eq!(legendre(&big(*a), &p), -1),
+        }
+    }
+    let p = big(17);
+    assert_eq!(sqrt_mod(&big(3), &p), None);
+    let r = sqrt_mod(&big(2), &p).unwrap();
+    assert_eq!(&r * &r % &p, big(2));
+}
+
+#[test]
+fn discrete_log_helpers_work() {
+    let p = BigUint::from(1019_u32);
+    let g = BigUint::from(2_u32);
+    let h = g.modpow(&BigUint::from(777_u32), &p);
+    assert_eq!(
+        discrete_log_brute(&g, &h, &p, 1018),
+        Some(BigUint::from(777_u32))
+    );
+
+    // 1018 = 2 * 509, 4 is a square so its order is 509
+    let factors = [(BigUint::from(2_u32), 1), (BigUint::from(509_u32), 1)];
+    assert_eq!(
+        multiplicative_order(&BigUint::from(4_u32), &p, &factors),
+        BigUint::from(509_u32)
+    );
+    assert_eq!(
+        multiplicative_order(&(&p - 1_u32), &p, &factors),
+        BigUint::from(2_u32)
+    );
+}
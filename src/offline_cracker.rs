@@ -0,0 +1,361 @@
+#![allow(non_snake_case)]
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+
+use crate::md4::md4;
+use crate::sha1::Sha1;
+
+/*
+Offline dictionary attacks. Words come from a wordlist, every word is expanded
+by hashcat-style mangling rules, and a Target tells whether a candidate
+password reproduces a captured transcript. Workers pull words off a shared
+iterator in batches, so a huge wordlist file is never held in memory.
+*/
+
+// words a worker takes from the shared iterator at once
+const BATCH: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    Lowercase,
+    Uppercase,
+    // first letter up, the rest down
+    Capitalize,
+    // every letter flipped
+    ToggleCase,
+    // a@ e3 i1 o0 s5 t7 all at once, like a chain of hashcat s rules
+    Leet,
+    // 0-9, 00-99, ... up to the given number of digits
+    AppendDigits(u32),
+}
+
+pub const DEFAULT_RULES: [Rule; 4] = [
+    Rule::Capitalize,
+    Rule::ToggleCase,
+    Rule::Leet,
+    Rule::AppendDigits(2),
+];
+
+impl Rule {
+    fn apply(&self, word: &str) -> Vec<String> {
+        match self {
+            Rule::Lowercase => vec![word.to_lowercase()],
+            Rule::Uppercase => vec![word.to_uppercase()],
+            Rule::Capitalize => {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => vec![first
+                        .to_uppercase()
+                        .chain(chars.flat_map(|c| c.to_lowercase()))
+                        .collect()],
+                    None => vec![],
+                }
+            }
+            Rule::ToggleCase => vec![word
+                .chars()
+                .flat_map(|c| {
+                    if c.is_lowercase() {
+                        c.to_uppercase().collect::<Vec<_>>()
+                    } else {
+                        c.to_lowercase().collect()
+                    }
+                })
+                .collect()],
+            Rule::Leet => vec![word
+                .chars()
+                .map(|c| match c.to_ascii_lowercase() {
+                    'a' => '@',
+                    'e' => '3',
+                    'i' => '1',
+                    'o' => '0',
+                    's' => '5',
+                    't' => '7',
+                    _ => c,
+                })
+                .collect()],
+            Rule::AppendDigits(max) => {
+                let mut out = vec![];
+                for len in 1..=*max {
+                    for n in 0..10_u64.pow(len) {
+                        out.push(format!("{}{:0width$}", word, n, width = len as usize));
+                    }
+                }
+                out
+            }
+        }
+    }
+}
+
+/*
+the word itself plus whatever the rules make of it, each rule also runs on the
+output of the rules before it
+*/
+pub fn mangle(word: &str, rules: &[Rule]) -> Vec<String> {
+    // the Vec keeps the order, the set answers whether we had it already
+    let mut candidates = vec![word.to_string()];
+    let mut seen: HashSet<String> = candidates.iter().cloned().collect();
+    for rule in rules {
+        let mut more = vec![];
+        for candidate in candidates.iter() {
+            for mangled in rule.apply(candidate) {
+                if seen.insert(mangled.clone()) {
+                    more.push(mangled);
+                }
+            }
+        }
+        candidates.extend(more);
+    }
+    candidates
+}
+
+/*
+one word per line, blank lines and lines that aren't UTF-8 are skipped
+*/
+pub fn wordlist<P: AsRef<Path>>(path: P) -> Result<impl Iterator<Item = String>, ()> {
+    let file = File::open(path).map_err(|_| ())?;
+    Ok(BufReader::new(file)
+        .split(b'\n')
+        .map_while(Result::ok)
+        .filter_map(|line| String::from_utf8(line).ok())
+        .map(|line| line.trim_end_matches('\r').to_string())
+        .filter(|line| !line.is_empty()))
+}
+
+pub trait Target: Sync {
+    fn matches(&self, password: &str) -> bool;
+}
+
+/*
+What the evil weakened-SRP server sees: it picked salt, u and its own secret
+b, the client sent A and HMAC-SHA256(K, salt) computed from its password.
+*/
+#[derive(Clone, Debug)]
+pub struct WeakenedSrpTranscript {
+    pub N: BigUint,
+    pub g: BigUint,
+    pub A: BigUint,
+    pub b: BigUint,
+    pub salt: Vec<u8>,
+    pub u: BigUint,
+    pub hmac: Vec<u8>,
+}
+
+impl Target for WeakenedSrpTranscript {
+    fn matches(&self, password: &str) -> bool {
+        let x = BigUint::from_bytes_be(&Sha256::digest(
+            &[&self.salt[..], password.as_bytes()].concat(),
+        ));
+        let v = self.g.modpow(&x, &self.N);
+        let S = (&self.A * v.modpow(&self.u, &self.N)).modpow(&self.b, &self.N);
+        let K = Sha256::digest(&S.to_bytes_be());
+        Sha256::digest(&[&K[..], &self.salt[..]].concat())[..] == self.hmac[..]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MacHash {
+    Sha1,
+    Md4,
+}
+
+/*
+a secret-prefix MAC H(password | message) as in cryptopals 28 and 30
+*/
+#[derive(Clone, Debug)]
+pub struct KeyedMacTranscript {
+    pub hash: MacHash,
+    pub message: Vec<u8>,
+    pub mac: Vec<u8>,
+}
+
+impl KeyedMacTranscript {
+    pub fn mac(hash: MacHash, key: &[u8], message: &[u8]) -> Vec<u8> {
+        let input = [key, message].concat();
+        match hash {
+            MacHash::Sha1 => Sha1::digest_now(&input),
+            // md4() hands out words that print as the digest in big-endian
+            MacHash::Md4 => md4(input, 0, None)
+                .iter()
+                .flat_map(|word| word.to_be_bytes().to_vec())
+                .collect(),
+        }
+    }
+}
+
+impl Target for KeyedMacTranscript {
+    fn matches(&self, password: &str) -> bool {
+        KeyedMacTranscript::mac(self.hash, password.as_bytes(), &self.message) == self.mac
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub password: Option<String>,
+    pub tried: u64,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /*
+    candidates per second
+    */
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.tried as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/*
+Runs every mangled word against the target on `threads` workers until one
+matches or the words run out. `tried` counts the candidates actually tested.
+*/
+pub fn crack<I, T>(words: I, rules: &[Rule], target: &T, threads: usize) -> Report
+where
+    I: IntoIterator<Item = String>,
+    I::IntoIter: Send,
+    T: Target,
+{
+    let words = Mutex::new(words.into_iter());
+    let found = Mutex::new(None);
+    let done = AtomicBool::new(false);
+    let tried = AtomicU64::new(0);
+    let start = Instant::now();
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let batch: Vec<String> = words.lock().unwrap().by_ref().take(BATCH).collect();
+                    if batch.is_empty() {
+                        return;
+                    }
+                    for word in batch {
+                        for candidate in mangle(&word, rules) {
+                            if done.load(Ordering::Relaxed) {
+                                return;
+                            }
+                            tried.fetch_add(1, Ordering::Relaxed);
+                            if target.matches(&candidate) {
+                                *found.lock().unwrap() = Some(candidate);
+                                done.store(true, Ordering::Relaxed);
+                                return;
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    Report {
+        password: found.into_inner().unwrap(),
+        tried: tried.into_inner(),
+        elapsed: start.elapsed(),
+    }
+}
+
+#[test]
+fn mangle_works() {
+    assert_eq!(mangle("pass", &[Rule::Uppercase]), vec!["pass", "PASS"]);
+    assert_eq!(mangle("Pass", &[Rule::ToggleCase]), vec!["Pass", "pASS"]);
+    assert_eq!(
+        mangle("Äpfelß", &[Rule::ToggleCase]),
+        vec!["Äpfelß", "äPFELSS"]
+    );
+    assert_eq!(mangle("pASS", &[Rule::Capitalize]), vec!["pASS", "Pass"]);
+    assert_eq!(
+        mangle("password", &[Rule::Leet]),
+        vec!["password", "p@55w0rd"]
+    );
+    assert_eq!(
+        mangle("ABC", &[Rule::Lowercase, Rule::Leet]),
+        vec!["ABC", "abc", "@BC", "@bc"]
+    );
+
+    let digits = mangle("x", &[Rule::AppendDigits(2)]);
+    assert_eq!(digits.len(), 1 + 10 + 100);
+    assert_eq!(&digits[..3], &["x", "x0", "x1"]);
+    assert_eq!(digits.last().unwrap(), "x99");
+
+    // no duplicates when rules agree
+    assert_eq!(mangle("123", &DEFAULT_RULES[..3]), vec!["123"]);
+    assert_eq!(mangle("", &DEFAULT_RULES[..3]), vec![""]);
+}
+
+#[test]
+fn offline_cracker_works() {
+    use crate::dh::groups;
+    use std::io::Write;
+
+    assert_eq!(
+        KeyedMacTranscript::mac(MacHash::Md4, b"", b"abc"),
+        crate::hex_to_bytes("a448017aaf21d8525fc10ae87aa6729d")
+    );
+    let words = ["alpha", "beta", "monkey", "password", "zulu"];
+    let list = || words.iter().map(|word| word.to_string());
+
+    // SRP as the weakened server runs it: salt 0, u 1, b 2
+    let (N, g) = (groups::MODP_1536.p(), groups::MODP_1536.g());
+    let a = BigUint::from(123456789_u32);
+    let A = g.modpow(&a, &N);
+    let (salt, u, b) = (vec![0_u8], BigUint::from(1_u32), BigUint::from(2_u32));
+    let B = g.modpow(&b, &N);
+    let x = BigUint::from_bytes_be(&Sha256::digest(&[&salt[..], b"p@55w0rd9"].concat()));
+    let S = B.modpow(&(&a + &u * x), &N);
+    let K = Sha256::digest(&S.to_bytes_be());
+    let hmac = Sha256::digest(&[&K[..], &salt[..]].concat()).to_vec();
+    let srp = WeakenedSrpTranscript {
+        N,
+        g,
+        A,
+        b,
+        salt,
+        u,
+        hmac,
+    };
+
+    // every candidate costs a 1536 bit modpow, keep the rules short
+    let rules = [Rule::Leet, Rule::AppendDigits(1)];
+    let report = crack(list(), &rules, &srp, 4);
+    assert_eq!(report.password, Some("p@55w0rd9".to_string()));
+    assert!(report.tried > 0);
+    assert!(crack(list(), &[], &srp, 4).password.is_none());
+
+    // the same words from a file, against secret-prefix MACs
+    let path = std::env::temp_dir().join(format!("wordlist-{}.txt", std::process::id()));
+    let mut file = File::create(&path).unwrap();
+    file.write_all(b"alpha\r\n\nbeta\nmonkey\n\xff\xfe\nzulu")
+        .unwrap();
+    drop(file);
+    assert_eq!(
+        wordlist(&path).unwrap().collect::<Vec<_>>(),
+        vec!["alpha", "beta", "monkey", "zulu"]
+    );
+    let message = b"comment1=cooking%20MCs;userdata=foo".to_vec();
+    for hash in [MacHash::Sha1, MacHash::Md4].iter() {
+        let mac = KeyedMacTranscript::mac(*hash, b"MONKEY7", &message);
+        let target = KeyedMacTranscript {
+            hash: *hash,
+            message: message.clone(),
+            mac,
+        };
+        let report = crack(wordlist(&path).unwrap(), &DEFAULT_RULES, &target, 2);
+        assert_eq!(report.password, Some("MONKEY7".to_string()));
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert!(wordlist(&path).is_err());
+}
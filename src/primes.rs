@@ -0,0 +1,305 @@
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::{One, Zero};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::OnceLock;
+
+// how far we walk from a random starting point before picking a new one
+const MAX_SIEVE_DELTA: u64 = 1 << 20;
+const TRIAL_DIVISION_LIMIT: u64 = 2048;
+pub const MILLER_RABIN_ROUNDS: usize = 40;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum PrimeKind {
+    // any prime with the top two bits set
+    Random,
+    // p = 2q + 1 where q is also prime
+    Safe,
+    // Gordon's strong prime: p-1, p+1 and r-1 all have a large prime factor
+    Strong,
+}
+
+// sieved once, every primality test goes through these
+pub fn small_primes() -> &'static [u64] {
+    static SMALL_PRIMES: OnceLock<Vec<u64>> = OnceLock::new();
+    SMALL_PRIMES.get_or_init(|| primes_below(TRIAL_DIVISION_LIMIT))
+}
+
+pub fn primes_below(limit: u64) -> Vec<u64> {
+    let limit = limit as usize;
+    if limit < 2 {
+        return vec![];
+    }
+    let mut sieve = vec![true; limit];
+    sieve[0] = false;
+    sieve[1] = false;
+    for i in 2..limit {
+        if sieve[i] {
+            let mut j = i * i;
+            while j < limit {
+                sieve[j] = false;
+                j += i;
+            }
+        }
+    }
+    (0..limit as u64).filter(|&i| sieve[i as usize]).collect()
+}
+
+fn mod_small(n: &BigUint, p: u64) -> u64 {
+    let r = n % p;
+    r.to_u64_digits().first().cloned().unwrap_or(0)
+}
+
+/*
+Miller-Rabin with random bases drawn from rng, n must be odd and > 3
+*/
+pub fn miller_rabin<R: Rng + ?Sized>(n: &BigUint, rounds: usize, rng: &mut R) -> bool {
+    let big_1 = BigUint::one();
+    let big_2 = &big_1 + &big_1;
+    let n_minus_1 = n - &big_1;
+
+    let s = n_minus_1.trailing_zeros().unwrap_or(0);
+    let d = &n_minus_1 >> s;
+
+    'witness: for round in 0..rounds {
+        // always try base 2 first, it weeds out nearly every composite
+        let a = if round == 0 {
+            big_2.clone()
+        } else {
+            rng.gen_biguint_range(&big_2, &n_minus_1)
+        };
+        let mut x = a.modpow(&d, n);
+        if x == big_1 || x == n_minus_1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = x.modpow(&big_2, n);
+            if x == n_minus_1 {
+                continue 'witness;
+            }
+            if x == big_1 {
+                return false;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+pub fn is_probable_prime_with_rng<R: Rng + ?Sized>(
+    n: &BigUint,
+    rounds: usize,
+    rng: &mut R,
+) -> bool {
+    if n < &BigUint::from(2_u32) {
+        return false;
+    }
+    for &p in small_primes() {
+        if n == &BigUint::from(p) {
+            return true;
+        }
+        if mod_small(n, p) == 0 {
+            return false;
+        }
+    }
+    miller_rabin(n, rounds, rng)
+}
+
+pub fn is_probable_prime(n: &BigUint) -> bool {
+    is_probable_prime_with_rng(n, MILLER_RABIN_ROUNDS, &mut rand::thread_rng())
+}
+
+// smallest odd prime >= n
+pub fn next_prime(n: &BigUint) -> BigUint {
+    let mut candidate = n.clone();
+    candidate.set_bit(0, true);
+    while !is_probable_prime(&candidate) {
+        candidate += 2_u32;
+    }
+    candidate
+}
+
+pub fn gen_prime<R: Rng + ?Sized>(bits: usize, kind: PrimeKind, rng: &mut R) -> BigUint {
+    match kind {
+        PrimeKind::Random => gen_random_prime(bits, rng),
+        PrimeKind::Safe => gen_safe_prime(bits, rng),
+        PrimeKind::Strong => gen_strong_prime(bits, rng),
+    }
+}
+
+/*
+same seed gives the same prime, handy for reproducible keys in tests
+*/
+pub fn gen_prime_seeded(bits: usize, kind: PrimeKind, seed: u64) -> BigUint {
+    let mut rng = StdRng::seed_from_u64(seed);
+    gen_prime(bits, kind, &mut rng)
+}
+
+// random odd number of exactly `bits` bits with the top two bits set
+fn random_candidate<R: Rng + ?Sized>(bits: usize, rng: &mut R) -> BigUint {
+    assert!(bits >= 3, "need at least 3 bits for a prime");
+    let mut candidate = rng.gen_biguint(bits as u64);
+    candidate.set_bit(bits as u64 - 1, true);
+    candidate.set_bit(bits as u64 - 2, true);
+    candidate.set_bit(0, true);
+    candidate
+}
+
+/*
+walks candidate, candidate+2, candidate+4 .. keeping residues against the small
+primes up to date so that only survivors of the sieve reach Miller-Rabin.
+`accept` gets the residue of a survivor for every small prime and can reject it
+*/
+fn sieve_walk<R, F>(start: &BigUint, bits: usize, rng: &mut R, accept: F) -> Option<BigUint>
+where
+    R: Rng + ?Sized,
+    F: Fn(&BigUint, &mut R) -> bool,
+{
+    let primes = small_primes();
+    let residues = primes
+        .iter()
+        .map(|p| mod_small(start, *p))
+        .collect::<Vec<_>>();
+
+    let mut delta = 0_u64;
+    while delta < MAX_SIEVE_DELTA {
+        let survives = primes
+            .iter()
+            .zip(residues.iter())
+            .all(|(p, r)| (r + delta) % p != 0);
+        if survives {
+            let candidate = start + delta;
+            if candidate.bits() as usize != bits {
+                return None;
+            }
+            if accept(&candidate, rng) {
+                return Some(candidate);
+            }
+        }
+        delta += 2;
+    }
+    None
+}
+
+fn gen_random_prime<R: Rng + ?Sized>(bits: usize, rng: &mut R) -> BigUint {
+    if bits < 16 {
+        // the sieve would eat the primes themselves, just brute force it
+        loop {
+            let candidate = random_candidate(bits, rng);
+            if is_probable_prime_with_rng(&candidate, MILLER_RABIN_ROUNDS, rng) {
+                return candidate;
+            }
+        }
+    }
+    loop {
+        let start = random_candidate(bits, rng);
+        let found = sieve_walk(&start, bits, rng, |c, rng| {
+            miller_rabin(c, MILLER_RABIN_ROUNDS, rng)
+        });
+        if let Some(p) = found {
+            return p;
+        }
+    }
+}
+
+fn gen_safe_prime<R: Rng + ?Sized>(bits: usize, rng: &mut R) -> BigUint {
+    assert!(bits >= 16, "safe primes need at least 16 bits");
+    let primes = small_primes();
+    loop {
+        // search for q, p = 2q + 1 then also has the top two bits set
+        let start = random_candidate(bits - 1, rng);
+        let found = sieve_walk(&start, bits - 1, rng, |q, rng| {
+            // cheap check that 2q+1 survives the sieve as well
+            let p_survives = primes.iter().all(|p| (2 * mod_small(q, *p) + 1) % p != 0);
+            if !p_survives {
+                return false;
+            }
+            let p = (q << 1) + 1_u32;
+            miller_rabin(&p, 1, rng)
+                && miller_rabin(q, MILLER_RABIN_ROUNDS, rng)
+                && miller_rabin(&p, MILLER_RABIN_ROUNDS, rng)
+        });
+        if let Some(q) = found {
+            return (q << 1) + 1_u32;
+        }
+    }
+}
+
+/*
+Gordon's algorithm:
+1. random primes s and t
+2. r = 2it + 1 prime
+3. p0 = 2(s^(r-2) mod r)s - 1
+4. p = p0 + 2jrs prime
+*/
+fn gen_strong_prime<R: Rng + ?Sized>(bits: usize, rng: &mut R) -> BigUint {
+    assert!(bits >= 128, "strong primes need at least 128 bits");
+    let big_1 = BigUint::one();
+    let big_2 = &big_1 + &big_1;
+
+    let lower = &big_1 << (bits - 1);
+    let upper = &big_1 << bits;
+    loop {
+        let s = gen_random_prime(bits / 2 - 16, rng);
+        let t = gen_random_prime(bits / 2 - 32, rng);
+
+        let mut r = (&t << 1) + &big_1;
+        while !is_probable_prime_with_rng(&r, MILLER_RABIN_ROUNDS, rng) {
+            r += &t << 1;
+        }
+
+        let p0 = (&big_2 * s.modpow(&(&r - &big_2), &r) * &s) - &big_1;
+        let rs2 = (&r * &s) << 1;
+
+        // first j that lands p in the target bit range
+        let j = if p0 >= lower {
+            BigUint::zero()
+        } else {
+            (&lower - &p0 + &rs2 - &big_1) / &rs2
+        };
+        let mut p = p0 + j * &rs2;
+        while p < upper {
+            if is_probable_prime_with_rng(&p, MILLER_RABIN_ROUNDS, rng) {
+                return p;
+            }
+            p += &rs2;
+        }
+    }
+}
+
+#[test]
+fn is_probable_prime_works() {
+    let primes = [
+        2_u64,
+        3,
+        5,
+        7,
+        2047 + 6,
+        7919,
+        1_000_000_007,
+        2_305_843_009_213_693_951,
+    ];
+    for p in primes.iter() {
+        assert!(is_probable_prime(&BigUint::from(*p)), "{} is prime", p);
+    }
+    // 2047 and 3215031751 are strong pseudoprimes to small bases
+    let composites = [0_u64, 1, 4, 2047, 561, 3_215_031_751, 1_000_000_007 * 3];
+    for c in composites.iter() {
+        assert!(!is_probable_prime(&BigUint::from(*c)), "{} is composite", c);
+    }
+}
+
+#[test]
+fn gen_prime_works() {
+    let big_1 = BigUint::one();
+    for kind in [PrimeKind::Random, PrimeKind::Safe, PrimeKind::Strong].iter() {
+        let p = gen_prime_seeded(128, *kind, 1337);
+        assert_eq!(p.bits(), 128);
+        assert!(is_probable_prime(&p));
+        // deterministic for a given seed
+        assert_eq!(p, gen_prime_seeded(128, *kind, 1337));
+        if *kind == PrimeKind::Safe {
+            assert!(is_probable_prime(&((&p - &big_1) >> 1)));
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::thread;
+
+/*
+RC4 and two ways of breaking it: the biases of single keystream bytes, which
+give away a secret encrypted under many different keys (cryptopals 56), and
+Fluhrer, Mantin and Shamir's related-key attack on WEP, where every frame is
+encrypted under its public IV followed by the same root key.
+*/
+
+pub struct RC4 {
+    s: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl RC4 {
+    /*
+    key has to be 1 to 256 bytes long
+    */
+    pub fn new(key: &[u8]) -> RC4 {
+        assert!(!key.is_empty() && key.len() <= 256);
+        let mut s = [0_u8; 256];
+        for (i, x) in s.iter_mut().enumerate() {
+            *x = i as u8;
+        }
+        let mut j = 0_u8;
+        for i in 0..256 {
+            j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+            s.swap(i, j as usize);
+        }
+        RC4 { s, i: 0, j: 0 }
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        self.i = self.i.wrapping_add(1);
+        self.j = self.j.wrapping_add(self.s[self.i as usize]);
+        self.s.swap(self.i as usize, self.j as usize);
+        self.s[self.s[self.i as usize].wrapping_add(self.s[self.j as usize]) as usize]
+    }
+
+    pub fn keystream(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_byte()).collect()
+    }
+
+    /*
+    encrypts or decrypts, it's the same xor
+    */
+    pub fn crypt(&mut self, data: &[u8]) -> Vec<u8> {
+        data.iter().map(|byte| byte ^ self.next_byte()).collect()
+    }
+}
+
+/*
+A keystream byte that takes one value more often than the others, `position`
+counts from 0.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bias {
+    pub position: usize,
+    pub value: u8,
+}
+
+// Mantin and Shamir, the second byte is 0 twice as often as it should be
+pub const Z2: Bias = Bias {
+    position: 1,
+    value: 0,
+};
+
+// AlFardan et al., much weaker, 2^24 samples a byte is about right
+pub const Z16: Bias = Bias {
+    position: 15,
+    value: 240,
+};
+
+pub const Z32: Bias = Bias {
+    position: 31,
+    value: 224,
+};
+
+/*
+how often each ciphertext byte showed up at `position` over `samples` calls
+*/
+pub fn ciphertext_counts<F>(
+    oracle: &F,
+    prefix: &[u8],
+    position: usize,
+    samples: usize,
+    threads: usize,
+) -> Vec<u64>
+where
+    F: Fn(&[u8]) -> Vec<u8> + Sync,
+{
+    let threads = threads.max(1);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                // the first few workers take the remainder
+                let share = samples / threads + (worker < samples % threads) as usize;
+                scope.spawn(move || {
+                    let mut counts = vec![0_u64; 256];
+                    for _ in 0..share {
+                        if let Some(&byte) = oracle(prefix).get(position) {
+                            counts[byte as usize] += 1;
+                        }
+                    }
+                    counts
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .fold(vec![0; 256], |total, counts| {
+                total
+                    .iter()
+                    .zip(counts.iter())
+                    .map(|(a, b)| a + b)
+                    .collect()
+            })
+    })
+}
+
+/*
+Recovers `secret_len` bytes that the oracle encrypts under a fresh key each
+call, right after `offset` bytes of its own and the prefix we hand it. Every
+byte is slid under each bias that can reach it and the votes are added up.
+*/
+pub fn recover_secret<F>(
+    oracle: F,
+    secret_len: usize,
+    offset: usize,
+    biases: &[Bias],
+    samples: usize,
+    threads: usize,
+) -> Result<Vec<u8>, ()>
+where
+    F: Fn(&[u8]) -> Vec<u8> + Sync,
+{
+    let mut secret = vec![];
+    for k in 0..secret_len {
+        let mut votes = vec![0_u64; 256];
+        let mut reached = false;
+        for bias in biases.iter().filter(|bias| bias.position >= offset + k) {
+            let prefix = vec![b'A'; bias.position - offset - k];
+            let counts = ciphertext_counts(&oracle, &prefix, bias.position, samples, threads);
+            for (candidate, vote) in votes.iter_mut().enumerate() {
+                *vote += counts[candidate ^ bias.value as usize];
+            }
+            reached = true;
+        }
+        if !reached {
+            return Err(());
+        }
+        let best = (0..256).max_by_key(|&candidate| votes[candidate]).unwrap();
+        secret.push(best as u8);
+    }
+    Ok(secret)
+}
+
+/*
+WEP puts a 3 byte IV in front of the root key, and the frames start with the
+LLC/SNAP header, so the first keystream byte of every frame is known.
+*/
+pub const SNAP_HEADER: u8 = 0xAA;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WepFrame {
+    pub iv: [u8; 3],
+    pub ciphertext: Vec<u8>,
+}
+
+impl WepFrame {
+    pub fn encrypt(root_key: &[u8], iv: [u8; 3], plaintext: &[u8]) -> WepFrame {
+        WepFrame {
+            iv,
+            ciphertext: RC4::new(&[&iv[..], root_key].concat()).crypt(plaintext),
+        }
+    }
+
+    pub fn decrypt(&self, root_key: &[u8]) -> Vec<u8> {
+        RC4::new(&[&self.iv[..], root_key].concat()).crypt(&self.ciphertext)
+    }
+}
+
+/*
+the classic weak IVs (A + 3, 255, x), 256 of them for every root key byte A
+*/
+pub fn weak_ivs(key_len: usize) -> impl Iterator<Item = [u8; 3]> {
+    (0..key_len).flat_map(|a| (0..=255).map(move |x| [a as u8 + 3, 255, x]))
+}
+
+/*
+The root key, one byte at a time. Run the key schedule as far as the known
+key bytes go, if S[1] = X < A + 3 and X + S[X] = A + 3 the first output is
+usually S[A + 3] right after step A + 3, which gives away the next key byte.
+Any IV that gets there counts, the weak ones get there most often.
+*/
+pub fn fms_attack(frames: &[WepFrame], key_len: usize) -> Result<Vec<u8>, ()> {
+    let mut root_key = vec![];
+    for a in 0..key_len {
+        let mut votes = [0_u32; 256];
+        for frame in frames.iter().filter(|frame| !frame.ciphertext.is_empty()) {
+            let known = [&frame.iv[..], &root_key].concat();
+            let mut s = [0_u8; 256];
+            for (i, x) in s.iter_mut().enumerate() {
+                *x = i as u8;
+            }
+            let mut j = 0_u8;
+            for (i, &k) in known.iter().enumerate() {
+                j = j.wrapping_add(s[i]).wrapping_add(k);
+                s.swap(i, j as usize);
+            }
+            let x = s[1] as usize;
+            if x >= a + 3 || x + s[x] as usize != a + 3 {
+                continue;
+            }
+            let z = frame.ciphertext[0] ^ SNAP_HEADER;
+            let z_at = s.iter().position(|&v| v == z).unwrap() as u8;
+            let guess = z_at.wrapping_sub(j).wrapping_sub(s[a + 3]);
+            votes[guess as usize] += 1;
+        }
+        if votes.iter().all(|&v| v == 0) {
+            return Err(());
+        }
+        let best = (0..256).max_by_key(|&guess| votes[guess]).unwrap();
+        root_key.push(best as u8);
+    }
+    // every frame has to decrypt to a SNAP header
+    if frames.iter().any(|frame| {
+        frame
+            .decrypt(&root_key)
+            .first()
+            .is_some_and(|&b| b != SNAP_HEADER)
+    }) {
+        return Err(());
+    }
+    Ok(root_key)
+}
+
+#[test]
+fn rc4_works() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // RFC 6229, keystream at offsets 0, 16, 240 and 256
+    let vectors: [(&str, [&str; 4]); 2] = [
+        (
+            "0102030405",
+            [
+                "b2396305f03dc027ccc3524a0a1118a8",
+                "6982944f18fc82d589c403a47a0d0919",
+                "28cb1132c96ce286421dcaadb8b69eae",
+                "1cfcf62b03eddb641d77dfcf7f8d8c93",
+            ],
+        ),
+        (
+            "0102030405060708090a0b0c0d0e0f10",
+            [
+                "9ac7cc9a609d1ef7b2932899cde41b97",
+                "5248c4959014126a6e8a84f11d1a9e1c",
+                "065902e4b620f6cc36c8589f66432f2b",
+                "d39d566bc6bce3010768151549f3873f",
+            ],
+        ),
+    ];
+    for (key, expected) in vectors.iter() {
+        let keystream = RC4::new(&crate::hex_to_bytes(key)).keystream(272);
+        for (&offset, block) in [0, 16, 240, 256].iter().zip(expected.iter()) {
+            assert_eq!(crate::bytes_to_hex(&keystream[offset..offset + 16]), *block);
+        }
+    }
+    let mut rc4 = RC4::new(b"Key");
+    let ct = rc4.crypt(b"Plaintext");
+    assert_eq!(crate::bytes_to_hex(&ct), "bbf316e8d940af0ad3");
+    assert_eq!(RC4::new(b"Key").crypt(&ct), b"Plaintext");
+
+    // a fresh key every time, the second keystream byte leans towards 0
+    let secret = b"hi";
+    let keys = std::sync::Mutex::new(StdRng::seed_from_u64(56));
+    let oracle = |prefix: &[u8]| {
+        let key: [u8; 16] = keys.lock().unwrap().gen();
+        RC4::new(&key).crypt(&[prefix, &secret[..]].concat())
+    };
+    assert_eq!(
+        recover_secret(oracle, 2, 0, &[Z2], 20_000, 4),
+        Ok(secret.to_vec())
+    );
+    assert_eq!(recover_secret(oracle, 3, 0, &[Z2], 10, 4), Err(()));
+
+    // WEP-40 against its weak IVs, plus some traffic that doesn't help much
+    let mut rng = StdRng::seed_from_u64(47);
+    let root_key: [u8; 5] = rng.gen();
+    let payload = [SNAP_HEADER, SNAP_HEADER, 0x03, 0, 0, 0, 0x08, 0x00];
+    let mut frames: Vec<WepFrame> = weak_ivs(5)
+        .map(|iv| WepFrame::encrypt(&root_key, iv, &payload))
+        .collect();
+    frames.extend((0..1000).map(|_| WepFrame::encrypt(&root_key, rng.gen(), &payload)));
+    assert_eq!(fms_attack(&frames, 5), Ok(root_key.to_vec()));
+    assert_eq!(frames[0].decrypt(&root_key), payload);
+    assert!(fms_attack(&frames[1280..], 5).is_err());
+}
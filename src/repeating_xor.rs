@@ -0,0 +1,259 @@
+use crate::scoring::{score_or_worst, Scorer};
+use crate::{search_xor_key_with, xor_arrays};
+
+/*
+Breaking repeating-key xor without being told the key size. Sizes are ranked
+by how close consecutive key-size blocks are in Hamming distance and by how
+often the bytes within a column coincide. For the best few, every column is
+solved as single byte xor and the key is then tuned byte by byte against the
+scorer on the whole plaintext, which is where n-gram models pay off.
+*/
+
+// passes over the key while tuning, each tries every value of every byte
+const MAX_ROUNDS: usize = 3;
+
+pub fn hamming_distance(a: &[u8], b: &[u8]) -> u32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum()
+}
+
+/*
+the Hamming distance of consecutive key_size blocks per bit of key, averaged
+over all the pairs there are, lower is more likely
+*/
+pub fn normalized_hamming(data: &[u8], key_size: usize) -> f64 {
+    let blocks: Vec<&[u8]> = data.chunks_exact(key_size).collect();
+    if blocks.len() < 2 {
+        return f64::INFINITY;
+    }
+    let total: u32 = blocks
+        .windows(2)
+        .map(|pair| hamming_distance(pair[0], pair[1]))
+        .sum();
+    total as f64 / ((blocks.len() - 1) * key_size) as f64
+}
+
+/*
+the chance that two bytes of the same column are equal, averaged over the
+columns. Text stays well above the 1/256 of random bytes under the right size.
+*/
+pub fn index_of_coincidence(data: &[u8], key_size: usize) -> f64 {
+    let mut total = 0.0;
+    for column in 0..key_size {
+        let mut counts = [0_u64; 256];
+        let mut n = 0;
+        for &b in data.iter().skip(column).step_by(key_size) {
+            counts[b as usize] += 1;
+            n += 1;
+        }
+        if n < 2 {
+            return 0.0;
+        }
+        let pairs: u64 = counts.iter().map(|c| c * c.saturating_sub(1)).sum();
+        total += pairs as f64 / (n * (n - 1)) as f64;
+    }
+    total / key_size as f64
+}
+
+/*
+key sizes 1..=max_key_size, best first, taking turns between the two rankings
+and keeping `top` of each
+*/
+pub fn candidate_key_sizes(data: &[u8], max_key_size: usize, top: usize) -> Vec<usize> {
+    let mut by_hamming: Vec<(usize, f64)> = (1..=max_key_size)
+        .map(|size| (size, normalized_hamming(data, size)))
+        .collect();
+    by_hamming.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let mut by_coincidence: Vec<(usize, f64)> = (1..=max_key_size)
+        .map(|size| (size, index_of_coincidence(data, size)))
+        .collect();
+    by_coincidence.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let mut sizes = vec![];
+    for (&(a, _), &(b, _)) in by_hamming.iter().zip(by_coincidence.iter()).take(top) {
+        for size in [a, b].iter() {
+            if !sizes.contains(size) {
+                sizes.push(*size);
+            }
+        }
+    }
+    sizes
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub key: Vec<u8>,
+    pub plaintext: Vec<u8>,
+    pub score: f64,
+}
+
+/*
+What the user already knows: key bytes by their index in the key, and
+plaintext fragments by their offset in the plaintext.
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pins {
+    pub key_bytes: Vec<(usize, u8)>,
+    pub plaintext: Vec<(usize, Vec<u8>)>,
+}
+
+impl Pins {
+    /*
+    the key bytes the pins fix for this key size, Err if they disagree
+    */
+    fn key(&self, data: &[u8], key_size: usize) -> Result<Vec<Option<u8>>, ()> {
+        let mut key = vec![None; key_size];
+        let mut pin = |index: usize, value: u8| match key[index] {
+            Some(pinned) if pinned != value => Err(()),
+            _ => {
+                key[index] = Some(value);
+                Ok(())
+            }
+        };
+        for &(index, value) in self.key_bytes.iter() {
+            if index >= key_size {
+                return Err(());
+            }
+            pin(index, value)?;
+        }
+        for (offset, fragment) in self.plaintext.iter() {
+            for (i, &p) in fragment.iter().enumerate() {
+                let c = data.get(offset + i).ok_or(())?;
+                pin((offset + i) % key_size, c ^ p)?;
+            }
+        }
+        Ok(key)
+    }
+}
+
+// a key that repeats itself is really the shorter one
+fn shortest_period(key: &[u8]) -> Vec<u8> {
+    let period = (1..key.len())
+        .find(|&p| key.len() % p == 0 && key.iter().zip(key[p..].iter()).all(|(a, b)| a == b))
+        .unwrap_or(key.len());
+    key[..period].to_vec()
+}
+
+/*
+The best key of one size that agrees with the pins. Columns are solved on
+their own first, then every byte that isn't pinned is tuned on the whole text.
+*/
+pub fn solve_key_size<S: Scorer + ?Sized>(
+    data: &[u8],
+    key_size: usize,
+    pins: &Pins,
+    scorer: &S,
+) -> Result<Candidate, ()> {
+    if key_size == 0 || key_size > data.len() {
+        return Err(());
+    }
+    let pinned = pins.key(data, key_size)?;
+    let (_, mut key, _) = search_xor_key_with(data, key_size, scorer);
+    for (byte, pin) in key.iter_mut().zip(pinned.iter()) {
+        if let Some(value) = pin {
+            *byte = *value;
+        }
+    }
+
+    let mut best = score_or_worst(scorer.score(&xor_arrays(data, &key)));
+    for _ in 0..MAX_ROUNDS {
+        let mut improved = false;
+        for i in (0..key_size).filter(|&i| pinned[i].is_none()) {
+            let mut kept = key[i];
+            for value in 0..=255_u8 {
+                key[i] = value;
+                let score = score_or_worst(scorer.score(&xor_arrays(data, &key)));
+                if score > best {
+                    best = score;
+                    kept = value;
+                    improved = true;
+                }
+            }
+            key[i] = kept;
+        }
+        if !improved {
+            break;
+        }
+    }
+    let key = shortest_period(&key);
+    Ok(Candidate {
+        plaintext: xor_arrays(data, &key),
+        key,
+        score: best,
+    })
+}
+
+/*
+Every key size the rankings suggest, solved, best candidate first. Sizes that
+turn out to repeat a shorter key only show up once.
+*/
+pub fn break_repeating_xor<S: Scorer + ?Sized>(
+    data: &[u8],
+    max_key_size: usize,
+    top: usize,
+    pins: &Pins,
+    scorer: &S,
+) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = vec![];
+    for key_size in candidate_key_sizes(data, max_key_size.min(data.len()), top) {
+        if let Ok(candidate) = solve_key_size(data, key_size, pins, scorer) {
+            if !candidates.iter().any(|seen| seen.key == candidate.key) {
+                candidates.push(candidate);
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates
+}
+
+#[test]
+fn repeating_xor_works() {
+    use crate::scoring::ChiSquared;
+
+    assert_eq!(hamming_distance(b"this is a test", b"wokka wokka!!!"), 37);
+
+    let text = b"Now, what I want is, Facts. Teach these boys and girls nothing but Facts. \
+        Facts alone are wanted in life. Plant nothing else, and root out everything else. \
+        You can only form the minds of reasoning animals upon Facts: nothing else will \
+        ever be of any service to them. This is the principle on which I bring up my own \
+        children, and this is the principle on which I bring up these children. Stick to \
+        Facts, sir!";
+    let key = b"Dickens";
+    let ct = xor_arrays(text, key);
+    let sizes = candidate_key_sizes(&ct, 20, 3);
+    assert!(sizes[..2].contains(&7));
+    assert!(index_of_coincidence(&ct, 7) > 2.0 * index_of_coincidence(&ct, 5));
+
+    let english = ChiSquared::english();
+    let candidates = break_repeating_xor(&ct, 20, 3, &Pins::default(), &english);
+    assert_eq!(candidates[0].key, key);
+    assert_eq!(candidates[0].plaintext, &text[..]);
+    // 14 is just 7 twice
+    assert_eq!(
+        candidates.iter().filter(|c| c.key.len() % 7 == 0).count(),
+        1
+    );
+
+    // too little text for the statistics, until we know how it starts
+    let short = &text[..60];
+    let ct = xor_arrays(short, b"a longer key!");
+    let guess = solve_key_size(&ct, 13, &Pins::default(), &english).unwrap();
+    assert_ne!(guess.plaintext, short);
+    let pins = Pins {
+        key_bytes: vec![(10, b'e'), (12, b'!')],
+        plaintext: vec![(0, b"Now, what".to_vec())],
+    };
+    let pinned = solve_key_size(&ct, 13, &pins, &english).unwrap();
+    assert_eq!(pinned.key, b"a longer key!");
+    assert_eq!(pinned.plaintext, short);
+    let conflicting = Pins {
+        key_bytes: vec![(0, b'b')],
+        ..pins
+    };
+    assert!(solve_key_size(&ct, 13, &conflicting, &english).is_err());
+
+    // a scorer that has no opinion mustn't bring the breaker down
+    let nan = |_: &[u8]| f64::NAN;
+    assert!(!break_repeating_xor(&ct, 20, 2, &Pins::default(), &nan).is_empty());
+}
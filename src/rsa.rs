@@ -1,10 +1,11 @@
-extern crate openssl;
-
 use rand::prelude::*;
 
-use num_bigint::{BigInt, BigUint, RandBigInt, ToBigInt, ToBigUint};
+use num_bigint::{BigUint, ToBigUint};
 use sha2::{Digest, Sha256};
 
+use crate::numtheory;
+use crate::primes::{gen_prime, PrimeKind};
+
 pub struct RSA {
     pub e: BigUint,
     pub n: BigUint,
@@ -15,37 +16,52 @@ pub struct RSA {
 
 impl RSA {
     pub fn new(bits: i32) -> RSA {
-        let big_1 = &1.to_biguint().unwrap();
+        RSA::new_with_rng(bits, &mut rand::thread_rng())
+    }
 
+    /*
+    seeding the rng makes key generation reproducible, p and q are safe primes
+    as they always were
+    */
+    pub fn new_with_rng<R: Rng + ?Sized>(bits: i32, rng: &mut R) -> RSA {
         let e = &3.to_biguint().unwrap();
         loop {
-            let p = &RSA::gen_big_prime(bits);
-            let q = &RSA::gen_big_prime(bits);
-            let n = &(p * q);
-            let et = ((p - big_1) * (q - big_1)) % n;
-            match RSA::mod_inv(&e, &et) {
-                Err(_) => continue,
-                Ok(d) => {
-                    return RSA {
-                        e: e.to_owned(),
-                        n: n.to_owned(),
-                        d: d.to_owned(),
-                        pubkey: (e.clone(), n.clone()),
-                        privkey: (d.clone(), n.clone()),
-                    }
-                }
-            };
+            let p = &gen_prime(bits as usize, PrimeKind::Safe, rng);
+            let q = &gen_prime(bits as usize, PrimeKind::Safe, rng);
+            if let Ok(rsa) = RSA::from_primes(p, q, e) {
+                return rsa;
+            }
+        }
+    }
+
+    pub fn from_primes(p: &BigUint, q: &BigUint, e: &BigUint) -> Result<RSA, ()> {
+        let big_1 = &1.to_biguint().unwrap();
+        let et = (p - big_1) * (q - big_1);
+        let d = RSA::mod_inv(e, &et)?;
+        Ok(RSA::from_private(e, &d, &(p * q)))
+    }
+
+    pub fn from_private(e: &BigUint, d: &BigUint, n: &BigUint) -> RSA {
+        RSA {
+            e: e.clone(),
+            n: n.clone(),
+            d: d.clone(),
+            pubkey: (e.clone(), n.clone()),
+            privkey: (d.clone(), n.clone()),
         }
     }
 
     pub fn pad_pkcs_1_5(&self, m: &[u8]) -> Vec<u8> {
+        self.pad_pkcs_1_5_with_rng(m, &mut rand::thread_rng())
+    }
+
+    pub fn pad_pkcs_1_5_with_rng<R: Rng + ?Sized>(&self, m: &[u8], rng: &mut R) -> Vec<u8> {
         let m_b = m.len();
         let k = self.n.to_bytes_be().len();
         let ps_len = k - 3 - m_b;
         assert!(m_b <= k - 11);
 
         let mut random_bytes = vec![];
-        let mut rng = rand::thread_rng();
 
         // correct way to get unbiased random bytes
         while random_bytes.len() < ps_len {
@@ -97,65 +113,14 @@ impl RSA {
     }
 
     pub fn gen_big_prime(bits: i32) -> BigUint {
-        use openssl::bn::BigNum;
-        let mut big = BigNum::new().unwrap();
-
-        big.generate_prime(bits, true, None, None).unwrap();
-        let prime_bytes = big.to_vec();
-        BigUint::from_bytes_be(&prime_bytes)
-    }
-
-    /*
-    unusded
-    alternative to egcd
-    */
-    fn _xgcd(a: &BigInt, b: &BigInt) -> (BigInt, BigInt, BigInt) {
-        let (mut x0, mut x1, mut y0, mut y1) = (
-            0.to_bigint().unwrap(),
-            1.to_bigint().unwrap(),
-            1.to_bigint().unwrap(),
-            0.to_bigint().unwrap(),
-        );
-
-        let mut a = a.clone();
-        let mut b = b.clone();
-
-        while a != 0.to_bigint().unwrap() {
-            let (q, _a) = (&b / &a, &b % &a);
-            b = a;
-            a = _a;
-            let (_y0, _y1) = (&y1, &y0 - (&q * &y1));
-            y0 = _y0.clone();
-            y1 = _y1;
-            let (_x0, _x1) = (&x1, &x0 - (&q * &x1));
-            x0 = _x0.clone();
-            x1 = _x1;
-        }
-        (b, x0, y0)
+        RSA::gen_big_prime_with_rng(bits, &mut rand::thread_rng())
     }
 
-    fn egcd(a: &BigInt, b: &BigInt) -> (BigInt, BigInt, BigInt) {
-        if a == &0.to_bigint().unwrap() {
-            return (b.clone(), 0.to_bigint().unwrap(), 1.to_bigint().unwrap());
-        }
-        let (b_div_a, b_mod_a) = (b / a, b % a);
-        let (g, x, y) = RSA::egcd(&b_mod_a, a);
-        (g, y - (b_div_a * &x), x)
+    pub fn gen_big_prime_with_rng<R: Rng + ?Sized>(bits: i32, rng: &mut R) -> BigUint {
+        gen_prime(bits as usize, PrimeKind::Safe, rng)
     }
 
-    // https://rosettacode.org/wiki/Modular_inverse#Rust
     pub fn mod_inv(a: &BigUint, b: &BigUint) -> Result<BigUint, ()> {
-        let a = a.clone().to_bigint().unwrap();
-        let b = b.clone().to_bigint().unwrap();
-        let (g, x, _) = RSA::egcd(&a, &b);
-        //println!(">> g:{} x:{}", g, x);
-        if g != 1.to_bigint().unwrap() {
-            return Err(());
-        }
-        if x < 0.to_bigint().unwrap() {
-            Ok(((&b + x) % &b).to_biguint().unwrap())
-        } else {
-            Ok((x % b).to_biguint().unwrap())
-        }
+        numtheory::mod_inv(a, b).ok_or(())
     }
 }
@@ -0,0 +1,145 @@
+use num_bigint::{BigInt, BigUint, ToBigInt};
+use num_traits::{One, ToPrimitive, Zero};
+
+use crate::lll::small_roots;
+use crate::numtheory::{crt, mod_inv, nth_root};
+#[cfg(test)]
+use crate::rsa::RSA;
+
+// the polynomial and lattice grow with e, past this it isn't "small" any more
+pub const MAX_E: u32 = 64;
+
+fn small_e(e: &BigUint) -> Option<u32> {
+    e.to_u32().filter(|e| (1..=MAX_E).contains(e))
+}
+
+/*
+the same message sent to e recipients with exponent e and no padding: CRT gives
+m^e over the integers, take the e-th root. `cts` are (ciphertext, (e, n)) pairs
+*/
+pub fn broadcast_attack(cts: &[(BigUint, (BigUint, BigUint))]) -> Option<BigUint> {
+    let e = &(cts.first()?.1).0;
+    if cts.iter().any(|(_, (other_e, _))| other_e != e) {
+        return None;
+    }
+    let e_u32 = small_e(e)?;
+    if cts.len() < e_u32 as usize {
+        return None;
+    }
+
+    let residues = cts.iter().map(|(c, _)| c.clone()).collect::<Vec<_>>();
+    let moduli = cts.iter().map(|(_, (_, n))| n.clone()).collect::<Vec<_>>();
+    let combined = crt(&residues, &moduli)?;
+
+    let m = nth_root(&combined, e_u32);
+    if m.pow(e_u32) == combined {
+        Some(m)
+    } else {
+        None
+    }
+}
+
+pub struct PaddedBroadcast {
+    pub c: BigUint,
+    pub pubkey: (BigUint, BigUint),
+    // what got encrypted is a * m + b
+    pub a: BigUint,
+    pub b: BigUint,
+}
+
+/*
+Hastad with known linear padding: every (a_i * x + b_i)^e - c_i vanishes at m
+mod n_i, CRT glues them into one polynomial mod prod(n_i) and Coppersmith
+finds the small root. `m_bits` bounds the size of the message
+*/
+pub fn hastad_attack(cts: &[PaddedBroadcast], m_bits: usize) -> Option<BigUint> {
+    let e = &cts.first()?.pubkey.0;
+    if cts.iter().any(|ct| &ct.pubkey.0 != e) {
+        return None;
+    }
+    let e_u32 = small_e(e)?;
+    let e_usize = e_u32 as usize;
+
+    let moduli = cts.iter().map(|ct| ct.pubkey.1.clone()).collect::<Vec<_>>();
+    let n: BigUint = moduli.iter().product();
+
+    // sum of T_i * g_i(x) where T_i = 1 mod n_i and 0 mod the others
+    let mut coeffs = vec![BigUint::zero(); e_usize + 1];
+    for ct in cts.iter() {
+        let n_i = &ct.pubkey.1;
+        let others = &n / n_i;
+        let t_i = &others * mod_inv(&others, n_i)?;
+
+        // binomial expansion of (a x + b)^e
+        let mut binomial = BigUint::one();
+        for (k, coeff) in coeffs.iter_mut().enumerate() {
+            let term = &binomial * ct.a.pow(k as u32) * ct.b.pow(e_u32 - k as u32);
+            *coeff = (&*coeff + &t_i * term) % &n;
+            binomial = binomial * (e_usize - k) / (k + 1);
+        }
+        coeffs[0] = (&coeffs[0] + &n - (&t_i * &ct.c) % &n) % &n;
+    }
+
+    let coeffs = coeffs
+        .iter()
+        .map(|c| c.to_bigint().unwrap())
+        .collect::<Vec<BigInt>>();
+
+    let bound = BigUint::one() << m_bits;
+    small_roots(&coeffs, &n, &bound)
+        .into_iter()
+        .filter(|r| r >= &BigInt::zero())
+        .map(|r| r.to_biguint().unwrap())
+        .next()
+}
+
+#[test]
+fn broadcast_attacks_work() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(40);
+    let m = BigUint::from_bytes_be(b"ATTACK AT DAWN!!");
+    let rsas = (0..3)
+        .map(|_| RSA::new_with_rng(128, &mut rng))
+        .collect::<Vec<_>>();
+
+    let cts = rsas
+        .iter()
+        .map(|rsa| (rsa.encrypt(&m), rsa.pubkey.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(broadcast_attack(&cts), Some(m.clone()));
+    // only the low limb of this one is 3
+    let big_e: BigUint = (BigUint::one() << 32_u32) + 3_u32;
+    let wide = cts
+        .iter()
+        .map(|(c, (_, n))| (c.clone(), (big_e.clone(), n.clone())))
+        .collect::<Vec<_>>();
+    assert_eq!(broadcast_attack(&wide), None);
+
+    let padded = rsas
+        .iter()
+        .enumerate()
+        .map(|(i, rsa)| {
+            let a = BigUint::from(i as u32 + 2);
+            let b = BigUint::one() << (200 + i);
+            PaddedBroadcast {
+                c: rsa.encrypt(&(&a * &m + &b)),
+                pubkey: rsa.pubkey.clone(),
+                a,
+                b,
+            }
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(hastad_attack(&padded, 8 * 16), Some(m));
+    let e_65537 = padded
+        .iter()
+        .map(|ct| PaddedBroadcast {
+            pubkey: (BigUint::from(65537_u32), ct.pubkey.1.clone()),
+            c: ct.c.clone(),
+            a: ct.a.clone(),
+            b: ct.b.clone(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(hastad_attack(&e_65537, 8 * 16), None);
+}
@@ -0,0 +1,218 @@
+use num_bigint::{BigInt, BigUint, RandBigInt, ToBigInt};
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+use rand::Rng;
+
+use crate::numtheory::mod_inv;
+#[cfg(test)]
+use crate::rsa::RSA;
+
+/*
+Generic solvers for RSA decryption oracles. Public keys are (e, n) tuples like
+`RSA::pubkey`, the oracles are plain closures so they can wrap anything from a
+local `RSA` to a network service.
+*/
+
+/*
+unpadded message recovery: the oracle decrypts anything except the ciphertext we
+care about, so ask for s^e * c instead and divide s back out
+*/
+pub fn unpadded_recovery<F>(pubkey: &(BigUint, BigUint), ct: &BigUint, oracle: F) -> Option<BigUint>
+where
+    F: FnMut(&BigUint) -> Option<BigUint>,
+{
+    unpadded_recovery_with_rng(pubkey, ct, oracle, &mut rand::thread_rng())
+}
+
+pub fn unpadded_recovery_with_rng<F, R>(
+    pubkey: &(BigUint, BigUint),
+    ct: &BigUint,
+    mut oracle: F,
+    rng: &mut R,
+) -> Option<BigUint>
+where
+    F: FnMut(&BigUint) -> Option<BigUint>,
+    R: Rng + ?Sized,
+{
+    let (e, n) = pubkey;
+    // every s^e * 0 is 0 again, but then so is the plaintext
+    if ct.is_zero() {
+        return Some(BigUint::zero());
+    }
+    let big_2 = BigUint::from(2_u32);
+    loop {
+        let s = rng.gen_biguint_range(&big_2, n);
+        let s_inv = match mod_inv(&s, n) {
+            Some(s_inv) => s_inv,
+            None => continue,
+        };
+        let ct_prime = s.modpow(e, n) * ct % n;
+        if &ct_prime == ct {
+            continue;
+        }
+        let pt_prime = oracle(&ct_prime)?;
+        return Some(pt_prime * s_inv % n);
+    }
+}
+
+/*
+the oracle leaks the low `bits` bits of the plaintext. Multiplying the plaintext
+by 2^bits and reducing mod n subtracts j*n for some j < 2^bits, and since n is
+odd the leaked low bits of -j*n pin down j. Every query shrinks the interval
+[n*a/2^(k*bits), n*(a+1)/2^(k*bits)] by a factor 2^bits, the bounds are kept as
+exact rationals so nothing is lost to integer division along the way.
+`progress` sees the bounds after every query
+*/
+pub fn low_bits_oracle_attack<F, P>(
+    pubkey: &(BigUint, BigUint),
+    ct: &BigUint,
+    bits: usize,
+    mut oracle: F,
+    mut progress: P,
+) -> BigUint
+where
+    F: FnMut(&BigUint) -> BigUint,
+    P: FnMut(&BigRational, &BigRational),
+{
+    let (e, n) = pubkey;
+    let n_int = n.to_bigint().unwrap();
+    let modulus = BigUint::one() << bits;
+    let modulus_int = modulus.to_bigint().unwrap();
+    // j = -leak * n^-1 mod 2^bits
+    let n_inv = mod_inv(n, &modulus).unwrap();
+    let factor = modulus.modpow(e, n);
+
+    let mut a = BigInt::zero();
+    let mut denominator = BigInt::one();
+    let mut candidate = ct.clone();
+    while denominator < n_int {
+        candidate = candidate * &factor % n;
+        let leak = oracle(&candidate) % &modulus;
+        let j = (&modulus - leak) * &n_inv % &modulus;
+
+        a = a * &modulus_int + j.to_bigint().unwrap();
+        denominator *= &modulus_int;
+
+        let lower = BigRational::new(&n_int * &a, denominator.clone());
+        let upper = BigRational::new(&n_int * (&a + 1), denominator.clone());
+        progress(&lower, &upper);
+    }
+    // the interval is now narrower than 1, its only integer is the plaintext
+    (&n_int * &a).div_ceil(&denominator).to_biguint().unwrap()
+}
+
+/*
+parity oracle, the one bit version of the above
+*/
+pub fn parity_oracle_attack<F, P>(
+    pubkey: &(BigUint, BigUint),
+    ct: &BigUint,
+    mut is_odd: F,
+    progress: P,
+) -> BigUint
+where
+    F: FnMut(&BigUint) -> bool,
+    P: FnMut(&BigRational, &BigRational),
+{
+    low_bits_oracle_attack(pubkey, ct, 1, |c| BigUint::from(is_odd(c) as u32), progress)
+}
+
+/*
+the oracle tells the least significant byte of the plaintext, 8 bits per query
+*/
+pub fn lsb_oracle_attack<F, P>(
+    pubkey: &(BigUint, BigUint),
+    ct: &BigUint,
+    mut low_byte: F,
+    progress: P,
+) -> BigUint
+where
+    F: FnMut(&BigUint) -> u8,
+    P: FnMut(&BigRational, &BigRational),
+{
+    low_bits_oracle_attack(pubkey, ct, 8, |c| BigUint::from(low_byte(c)), progress)
+}
+
+/*
+the oracle tells whether the plaintext is in the top half, m >= n/2. 2m mod n is
+odd exactly when m >= n/2, so querying c * 2^-e turns it into a parity oracle
+*/
+pub fn msb_oracle_attack<F, P>(
+    pubkey: &(BigUint, BigUint),
+    ct: &BigUint,
+    mut top_half: F,
+    progress: P,
+) -> BigUint
+where
+    F: FnMut(&BigUint) -> bool,
+    P: FnMut(&BigRational, &BigRational),
+{
+    let (e, n) = pubkey;
+    let half_e = mod_inv(&BigUint::from(2_u32), n).unwrap().modpow(e, n);
+    parity_oracle_attack(pubkey, ct, |c| top_half(&(c * &half_e % n)), progress)
+}
+
+#[test]
+fn rsa_oracle_attacks_work() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(46);
+    let rsa = RSA::new_with_rng(128, &mut rng);
+    let pt = BigUint::from_bytes_be(b"Funky Cold Medina");
+    let ct = rsa.encrypt(&pt);
+
+    let oracle = |c: &BigUint| {
+        if c == &ct {
+            None
+        } else {
+            Some(rsa.decrypt(c))
+        }
+    };
+    assert_eq!(
+        unpadded_recovery(&rsa.pubkey, &ct, oracle),
+        Some(pt.clone())
+    );
+    let recovered = unpadded_recovery_with_rng(&rsa.pubkey, &ct, oracle, &mut rng);
+    assert_eq!(recovered, Some(pt.clone()));
+    let zero = BigUint::zero();
+    let refuses_zero = |c: &BigUint| {
+        if c.is_zero() {
+            None
+        } else {
+            Some(rsa.decrypt(c))
+        }
+    };
+    assert_eq!(
+        unpadded_recovery(&rsa.pubkey, &zero, refuses_zero),
+        Some(zero.clone())
+    );
+
+    let mut last_width = None;
+    let recovered = parity_oracle_attack(
+        &rsa.pubkey,
+        &ct,
+        |c| rsa.decrypt(c).is_odd(),
+        |lower, upper| {
+            let width = upper - lower;
+            if let Some(last) = &last_width {
+                assert!(&width < last);
+            }
+            last_width = Some(width);
+        },
+    );
+    assert_eq!(recovered, pt);
+
+    let recovered = lsb_oracle_attack(
+        &rsa.pubkey,
+        &ct,
+        |c| rsa.decrypt(c).to_bytes_le()[0],
+        |_, _| {},
+    );
+    assert_eq!(recovered, pt);
+
+    let half = &rsa.n >> 1;
+    let recovered = msb_oracle_attack(&rsa.pubkey, &ct, |c| rsa.decrypt(c) > half, |_, _| {});
+    assert_eq!(recovered, pt);
+}
@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/*
+Ways to tell how much a byte string looks like the plaintext we're after, for
+the xor breakers. Higher is more plausible. Scores are only compared between
+candidates of the same length, so every scorer may scale them as it likes.
+*/
+pub trait Scorer: Sync {
+    fn score(&self, data: &[u8]) -> f64;
+}
+
+impl<F: Fn(&[u8]) -> f64 + Sync> Scorer for F {
+    fn score(&self, data: &[u8]) -> f64 {
+        self(data)
+    }
+}
+
+/*
+for sorting by score, a scorer that returns NaN gets the worst place instead
+of panicking whoever sorts
+*/
+pub(crate) fn score_or_worst(score: f64) -> f64 {
+    if score.is_nan() {
+        f64::NEG_INFINITY
+    } else {
+        score
+    }
+}
+
+/*
+the original hand-tuned english_frequency_score
+*/
+pub struct EnglishHeuristic;
+
+impl Scorer for EnglishHeuristic {
+    fn score(&self, data: &[u8]) -> f64 {
+        crate::english_frequency_score(data) as f64
+    }
+}
+
+// letter and space frequencies of running English text
+const ENGLISH_UNIGRAMS: [(u8, f64); 27] = [
+    (b' ', 0.1918182),
+    (b'e', 0.1041442),
+    (b't', 0.0729357),
+    (b'a', 0.0651738),
+    (b'o', 0.0596302),
+    (b'n', 0.0564513),
+    (b'i', 0.0558094),
+    (b's', 0.0515760),
+    (b'r', 0.0497563),
+    (b'h', 0.0492888),
+    (b'd', 0.0349835),
+    (b'l', 0.0331490),
+    (b'u', 0.0225134),
+    (b'c', 0.0217339),
+    (b'm', 0.0202124),
+    (b'f', 0.0197881),
+    (b'w', 0.0171272),
+    (b'g', 0.0158610),
+    (b'y', 0.0145984),
+    (b'p', 0.0137645),
+    (b'b', 0.0124248),
+    (b'v', 0.0082903),
+    (b'k', 0.0050529),
+    (b'x', 0.0013692),
+    (b'j', 0.0009033),
+    (b'q', 0.0008606),
+    (b'z', 0.0007836),
+];
+
+/*
+Minus Pearson's chi-squared statistic of the byte counts against expected
+byte probabilities. With fold_case, upper case letters count as lower case.
+*/
+pub struct ChiSquared {
+    expected: Vec<f64>,
+    fold_case: bool,
+}
+
+impl ChiSquared {
+    /*
+    letters and spaces as in ENGLISH_UNIGRAMS, a little room for punctuation
+    and digits and hardly any for everything else
+    */
+    pub fn english() -> ChiSquared {
+        let mut expected = vec![0.00001; 256];
+        for &b in b".,;:'\"!?-()\n0123456789".iter() {
+            expected[b as usize] = 0.001;
+        }
+        for &(b, p) in ENGLISH_UNIGRAMS.iter() {
+            expected[b as usize] = p;
+        }
+        ChiSquared::normalized(expected, true)
+    }
+
+    /*
+    byte frequencies of a sample text, add-one smoothed
+    */
+    pub fn from_corpus(corpus: &[u8]) -> ChiSquared {
+        let mut expected = vec![1.0; 256];
+        for &b in corpus {
+            expected[b as usize] += 1.0;
+        }
+        ChiSquared::normalized(expected, false)
+    }
+
+    fn normalized(mut expected: Vec<f64>, fold_case: bool) -> ChiSquared {
+        let total: f64 = expected.iter().sum();
+        for p in expected.iter_mut() {
+            *p /= total;
+        }
+        ChiSquared {
+            expected,
+            fold_case,
+        }
+    }
+}
+
+impl Scorer for ChiSquared {
+    fn score(&self, data: &[u8]) -> f64 {
+        if data.is_empty() {
+            return 0.0;
+        }
+        let mut counts = [0_usize; 256];
+        for &b in data {
+            let b = if self.fold_case {
+                b.to_ascii_lowercase()
+            } else {
+                b
+            };
+            counts[b as usize] += 1;
+        }
+        let n = data.len() as f64;
+        -counts
+            .iter()
+            .zip(self.expected.iter())
+            .map(|(&observed, &p)| (observed as f64 - n * p).powi(2) / (n * p))
+            .sum::<f64>()
+    }
+}
+
+/*
+Average log-probability of the n-grams (n up to 8) in the data under counts
+taken from a corpus. N-grams the corpus never had get a floor of 1% of a
+single occurrence.
+*/
+pub struct NgramModel {
+    n: usize,
+    log_probs: HashMap<u64, f64>,
+    floor: f64,
+}
+
+// an n-gram packed into a u64, so looking one up doesn't allocate
+fn ngram_key(gram: &[u8]) -> u64 {
+    gram.iter().fold(0, |key, &b| key << 8 | b as u64)
+}
+
+impl NgramModel {
+    pub fn train(corpus: &[u8], n: usize) -> NgramModel {
+        assert!((1..=8).contains(&n));
+        let mut counts: HashMap<u64, usize> = HashMap::new();
+        for gram in corpus.windows(n) {
+            *counts.entry(ngram_key(gram)).or_insert(0) += 1;
+        }
+        let total = corpus.len().saturating_sub(n - 1).max(1) as f64;
+        NgramModel {
+            n,
+            log_probs: counts
+                .into_iter()
+                .map(|(key, count)| (key, (count as f64 / total).ln()))
+                .collect(),
+            floor: (0.01 / total).ln(),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P, n: usize) -> Result<NgramModel, ()> {
+        let corpus = std::fs::read(path).map_err(|_| ())?;
+        Ok(NgramModel::train(&corpus, n))
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+}
+
+impl Scorer for NgramModel {
+    fn score(&self, data: &[u8]) -> f64 {
+        if data.len() < self.n {
+            return self.floor;
+        }
+        let total: f64 = data
+            .windows(self.n)
+            .map(|gram| *self.log_probs.get(&ngram_key(gram)).unwrap_or(&self.floor))
+            .sum();
+        total / (data.len() - self.n + 1) as f64
+    }
+}
+
+/*
+the share of bytes that are printable ASCII, tabs or line breaks
+*/
+pub struct PrintableRatio;
+
+impl Scorer for PrintableRatio {
+    fn score(&self, data: &[u8]) -> f64 {
+        if data.is_empty() {
+            return 0.0;
+        }
+        let printable = data
+            .iter()
+            .filter(|&&b| (0x20..0x7f).contains(&b) || b == b'\t' || b == b'\n' || b == b'\r')
+            .count();
+        printable as f64 / data.len() as f64
+    }
+}
+
+/*
+`bonus` times the share of bytes that are part of well-formed UTF-8, only
+meaningful on contiguous text
+*/
+pub struct Utf8Bonus {
+    pub bonus: f64,
+}
+
+impl Scorer for Utf8Bonus {
+    fn score(&self, data: &[u8]) -> f64 {
+        if data.is_empty() {
+            return 0.0;
+        }
+        let mut valid = 0;
+        let mut rest = data;
+        while !rest.is_empty() {
+            match std::str::from_utf8(rest) {
+                Ok(_) => {
+                    valid += rest.len();
+                    break;
+                }
+                Err(error) => {
+                    valid += error.valid_up_to();
+                    let skip = error
+                        .error_len()
+                        .unwrap_or(rest.len() - error.valid_up_to());
+                    rest = &rest[error.valid_up_to() + skip..];
+                }
+            }
+        }
+        self.bonus * valid as f64 / data.len() as f64
+    }
+}
+
+/*
+a weighted sum of other scorers
+*/
+pub struct Combined(pub Vec<(f64, Box<dyn Scorer>)>);
+
+impl Scorer for Combined {
+    fn score(&self, data: &[u8]) -> f64 {
+        self.0
+            .iter()
+            .map(|(weight, scorer)| weight * scorer.score(data))
+            .sum()
+    }
+}
+
+#[test]
+fn scoring_works() {
+    use crate::{find_xor_key_with, search_xor_key_with, xor_arrays};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let corpus = b"It was the best of times, it was the worst of times, it was the age of \
+        wisdom, it was the age of foolishness, it was the epoch of belief, it was the epoch \
+        of incredulity, it was the season of Light, it was the season of Darkness, it was the \
+        spring of hope, it was the winter of despair, we had everything before us, we had \
+        nothing before us, we were all going direct to Heaven, we were all going direct the \
+        other way - in short, the period was so far like the present period, that some of its \
+        noisiest authorities insisted on its being received, for good or for evil, in the \
+        superlative degree of comparison only.";
+    let text = b"There were a king with a large jaw and a queen with a plain face, on the throne";
+    let noise = xor_arrays(text, &[0x5a]);
+
+    let chi = ChiSquared::english();
+    assert!(chi.score(text) > chi.score(&noise));
+    assert_eq!(chi.score(b"HELLO there"), chi.score(b"hello THERE"));
+    let own = ChiSquared::from_corpus(corpus);
+    assert!(own.score(text) > own.score(&noise));
+    assert_eq!(PrintableRatio.score(b"ab\x00\xff"), 0.5);
+    assert_eq!(Utf8Bonus { bonus: 2.0 }.score("süß".as_bytes()), 2.0);
+    assert_eq!(Utf8Bonus { bonus: 2.0 }.score(b"ab\xc3"), 2.0 * 2.0 / 3.0);
+
+    // bigrams from a corpus file
+    let path = std::env::temp_dir().join(format!("corpus-{}.txt", std::process::id()));
+    std::fs::write(&path, &corpus[..]).unwrap();
+    let bigrams = NgramModel::from_file(&path, 2).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(NgramModel::from_file(&path, 2).is_err());
+    assert_eq!(bigrams.n(), 2);
+    let (_, key, plaintext) = find_xor_key_with(&xor_arrays(text, &[77]), &bigrams);
+    assert_eq!((key, &plaintext[..]), (77, &text[..]));
+    let trigrams = NgramModel::train(corpus, 3);
+    assert!(trigrams.score(b"it was the") > trigrams.score(b"ti saw eht"));
+
+    // German, where swapping the case of everything is just as plausible to
+    // chi-squared but breaks the UTF-8
+    let german = "Größere Änderungen führen später zu Ärger über die Maßnahmen".as_bytes();
+    let scorer = Combined(vec![
+        (1.0, Box::new(ChiSquared::english())),
+        (100.0, Box::new(Utf8Bonus { bonus: 1.0 })),
+    ]);
+    let (_, key, plaintext) = find_xor_key_with(&xor_arrays(german, &[0x13]), &scorer);
+    assert_eq!((key, &plaintext[..]), (0x13, german));
+
+    // something binary and mostly zero, under a repeating key
+    let mut rng = StdRng::seed_from_u64(48);
+    let data: Vec<u8> = (0..400)
+        .map(|_| if rng.gen_bool(0.1) { rng.gen() } else { 0 })
+        .collect();
+    let key: Vec<u8> = (0..5).map(|_| rng.gen()).collect();
+    let zeros = |data: &[u8]| data.iter().filter(|&&b| b == 0).count() as f64;
+    let (_, found, plaintext) = search_xor_key_with(&xor_arrays(&data, &key), 5, &zeros);
+    assert_eq!((found, plaintext), (key, data));
+}
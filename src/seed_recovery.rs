@@ -0,0 +1,230 @@
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use rand::RngCore;
+
+use crate::mt19937::Mt19937;
+
+/*
+What people build on a time- or otherwise small-seeded MT19937, the cryptopals
+22 and 24 constructions, and getting the seed back. Every search hands each
+candidate seed's fresh generator to a check and splits the seed range across
+threads, a 32 bit space is hours of work, 16 bits or a time window is quick.
+*/
+
+pub const SEEDS_16: RangeInclusive<u32> = 0..=0xffff;
+pub const SEEDS_32: RangeInclusive<u32> = 0..=u32::MAX;
+
+/*
+every second from `secs_back` before `now` up to `now`
+*/
+pub fn time_window(now: u64, secs_back: u64) -> RangeInclusive<u32> {
+    (now.saturating_sub(secs_back) as u32)..=(now as u32)
+}
+
+/*
+a seed whose generator passes `check`, not necessarily the smallest one
+*/
+pub fn search_seeds<F>(seeds: RangeInclusive<u32>, threads: usize, check: F) -> Option<u32>
+where
+    F: Fn(Mt19937) -> bool + Sync,
+{
+    let (start, end) = (*seeds.start() as u64, *seeds.end() as u64);
+    let threads = threads.max(1) as u64;
+    let found = Mutex::new(None);
+    let done = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        for worker in 0..threads {
+            let (check, found, done) = (&check, &found, &done);
+            scope.spawn(move || {
+                let mut seed = start + worker;
+                while seed <= end && !done.load(Ordering::Relaxed) {
+                    if check(Mt19937::from_seed(seed as u32)) {
+                        *found.lock().unwrap() = Some(seed as u32);
+                        done.store(true, Ordering::Relaxed);
+                    }
+                    seed += threads;
+                }
+            });
+        }
+    });
+    found.into_inner().unwrap()
+}
+
+/*
+For outputs taken from somewhere in the first max_offset + outputs.len() of
+a seeded stream: the seed and how many outputs came before them.
+*/
+pub fn recover_seed_from_outputs(
+    outputs: &[u32],
+    max_offset: usize,
+    seeds: RangeInclusive<u32>,
+    threads: usize,
+) -> Option<(u32, usize)> {
+    if outputs.is_empty() {
+        return None;
+    }
+    let stream = |mut mt: Mt19937| -> Vec<u32> {
+        (0..max_offset + outputs.len())
+            .map(|_| mt.extract_number())
+            .collect()
+    };
+    let find = |stream: &[u32]| stream.windows(outputs.len()).position(|w| w == outputs);
+    let seed = search_seeds(seeds, threads, |mt| find(&stream(mt)).is_some())?;
+    let offset = find(&stream(Mt19937::from_seed(seed)))?;
+    Some((seed, offset))
+}
+
+/*
+The cryptopals 24 stream cipher, the low byte of each output is xored on.
+Encrypting and decrypting are the same thing.
+*/
+pub fn mt_crypt(key: u16, data: &[u8]) -> Vec<u8> {
+    let mut mt = Mt19937::from_seed(key as u32);
+    data.iter()
+        .map(|byte| byte ^ mt.extract_number() as u8)
+        .collect()
+}
+
+/*
+the key, given a plaintext fragment that shows up somewhere after an unknown
+amount of other plaintext
+*/
+pub fn recover_stream_key(ct: &[u8], known: &[u8], threads: usize) -> Option<u16> {
+    if known.is_empty() || known.len() > ct.len() {
+        return None;
+    }
+    let seed = search_seeds(SEEDS_16, threads, |mut mt| {
+        let pt: Vec<u8> = ct
+            .iter()
+            .map(|byte| byte ^ mt.extract_number() as u8)
+            .collect();
+        pt.windows(known.len()).any(|w| w == known)
+    })?;
+    Some(seed as u16)
+}
+
+// 4 outputs per token
+const TOKEN_LEN: usize = 16;
+
+/*
+Password reset tokens from an MT19937 seeded with the time the issuer started,
+hex of 16 bytes taken from consecutive outputs.
+*/
+pub struct TokenIssuer {
+    mt: Mt19937,
+}
+
+impl TokenIssuer {
+    pub fn new(seed: u32) -> TokenIssuer {
+        TokenIssuer {
+            mt: Mt19937::from_seed(seed),
+        }
+    }
+
+    pub fn from_time() -> TokenIssuer {
+        TokenIssuer::new(crate::get_timestamp().as_secs() as u32)
+    }
+
+    pub fn next_token(&mut self) -> String {
+        let mut token = [0_u8; TOKEN_LEN];
+        self.mt.fill_bytes(&mut token);
+        crate::bytes_to_hex(&token)
+    }
+}
+
+/*
+If the token came from a TokenIssuer seeded within `seeds`, that seed and how
+many tokens it had handed out before, checking up to `max_tokens` of them.
+*/
+pub fn token_seed(
+    token: &str,
+    seeds: RangeInclusive<u32>,
+    max_tokens: usize,
+    threads: usize,
+) -> Option<(u32, usize)> {
+    // hex_to_bytes panics on anything else
+    if token.len() != 2 * TOKEN_LEN || !token.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let bytes = crate::hex_to_bytes(token);
+    let outputs: Vec<u32> = bytes
+        .chunks(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+    let (seed, offset) =
+        recover_seed_from_outputs(&outputs, max_tokens * outputs.len(), seeds, threads)?;
+    // a token always starts on a multiple of 4 outputs
+    if offset % outputs.len() != 0 {
+        return None;
+    }
+    Some((seed, offset / outputs.len()))
+}
+
+#[test]
+fn seed_recovery_works() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(45);
+    let now = 1_600_000_000_u64;
+
+    // cryptopals 22, the value sits 5 outputs in
+    let seed = (now - rng.gen_range(40, 1000)) as u32;
+    let mut mt = Mt19937::from_seed(seed);
+    let outputs: Vec<u32> = (0..8).map(|_| mt.extract_number()).collect();
+    assert_eq!(
+        recover_seed_from_outputs(&outputs[5..6], 10, time_window(now, 1000), 4),
+        Some((seed, 5))
+    );
+    assert_eq!(
+        recover_seed_from_outputs(&outputs[5..6], 4, time_window(now, 1000), 4),
+        None
+    );
+
+    // cryptopals 24, a random prefix in front of the known plaintext
+    let key: u16 = rng.gen();
+    let prefix: Vec<u8> = (0..rng.gen_range(5, 40)).map(|_| rng.gen()).collect();
+    let pt = [&prefix[..], &[b'A'; 14][..], b" and a tail"].concat();
+    let ct = mt_crypt(key, &pt);
+    assert_eq!(mt_crypt(key, &ct), pt);
+    let found = recover_stream_key(&ct, &[b'A'; 14], 4).unwrap();
+    assert_eq!(mt_crypt(found, &ct), pt);
+
+    // reset tokens, the third one an issuer started a minute ago handed out
+    let seed = (now - 60) as u32;
+    let mut issuer = TokenIssuer::new(seed);
+    let tokens: Vec<String> = (0..3).map(|_| issuer.next_token()).collect();
+    assert_eq!(tokens[2].len(), 32);
+    assert_eq!(
+        token_seed(&tokens[2], time_window(now, 3600), 5, 4),
+        Some((seed, 2))
+    );
+    for malformed in [
+        "abc",
+        "zz",
+        &tokens[2][1..],
+        &format!("g{}", &tokens[2][1..]),
+    ]
+    .iter()
+    {
+        assert_eq!(token_seed(malformed, time_window(now, 3600), 5, 4), None);
+    }
+    let mut random_token = [0_u8; TOKEN_LEN];
+    rng.fill(&mut random_token);
+    assert_eq!(
+        token_seed(
+            &crate::bytes_to_hex(&random_token),
+            time_window(now, 3600),
+            5,
+            4
+        ),
+        None
+    );
+    assert_eq!(
+        search_seeds(SEEDS_16, 3, |mut mt| mt.extract_number() == outputs[0]),
+        None
+    );
+}
@@ -0,0 +1,189 @@
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::framing::{FieldReader, Message};
+
+/*
+Threaded TCP servers for the protocol demos. `serve` binds before returning, so
+"127.0.0.1:0" gets an ephemeral port that local_addr() reports and clients can
+connect right away. Every connection gets its own thread.
+*/
+
+// reasons sent back in Message::error
+pub const MALFORMED: &str = "malformed request";
+pub const UNKNOWN_COMMAND: &str = "unknown command";
+pub const UNKNOWN_USER: &str = "unknown user";
+pub const USER_EXISTS: &str = "user exists";
+pub const DISCONNECTED: &str = "connection lost";
+
+pub struct ServerHandle {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn connstring(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /*
+    stops accepting, connections already running are left to finish
+    */
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return,
+        };
+        self.stop.store(true, Ordering::SeqCst);
+        // wake up the blocking accept
+        let _ = TcpStream::connect(self.addr);
+        let _ = thread.join();
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub fn serve<A, F>(addr: A, handler: F) -> Result<ServerHandle, ()>
+where
+    A: ToSocketAddrs,
+    F: Fn(TcpStream) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr).map_err(|_| ())?;
+    let addr = listener.local_addr().map_err(|_| ())?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler = Arc::new(handler);
+
+    let thread = {
+        let stop = stop.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let handler = handler.clone();
+                    thread::spawn(move || handler(stream));
+                }
+            }
+        })
+    };
+    Ok(ServerHandle {
+        addr,
+        stop,
+        thread: Some(thread),
+    })
+}
+
+/*
+request/reply servers: the first field of a request names the command, the
+handler gets the rest, and whatever it fails with goes back as an error frame
+*/
+pub fn serve_commands<A, F>(addr: A, handler: F) -> Result<ServerHandle, ()>
+where
+    A: ToSocketAddrs,
+    F: Fn(&mut TcpStream, &str, FieldReader<'_>) -> Result<(), &'static str>
+        + Send
+        + Sync
+        + 'static,
+{
+    serve(addr, move |mut stream| {
+        let result = Message::recv(&mut stream)
+            .map_err(|_| MALFORMED)
+            .and_then(|request| {
+                let mut fields = request.fields();
+                let command = fields.read::<String>().map_err(|_| MALFORMED)?;
+                handler(&mut stream, &command, fields)
+            });
+        if let Err(reason) = result {
+            // the peer may be gone already
+            let _ = Message::error(reason).send(&mut stream);
+        }
+    })
+}
+
+#[test]
+fn server_handle_works() {
+    use std::sync::mpsc;
+
+    // echoes one frame back, after the second client has connected
+    let (arrived, wait) = mpsc::channel();
+    let wait = std::sync::Mutex::new(wait);
+    let server = serve("127.0.0.1:0", move |mut stream| {
+        if let Ok(message) = Message::recv(&mut stream) {
+            if message.fields().read::<String>() == Ok("first".to_string()) {
+                // only returns if the server is handling both clients at once
+                let _ = wait.lock().unwrap().recv();
+            } else {
+                arrived.send(()).unwrap();
+            }
+            let _ = message.send(&mut stream);
+        }
+    })
+    .unwrap();
+    assert_ne!(server.local_addr().port(), 0);
+
+    let mut first = TcpStream::connect(server.local_addr()).unwrap();
+    Message::new().with("first").send(&mut first).unwrap();
+    let mut second = TcpStream::connect(server.local_addr()).unwrap();
+    Message::new().with("second").send(&mut second).unwrap();
+    assert_eq!(
+        Message::recv(&mut second).unwrap(),
+        Message::new().with("second")
+    );
+    assert_eq!(
+        Message::recv(&mut first).unwrap(),
+        Message::new().with("first")
+    );
+
+    let addr = server.local_addr();
+    server.shutdown();
+    assert!(TcpStream::connect(addr).is_err());
+
+    let server = serve_commands("127.0.0.1:0", |stream, command, fields| match command {
+        "PING" => {
+            fields.finish().map_err(|_| MALFORMED)?;
+            Message::new()
+                .with("PONG")
+                .send(stream)
+                .map_err(|_| DISCONNECTED)
+        }
+        _ => Err(UNKNOWN_COMMAND),
+    })
+    .unwrap();
+    let ask = |request: Message| {
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        request.send(&mut stream).unwrap();
+        Message::recv(&mut stream).unwrap()
+    };
+    assert_eq!(
+        ask(Message::new().with("PING")),
+        Message::new().with("PONG")
+    );
+    assert_eq!(
+        ask(Message::new().with("PING").with("x")).error_reason(),
+        Some(MALFORMED.to_string())
+    );
+    assert_eq!(
+        ask(Message::new().with("PONG")).error_reason(),
+        Some(UNKNOWN_COMMAND.to_string())
+    );
+    assert_eq!(
+        ask(Message::new().with(&[1_u8][..])).error_reason(),
+        Some(MALFORMED.to_string())
+    );
+}
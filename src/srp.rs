@@ -1,16 +1,35 @@
 #![allow(non_snake_case)]
 
 use std::collections::HashMap;
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::thread;
+use std::net::TcpStream;
+use std::sync::{Mutex, RwLock};
 
 use num_bigint::{BigUint, RandBigInt, ToBigUint};
+use num_traits::Zero;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 use sha2::{Digest, Sha256};
 
-use crate::dh::DiffieHellmanState;
-use crate::{bytes_to_hexbytes, hexbytes_to_bytes};
+use crate::dh::{groups, DiffieHellmanState};
+use crate::framing::{FieldReader, Message};
+use crate::numtheory::mod_sub;
+use crate::server::{
+    serve_commands, ServerHandle, DISCONNECTED, MALFORMED, UNKNOWN_COMMAND, UNKNOWN_USER,
+    USER_EXISTS,
+};
+use crate::sha1::Sha1;
 
+/*
+The servers speak in commands: ("AUTH", username, A) starts a login and
+("REGISTER", username, ...) adds a user while the server runs. Anything the
+server can't go on with is answered with Message::error. Servers and clients
+draw salts and secrets from a caller-supplied rng in the _with_rng variants.
+*/
+
+// what the servers hold on to, shared by the connection threads
+pub(crate) type SharedRng = Mutex<Box<dyn RngCore + Send>>;
+
+#[derive(Clone)]
 struct UserSRP {
     v: BigUint,
     salt: BigUint,
@@ -20,7 +39,8 @@ pub struct SRPServer {
     N: BigUint,
     g: BigUint,
     k: BigUint,
-    users_srp: HashMap<String, UserSRP>,
+    users_srp: RwLock<HashMap<String, UserSRP>>,
+    rng: SharedRng,
 }
 
 impl SRPServer {
@@ -30,35 +50,75 @@ impl SRPServer {
         g: BigUint,
         k: BigUint,
         users: HashMap<String, String>,
-    ) {
+    ) -> Result<ServerHandle, ()> {
+        SRPServer::start_with_rng(connstring, N, g, k, users, StdRng::from_entropy())
+    }
+
+    pub fn start_with_rng<R: RngCore + Send + 'static>(
+        connstring: String,
+        N: BigUint,
+        g: BigUint,
+        k: BigUint,
+        users: HashMap<String, String>,
+        mut rng: R,
+    ) -> Result<ServerHandle, ()> {
         let mut users_srp: HashMap<String, UserSRP> = HashMap::new();
         for (username, password) in users.iter() {
-            let srp_params = SRPServer::calc_user_srp(&g, &N, password);
+            let srp_params = SRPServer::calc_user_srp(&g, &N, password, &mut rng);
             users_srp.insert(username.to_string(), srp_params);
         }
-        let server = SRPServer { N, g, k, users_srp };
-        thread::spawn(move || {
-            let listener = TcpListener::bind(connstring).unwrap();
-            for stream in listener.incoming() {
-                server.handle_connection(stream.unwrap());
-            }
-        });
+        let server = SRPServer {
+            N,
+            g,
+            k,
+            users_srp: RwLock::new(users_srp),
+            rng: Mutex::new(Box::new(rng)),
+        };
+        serve_commands(connstring, move |stream, command, fields| match command {
+            "AUTH" => server.authenticate(stream, fields),
+            "REGISTER" => server.register(stream, fields),
+            _ => Err(UNKNOWN_COMMAND),
+        })
+    }
+
+    fn register(
+        &self,
+        stream: &mut TcpStream,
+        mut fields: FieldReader<'_>,
+    ) -> Result<(), &'static str> {
+        let username = fields.read::<String>().map_err(|_| MALFORMED)?;
+        let password = fields.read::<String>().map_err(|_| MALFORMED)?;
+        fields.finish().map_err(|_| MALFORMED)?;
+
+        let srp_params =
+            SRPServer::calc_user_srp(&self.g, &self.N, &password, &mut *self.rng.lock().unwrap());
+        let mut users_srp = self.users_srp.write().unwrap();
+        if users_srp.contains_key(&username) {
+            return Err(USER_EXISTS);
+        }
+        users_srp.insert(username, srp_params);
+        drop(users_srp);
+        Message::new()
+            .with("OK")
+            .send(stream)
+            .map_err(|_| DISCONNECTED)
     }
 
-    fn handle_connection(&self, mut stream: TcpStream) {
-        let dh = DiffieHellmanState::new(&self.g, &self.N);
+    fn authenticate(
+        &self,
+        stream: &mut TcpStream,
+        mut fields: FieldReader<'_>,
+    ) -> Result<(), &'static str> {
+        let dh = DiffieHellmanState::new_with_rng(&self.g, &self.N, &mut *self.rng.lock().unwrap());
         /*
         read username+DH_pubkey
         */
-        let mut buffer = [0; 1024];
-        let read_bytes = stream.read(&mut buffer).unwrap();
-        let data = buffer[..read_bytes].to_vec();
-        let mut tokens = data.splitn(2, |x| x == &0x00_u8);
+        let username = fields.read::<String>().map_err(|_| MALFORMED)?;
+        let A = fields.read::<BigUint>().map_err(|_| MALFORMED)?;
+        fields.finish().map_err(|_| MALFORMED)?;
 
-        let username = String::from_utf8(tokens.next().unwrap().to_vec()).unwrap();
-        let A = BigUint::from_bytes_be(&hexbytes_to_bytes(&tokens.next().unwrap()));
-
-        let user_srp = self.users_srp.get(&username).unwrap();
+        let user_srp = self.users_srp.read().unwrap().get(&username).cloned();
+        let user_srp = user_srp.ok_or(UNKNOWN_USER)?;
         let user_v = &user_srp.v;
         let user_salt = &user_srp.salt;
 
@@ -66,16 +126,11 @@ impl SRPServer {
         /*
         write salt+B as response
         */
-        stream
-            .write_all(
-                &vec![
-                    bytes_to_hexbytes(&user_salt.to_bytes_be()),
-                    [0x00].to_vec(),
-                    bytes_to_hexbytes(&B.to_bytes_be()),
-                ]
-                .concat(),
-            )
-            .unwrap();
+        Message::new()
+            .with(user_salt)
+            .with(&B)
+            .send(stream)
+            .map_err(|_| DISCONNECTED)?;
         /*
         proceed with our calculations
         */
@@ -91,19 +146,28 @@ impl SRPServer {
         /*
         read KEX result from client
         */
-        let mut buffer = [0; 1024];
-        let read_bytes = stream.read(&mut buffer).unwrap();
-        let payload = &buffer[..read_bytes];
+        let client_hmac = Message::recv(stream)
+            .and_then(|reply| reply.fields().read::<Vec<u8>>())
+            .map_err(|_| MALFORMED)?;
 
-        if hexbytes_to_bytes(payload) == etalon.as_slice() {
-            stream.write_all(b"OK").unwrap();
+        let result = if client_hmac == etalon.as_slice() {
+            "OK"
         } else {
-            stream.write_all(b"FAIL").unwrap();
-        }
+            "FAIL"
+        };
+        Message::new()
+            .with(result)
+            .send(stream)
+            .map_err(|_| DISCONNECTED)
     }
 
-    fn calc_user_srp(g: &BigUint, N: &BigUint, password: &str) -> UserSRP {
-        let salt = rand::thread_rng().gen_biguint_below(N);
+    fn calc_user_srp<R: Rng + ?Sized>(
+        g: &BigUint,
+        N: &BigUint,
+        password: &str,
+        rng: &mut R,
+    ) -> UserSRP {
+        let salt = rng.gen_biguint_below(N);
 
         let mut hasher = Sha256::new();
         hasher.update(salt.to_bytes_be());
@@ -116,9 +180,36 @@ impl SRPServer {
     }
 }
 
+/*
+sends one request and reads the single-string answer, true on "OK"
+*/
+pub(crate) fn request_ok(connstring: &str, request: Message) -> bool {
+    let mut stream = match TcpStream::connect(connstring) {
+        Ok(stream) => stream,
+        Err(_) => return false,
+    };
+    if request.send(&mut stream).is_err() {
+        return false;
+    }
+    match Message::recv(&mut stream).and_then(|reply| reply.fields().read::<String>()) {
+        Ok(result) => result == "OK",
+        Err(_) => false,
+    }
+}
+
 pub struct SRPClient {}
 
 impl SRPClient {
+    pub fn register(connstring: String, username: String, password: String) -> bool {
+        request_ok(
+            &connstring,
+            Message::new()
+                .with("REGISTER")
+                .with(&username)
+                .with(&password),
+        )
+    }
+
     pub fn auth(
         connstring: String,
         N: BigUint,
@@ -128,38 +219,62 @@ impl SRPClient {
         password: String,
         attack: &Option<BigUint>,
     ) -> bool {
-        let mut stream = TcpStream::connect(connstring).unwrap();
+        SRPClient::auth_with_rng(
+            connstring,
+            N,
+            g,
+            k,
+            username,
+            password,
+            attack,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn auth_with_rng<R: Rng + ?Sized>(
+        connstring: String,
+        N: BigUint,
+        g: BigUint,
+        k: BigUint,
+        username: String,
+        password: String,
+        attack: &Option<BigUint>,
+        rng: &mut R,
+    ) -> bool {
+        let mut stream = match TcpStream::connect(connstring) {
+            Ok(stream) => stream,
+            Err(_) => return false,
+        };
 
         /*
         send username and DH pubkey
         */
-        let dh = DiffieHellmanState::new(&g, &N);
+        let dh = DiffieHellmanState::new_with_rng(&g, &N, rng);
         let A = match attack {
             None => dh.pubkey,
             Some(a) => a.clone(),
         };
-        stream
-            .write_all(
-                &vec![
-                    username.as_bytes(),
-                    &[0x00],
-                    &bytes_to_hexbytes(&A.to_bytes_be()),
-                ]
-                .concat(),
-            )
-            .unwrap();
+        if Message::new()
+            .with("AUTH")
+            .with(&username)
+            .with(&A)
+            .send(&mut stream)
+            .is_err()
+        {
+            return false;
+        }
 
         /*
-        read salt+B from server
+        read salt+B from server, an error reply won't parse
         */
-        let mut buffer = [0; 2048];
-        let read_bytes = stream.read(&mut buffer).unwrap();
-
-        let data = buffer[..read_bytes].to_vec();
-        let mut tokens = data.splitn(2, |x| x == &0x00_u8);
-
-        let server_salt = BigUint::from_bytes_be(&hexbytes_to_bytes(&tokens.next().unwrap()));
-        let B = BigUint::from_bytes_be(&hexbytes_to_bytes(&tokens.next().unwrap()));
+        let (server_salt, B) = match Message::recv(&mut stream).and_then(|reply| {
+            let mut fields = reply.fields();
+            Ok((fields.read::<BigUint>()?, fields.read::<BigUint>()?))
+        }) {
+            Ok(values) => values,
+            Err(_) => return false,
+        };
 
         /*
         compute stuff
@@ -172,7 +287,7 @@ impl SRPClient {
             &vec![server_salt.to_bytes_be(), password.as_bytes().to_vec()].concat(),
         ));
         let S = match attack {
-            None => (B - (k * (g.modpow(&x, &N)))).modpow(&(dh.secret + (u * x)), &N),
+            None => mod_sub(&B, &(k * g.modpow(&x, &N)), &N).modpow(&(dh.secret + (u * x)), &N),
             Some(_) => 0.to_biguint().unwrap(),
         };
         println!("client got S: {}", S);
@@ -183,17 +298,618 @@ impl SRPClient {
         send our HMAC(K, salt) to server for verification
         */
         let hmac = Sha256::digest(&vec![K, &server_salt.to_bytes_be()].concat());
-        stream.write_all(&bytes_to_hexbytes(&hmac)).unwrap();
+        if Message::new()
+            .with(hmac.as_slice())
+            .send(&mut stream)
+            .is_err()
+        {
+            return false;
+        }
 
         /*
         read final "OK" or "FAIL"
         */
-        let mut buffer = [0_u8; 2048];
-        let _ = stream.read(&mut buffer).unwrap();
-        if buffer[0] == 'O' as u8 {
-            true
-        } else {
-            false
+        match Message::recv(&mut stream).and_then(|reply| reply.fields().read::<String>()) {
+            Ok(result) => result == "OK",
+            Err(_) => false,
+        }
+    }
+}
+
+/*
+SRP-6a as RFC 5054 has it, next to the toy protocol above:
+  k = H(N | PAD(g)), x = H(s | H(I | ":" | P)), u = H(PAD(A) | PAD(B))
+  M1 = H(H(N) xor H(g) | H(I) | s | A | B | K), M2 = H(A | M1 | K), K = H(S)
+and both sides refuse public values that are 0 mod N.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SrpHash {
+    Sha1,
+    Sha256,
+}
+
+// RFC 5054 appendix A, the 3072 to 8192 bit groups are the RFC 3526 primes
+const RFC5054_1024: &str = "eeaf0ab9adb38dd69c33f80afa8fc5e86072618775ff3c0b9ea2314c9c256576\
+    d674df7496ea81d3383b4813d692c6e0e0d5d8e250b98be48e495c1d6089dad1\
+    5dc7d7b46154d6b6ce8ef4ad69b15d4982559b297bcf1885c529f566660e57ec\
+    68edbc3c05726cc02fd4cbf4976eaa9afd5138fe8376435b9fc61d2fc0eb06e3";
+const RFC5054_1536: &str = "9def3cafb939277ab1f12a8617a47bbbdba51df499ac4c80beeea9614b19cc4d\
+    5f4f5f556e27cbde51c6a94be4607a291558903ba0d0f84380b655bb9a22e8dc\
+    df028a7cec67f0d08134b1c8b97989149b609e0be3bab63d47548381dbc5b1fc\
+    764e3f4b53dd9da1158bfd3e2b9c8cf56edf019539349627db2fd53d24b7c486\
+    65772e437d6c7f8ce442734af7ccb7ae837c264ae3a9beb87f8a2fe9b8b5292e\
+    5a021fff5e91479e8ce7a28c2442c6f315180f93499a234dcf76e3fed135f9bb";
+const RFC5054_2048: &str = "ac6bdb41324a9a9bf166de5e1389582faf72b6651987ee07fc3192943db56050\
+    a37329cbb4a099ed8193e0757767a13dd52312ab4b03310dcd7f48a9da04fd50\
+    e8083969edb767b0cf6095179a163ab3661a05fbd5faaae82918a9962f0b93b8\
+    55f97993ec975eeaa80d740adbf4ff747359d041d5c33ea71d281e446b14773b\
+    ca97b43a23fb801676bd207a436c6481f1d2b9078717461a5b9d32e688f87748\
+    544523b524b0d57d5ea77a2775d2ecfa032cfbdbf52fb3786160279004e57ae6\
+    af874e7303ce53299ccc041c7bc308d82a5698f3a8d0c38271ae35f8e9dbfbb6\
+    94b5c803d89f7ae435de236d525f54759b65e372fcd68ef20fa7111f9e4aff73";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SrpGroup {
+    pub N: BigUint,
+    pub g: BigUint,
+}
+
+impl SrpGroup {
+    pub fn rfc5054(bits: usize) -> Option<SrpGroup> {
+        let hex = |h: &str| BigUint::parse_bytes(h.as_bytes(), 16).unwrap();
+        let (N, g) = match bits {
+            1024 => (hex(RFC5054_1024), 2_u32),
+            1536 => (hex(RFC5054_1536), 2),
+            2048 => (hex(RFC5054_2048), 2),
+            3072 => (groups::MODP_3072.p(), 5),
+            4096 => (groups::MODP_4096.p(), 5),
+            6144 => (groups::MODP_6144.p(), 5),
+            8192 => (groups::MODP_8192.p(), 19),
+            _ => return None,
+        };
+        Some(SrpGroup {
+            N,
+            g: BigUint::from(g),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Srp6a {
+    pub group: SrpGroup,
+    pub hash: SrpHash,
+}
+
+impl Srp6a {
+    pub fn new(group: SrpGroup, hash: SrpHash) -> Srp6a {
+        Srp6a { group, hash }
+    }
+
+    fn h(&self, parts: &[&[u8]]) -> Vec<u8> {
+        let data = parts.concat();
+        match self.hash {
+            SrpHash::Sha1 => Sha1::digest_now(&data),
+            SrpHash::Sha256 => Sha256::digest(&data).to_vec(),
+        }
+    }
+
+    /*
+    left padded with zeros to the length of N
+    */
+    fn pad(&self, x: &BigUint) -> Vec<u8> {
+        let len = self.group.N.bits().div_ceil(8) as usize;
+        let bytes = x.to_bytes_be();
+        [vec![0; len.saturating_sub(bytes.len())], bytes].concat()
+    }
+
+    pub fn k(&self) -> BigUint {
+        let N = self.group.N.to_bytes_be();
+        BigUint::from_bytes_be(&self.h(&[&N, &self.pad(&self.group.g)]))
+    }
+
+    pub fn x(&self, salt: &[u8], username: &str, password: &str) -> BigUint {
+        let inner = self.h(&[username.as_bytes(), b":", password.as_bytes()]);
+        BigUint::from_bytes_be(&self.h(&[salt, &inner]))
+    }
+
+    pub fn verifier(&self, salt: &[u8], username: &str, password: &str) -> BigUint {
+        self.group
+            .g
+            .modpow(&self.x(salt, username, password), &self.group.N)
+    }
+
+    pub fn u(&self, A: &BigUint, B: &BigUint) -> BigUint {
+        BigUint::from_bytes_be(&self.h(&[&self.pad(A), &self.pad(B)]))
+    }
+
+    pub fn client_pubkey(&self, a: &BigUint) -> BigUint {
+        self.group.g.modpow(a, &self.group.N)
+    }
+
+    pub fn server_pubkey(&self, v: &BigUint, b: &BigUint) -> BigUint {
+        let N = &self.group.N;
+        (self.k() * v + self.group.g.modpow(b, N)) % N
+    }
+
+    /*
+    S = (B - k g^x) ^ (a + u x), refuses B = 0 mod N and u = 0
+    */
+    pub fn client_premaster(
+        &self,
+        salt: &[u8],
+        username: &str,
+        password: &str,
+        a: &BigUint,
+        B: &BigUint,
+    ) -> Result<BigUint, ()> {
+        let N = &self.group.N;
+        let A = self.client_pubkey(a);
+        let u = self.u(&A, B);
+        if (B % N).is_zero() || u.is_zero() {
+            return Err(());
+        }
+        let x = self.x(salt, username, password);
+        let base = mod_sub(B, &(self.k() * self.group.g.modpow(&x, N)), N);
+        Ok(base.modpow(&(a + u * x), N))
+    }
+
+    /*
+    S = (A v^u) ^ b, refuses A = 0 mod N which would pin S to 0 (cryptopals 37)
+    */
+    pub fn server_premaster(&self, v: &BigUint, b: &BigUint, A: &BigUint) -> Result<BigUint, ()> {
+        let N = &self.group.N;
+        if (A % N).is_zero() {
+            return Err(());
+        }
+        let B = self.server_pubkey(v, b);
+        let u = self.u(A, &B);
+        if u.is_zero() {
+            return Err(());
+        }
+        Ok((A * v.modpow(&u, N)).modpow(b, N))
+    }
+
+    pub fn session_key(&self, S: &BigUint) -> Vec<u8> {
+        self.h(&[&S.to_bytes_be()])
+    }
+
+    pub fn client_proof(
+        &self,
+        username: &str,
+        salt: &[u8],
+        A: &BigUint,
+        B: &BigUint,
+        K: &[u8],
+    ) -> Vec<u8> {
+        let h_N = self.h(&[&self.group.N.to_bytes_be()]);
+        let h_g = self.h(&[&self.group.g.to_bytes_be()]);
+        let h_I = self.h(&[username.as_bytes()]);
+        let xored = crate::xor_arrays(&h_N, &h_g);
+        self.h(&[&xored, &h_I, salt, &A.to_bytes_be(), &B.to_bytes_be(), K])
+    }
+
+    pub fn server_proof(&self, A: &BigUint, M1: &[u8], K: &[u8]) -> Vec<u8> {
+        self.h(&[&A.to_bytes_be(), M1, K])
+    }
+}
+
+#[derive(Clone)]
+struct UserSrp6a {
+    salt: Vec<u8>,
+    v: BigUint,
+}
+
+pub struct Srp6aServer {
+    srp: Srp6a,
+    users: RwLock<HashMap<String, UserSrp6a>>,
+    rng: SharedRng,
+}
+
+impl Srp6aServer {
+    pub fn start(
+        connstring: String,
+        srp: Srp6a,
+        users: HashMap<String, String>,
+    ) -> Result<ServerHandle, ()> {
+        Srp6aServer::start_with_rng(connstring, srp, users, StdRng::from_entropy())
+    }
+
+    pub fn start_with_rng<R: RngCore + Send + 'static>(
+        connstring: String,
+        srp: Srp6a,
+        users: HashMap<String, String>,
+        mut rng: R,
+    ) -> Result<ServerHandle, ()> {
+        let users = users
+            .iter()
+            .map(|(username, password)| {
+                let salt = rng.gen::<[u8; 16]>().to_vec();
+                let v = srp.verifier(&salt, username, password);
+                (username.clone(), UserSrp6a { salt, v })
+            })
+            .collect();
+        let server = Srp6aServer {
+            srp,
+            users: RwLock::new(users),
+            rng: Mutex::new(Box::new(rng)),
+        };
+        serve_commands(connstring, move |stream, command, fields| match command {
+            "AUTH" => server.authenticate(stream, fields),
+            "REGISTER" => server.register(stream, fields),
+            _ => Err(UNKNOWN_COMMAND),
+        })
+    }
+
+    /*
+    the client picks the salt and sends the verifier, the password never
+    leaves it
+    */
+    fn register(
+        &self,
+        stream: &mut TcpStream,
+        mut fields: FieldReader<'_>,
+    ) -> Result<(), &'static str> {
+        let username = fields.read::<String>().map_err(|_| MALFORMED)?;
+        let salt = fields.read::<Vec<u8>>().map_err(|_| MALFORMED)?;
+        let v = fields.read::<BigUint>().map_err(|_| MALFORMED)?;
+        fields.finish().map_err(|_| MALFORMED)?;
+        if (&v % &self.srp.group.N).is_zero() {
+            return Err(MALFORMED);
+        }
+
+        let mut users = self.users.write().unwrap();
+        if users.contains_key(&username) {
+            return Err(USER_EXISTS);
         }
+        users.insert(username, UserSrp6a { salt, v });
+        drop(users);
+        Message::new()
+            .with("OK")
+            .send(stream)
+            .map_err(|_| DISCONNECTED)
+    }
+
+    fn authenticate(
+        &self,
+        stream: &mut TcpStream,
+        mut fields: FieldReader<'_>,
+    ) -> Result<(), &'static str> {
+        let srp = &self.srp;
+        /*
+        read I, A
+        */
+        let username = fields.read::<String>().map_err(|_| MALFORMED)?;
+        let A = fields.read::<BigUint>().map_err(|_| MALFORMED)?;
+        fields.finish().map_err(|_| MALFORMED)?;
+
+        let user = self.users.read().unwrap().get(&username).cloned();
+        let user = user.ok_or(UNKNOWN_USER)?;
+        let b = self.rng.lock().unwrap().gen_biguint_below(&srp.group.N);
+        let S = match srp.server_premaster(&user.v, &b, &A) {
+            Ok(S) => S,
+            Err(_) => return Err("illegal A"),
+        };
+        let B = srp.server_pubkey(&user.v, &b);
+        /*
+        write s, B
+        */
+        Message::new()
+            .with(&user.salt)
+            .with(&B)
+            .send(stream)
+            .map_err(|_| DISCONNECTED)?;
+
+        /*
+        check M1, answer with M2
+        */
+        let M1 = Message::recv(stream)
+            .and_then(|reply| reply.fields().read::<Vec<u8>>())
+            .map_err(|_| MALFORMED)?;
+        let K = srp.session_key(&S);
+        if M1 != srp.client_proof(&username, &user.salt, &A, &B, &K) {
+            return Message::new()
+                .with("FAIL")
+                .send(stream)
+                .map_err(|_| DISCONNECTED);
+        }
+        Message::new()
+            .with(&srp.server_proof(&A, &M1, &K))
+            .send(stream)
+            .map_err(|_| DISCONNECTED)
+    }
+}
+
+pub struct Srp6aClient {}
+
+impl Srp6aClient {
+    pub fn register(connstring: String, srp: &Srp6a, username: String, password: String) -> bool {
+        Srp6aClient::register_with_rng(connstring, srp, username, password, &mut rand::thread_rng())
+    }
+
+    pub fn register_with_rng<R: Rng + ?Sized>(
+        connstring: String,
+        srp: &Srp6a,
+        username: String,
+        password: String,
+        rng: &mut R,
+    ) -> bool {
+        let salt = rng.gen::<[u8; 16]>().to_vec();
+        let v = srp.verifier(&salt, &username, &password);
+        request_ok(
+            &connstring,
+            Message::new()
+                .with("REGISTER")
+                .with(&username)
+                .with(&salt)
+                .with(&v),
+        )
+    }
+
+    /*
+    with `attack` set the client sends that A and assumes S = 0 like the
+    cryptopals 37 client does
+    */
+    pub fn auth(
+        connstring: String,
+        srp: &Srp6a,
+        username: String,
+        password: String,
+        attack: &Option<BigUint>,
+    ) -> bool {
+        Srp6aClient::auth_with_rng(
+            connstring,
+            srp,
+            username,
+            password,
+            attack,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    pub fn auth_with_rng<R: Rng + ?Sized>(
+        connstring: String,
+        srp: &Srp6a,
+        username: String,
+        password: String,
+        attack: &Option<BigUint>,
+        rng: &mut R,
+    ) -> bool {
+        let mut stream = match TcpStream::connect(connstring) {
+            Ok(stream) => stream,
+            Err(_) => return false,
+        };
+        Srp6aClient::exchange(&mut stream, srp, &username, &password, attack, rng).unwrap_or(false)
     }
+
+    fn exchange<R: Rng + ?Sized>(
+        stream: &mut TcpStream,
+        srp: &Srp6a,
+        username: &str,
+        password: &str,
+        attack: &Option<BigUint>,
+        rng: &mut R,
+    ) -> Result<bool, ()> {
+        let a = rng.gen_biguint_below(&srp.group.N);
+        let A = match attack {
+            None => srp.client_pubkey(&a),
+            Some(A) => A.clone(),
+        };
+        Message::new()
+            .with("AUTH")
+            .with(username)
+            .with(&A)
+            .send(stream)?;
+
+        let reply = Message::recv(stream)?;
+        let mut fields = reply.fields();
+        let salt = fields.read::<Vec<u8>>()?;
+        let B = fields.read::<BigUint>()?;
+
+        let S = match attack {
+            None => srp.client_premaster(&salt, username, password, &a, &B)?,
+            Some(_) => BigUint::zero(),
+        };
+        let K = srp.session_key(&S);
+        let M1 = srp.client_proof(username, &salt, &A, &B, &K);
+        Message::new().with(&M1).send(stream)?;
+
+        let M2 = Message::recv(stream)?.fields().read::<Vec<u8>>()?;
+        Ok(M2 == srp.server_proof(&A, &M1, &K))
+    }
+}
+
+#[test]
+fn srp6a_rfc5054_vector_works() {
+    let hex = |h: &str| BigUint::parse_bytes(h.as_bytes(), 16).unwrap();
+    let srp = Srp6a::new(SrpGroup::rfc5054(1024).unwrap(), SrpHash::Sha1);
+    let (I, P) = ("alice", "password123");
+    let s = crate::hex_to_bytes("beb25379d1a8581eb5a727673a2441ee");
+    let a = hex("60975527035cf2ad1989806f0407210bc81edc04e2762a56afd529ddda2d4393");
+    let b = hex("e487cb59d31ac550471e81f00f6928e01dda08e974a004f49e61f5d105284d20");
+
+    assert_eq!(srp.k(), hex("7556aa045aef2cdd07abaf0f665c3e818913186f"));
+    assert_eq!(
+        srp.x(&s, I, P),
+        hex("94b7555aabe9127cc58ccf4993db6cf84d16c124")
+    );
+    let v = srp.verifier(&s, I, P);
+    assert_eq!(
+        v,
+        hex(
+            "7e273de8696ffc4f4e337d05b4b375beb0dde1569e8fa00a9886d8129bada1f1\
+             822223ca1a605b530e379ba4729fdc59f105b4787e5186f5c671085a1447b52a\
+             48cf1970b4fb6f8400bbf4cebfbb168152e08ab5ea53d15c1aff87b2b9da6e04\
+             e058ad51cc72bfc9033b564e26480d78e955a5e29e7ab245db2be315e2099afb"
+        )
+    );
+    let A = srp.client_pubkey(&a);
+    assert_eq!(
+        A,
+        hex(
+            "61d5e490f6f1b79547b0704c436f523dd0e560f0c64115bb72557ec44352e890\
+             3211c04692272d8b2d1a5358a2cf1b6e0bfcf99f921530ec8e39356179eae45e\
+             42ba92aeaced825171e1e8b9af6d9c03e1327f44be087ef06530e69f66615261\
+             eef54073ca11cf5858f0edfdfe15efeab349ef5d76988a3672fac47b0769447b"
+        )
+    );
+    let B = srp.server_pubkey(&v, &b);
+    assert_eq!(
+        B,
+        hex(
+            "bd0c61512c692c0cb6d041fa01bb152d4916a1e77af46ae105393011baf38964\
+             dc46a0670dd125b95a981652236f99d9b681cbf87837ec996c6da04453728610\
+             d0c6ddb58b318885d7d82c7f8deb75ce7bd4fbaa37089e6f9c6059f388838e7a\
+             00030b331eb76840910440b1b27aaeaeeb4012b7d7665238a8e3fb004b117b58"
+        )
+    );
+    assert_eq!(
+        srp.u(&A, &B),
+        hex("ce38b9593487da98554ed47d70a7ae5f462ef019")
+    );
+    let S = hex(
+        "b0dc82babcf30674ae450c0287745e7990a3381f63b387aaf271a10d233861e3\
+             59b48220f7c4693c9ae12b0a6f67809f0876e2d013800d6c41bb59b6d5979b5c\
+             00a172b4a2a5903a0bdcaf8a709585eb2afafa8f3499b200210dcc1f10eb3394\
+             3cd67fc88a2f39a4be5bec4ec0a3212dc346d7e474b29ede8a469ffeca686e5a",
+    );
+    assert_eq!(srp.client_premaster(&s, I, P, &a, &B), Ok(S.clone()));
+    assert_eq!(srp.server_premaster(&v, &b, &A), Ok(S.clone()));
+
+    // the RFC has no vectors for the proofs, so spell them out by hand
+    let sha1 = |parts: &[&[u8]]| Sha1::digest_now(&parts.concat());
+    let K = sha1(&[&S.to_bytes_be()]);
+    assert_eq!(srp.session_key(&S), K);
+    let h_N = sha1(&[&srp.group.N.to_bytes_be()]);
+    let h_g = sha1(&[&srp.group.g.to_bytes_be()]);
+    let xored: Vec<u8> = h_N.iter().zip(h_g.iter()).map(|(n, g)| n ^ g).collect();
+    let M1 = sha1(&[
+        &xored,
+        &sha1(&[I.as_bytes()]),
+        &s,
+        &A.to_bytes_be(),
+        &B.to_bytes_be(),
+        &K,
+    ]);
+    assert_eq!(srp.client_proof(I, &s, &A, &B, &K), M1);
+    assert_ne!(srp.client_proof("bob", &s, &A, &B, &K), M1);
+    let M2 = sha1(&[&A.to_bytes_be(), &M1, &K]);
+    assert_eq!(srp.server_proof(&A, &M1, &K), M2);
+
+    let mut forged = M1.clone();
+    forged[0] ^= 1;
+    assert_ne!(srp.server_proof(&A, &forged, &K), M2);
+}
+
+#[test]
+fn srp6a_rejects_zero_key() {
+    let srp = Srp6a::new(SrpGroup::rfc5054(2048).unwrap(), SrpHash::Sha256);
+    let N = &srp.group.N;
+    let v = srp.verifier(b"salt", "admin", "p@55w0rd");
+    let b = BigUint::from(12345_u32);
+    for A in [BigUint::zero(), N.clone(), N * 2_u32].iter() {
+        assert!(srp.server_premaster(&v, &b, A).is_err());
+    }
+    let a = BigUint::from(54321_u32);
+    assert!(srp
+        .client_premaster(b"salt", "admin", "p@55w0rd", &a, N)
+        .is_err());
+
+    let B = srp.server_pubkey(&v, &b);
+    let A = srp.client_pubkey(&a);
+    assert_eq!(
+        srp.client_premaster(b"salt", "admin", "p@55w0rd", &a, &B),
+        srp.server_premaster(&v, &b, &A)
+    );
+    assert!(SrpGroup::rfc5054(1000).is_none());
+    for bits in [1536, 3072, 4096, 6144, 8192].iter() {
+        assert_eq!(SrpGroup::rfc5054(*bits).unwrap().N.bits() as usize, *bits);
+    }
+}
+
+#[test]
+fn srp_server_registration_works() {
+    let N = groups::MODP_1536.p();
+    let (g, k) = (BigUint::from(2_u32), BigUint::from(3_u32));
+    let server = SRPServer::start(
+        "127.0.0.1:0".to_string(),
+        N.clone(),
+        g.clone(),
+        k.clone(),
+        HashMap::new(),
+    )
+    .unwrap();
+    let connstring = server.connstring();
+    let auth = move |connstring: String, username: &str, password: &str| {
+        SRPClient::auth(
+            connstring,
+            N.clone(),
+            g.clone(),
+            k.clone(),
+            username.to_string(),
+            password.to_string(),
+            &None,
+        )
+    };
+    assert!(!auth(connstring.clone(), "admin", "p@55w0rd"));
+
+    // an unknown user gets told why
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    Message::new()
+        .with("AUTH")
+        .with("admin")
+        .with(&BigUint::from(2_u32))
+        .send(&mut stream)
+        .unwrap();
+    let reply = Message::recv(&mut stream).unwrap();
+    assert_eq!(reply.error_reason(), Some(UNKNOWN_USER.to_string()));
+
+    let register = |username: &str| {
+        SRPClient::register(
+            connstring.clone(),
+            username.to_string(),
+            "p@55w0rd".to_string(),
+        )
+    };
+    assert!(register("admin"));
+    assert!(!register("admin"));
+    assert!(register("guest"));
+
+    let clients: Vec<_> = (0..4)
+        .map(|i| {
+            let connstring = connstring.clone();
+            let username = if i % 2 == 0 { "admin" } else { "guest" };
+            let auth = auth.clone();
+            std::thread::spawn(move || auth(connstring, username, "p@55w0rd"))
+        })
+        .collect();
+    for client in clients {
+        assert!(client.join().unwrap());
+    }
+    assert!(!auth(connstring.clone(), "admin", "wrong"));
+
+    server.shutdown();
+    assert!(!auth(connstring, "admin", "p@55w0rd"));
+
+    let srp = Srp6a::new(SrpGroup::rfc5054(1024).unwrap(), SrpHash::Sha256);
+    let server =
+        Srp6aServer::start("127.0.0.1:0".to_string(), srp.clone(), HashMap::new()).unwrap();
+    let connstring = server.connstring();
+    let (username, password) = ("admin".to_string(), "p@55w0rd".to_string());
+    assert!(!Srp6aClient::auth(
+        connstring.clone(),
+        &srp,
+        username.clone(),
+        password.clone(),
+        &None
+    ));
+    assert!(Srp6aClient::register(
+        connstring.clone(),
+        &srp,
+        username.clone(),
+        password.clone()
+    ));
+    assert!(Srp6aClient::auth(
+        connstring, &srp, username, password, &None
+    ));
 }
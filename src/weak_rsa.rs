@@ -0,0 +1,407 @@
+use num_bigint::{BigUint, RandBigInt, ToBigUint};
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::numtheory::mod_inv;
+use crate::primes::{gen_prime, is_probable_prime, next_prime, primes_below, PrimeKind};
+use crate::rsa::RSA;
+
+/*
+Deliberately broken RSA key generators, each paired with the attack that breaks it.
+Every attack takes only the public key (e, n) and hands back a working private key.
+*/
+
+pub const E_65537: u32 = 65537;
+
+fn e_65537() -> BigUint {
+    E_65537.to_biguint().unwrap()
+}
+
+/*
+q is the next prime after p + delta where delta has only bits/4 bits,
+so p and q share their top half and sqrt(n) lands right between them
+*/
+pub fn gen_close_primes_key<R: Rng + ?Sized>(bits: usize, rng: &mut R) -> RSA {
+    loop {
+        let p = gen_prime(bits, PrimeKind::Random, rng);
+        let delta = rng.gen_biguint((bits / 4) as u64);
+        let q = next_prime(&(&p + delta + 2_u32));
+        if let Ok(rsa) = RSA::from_primes(&p, &q, &e_65537()) {
+            return rsa;
+        }
+    }
+}
+
+/*
+d < n^(1/4) / 3, which is exactly what Wiener's attack needs
+*/
+pub fn gen_small_d_key<R: Rng + ?Sized>(bits: usize, rng: &mut R) -> RSA {
+    let big_1 = BigUint::one();
+    loop {
+        let p = gen_prime(bits, PrimeKind::Random, rng);
+        let q = gen_prime(bits, PrimeKind::Random, rng);
+        let n = &p * &q;
+        let et = (&p - &big_1) * (&q - &big_1);
+
+        let d_bits = (n.bits() / 4).saturating_sub(2);
+        let mut d = rng.gen_biguint(d_bits);
+        d.set_bit(0, true);
+        if let Some(e) = mod_inv(&d, &et) {
+            return RSA::from_private(&e, &d, &n);
+        }
+    }
+}
+
+/*
+a corpus of `count` keys where the first `shared` (after shuffling, random ones)
+reuse the same p, like keys from devices that booted with the same entropy
+*/
+pub fn gen_shared_prime_corpus<R: Rng + ?Sized>(
+    bits: usize,
+    count: usize,
+    shared: usize,
+    rng: &mut R,
+) -> Vec<RSA> {
+    assert!(shared <= count);
+    let common_p = gen_prime(bits, PrimeKind::Random, rng);
+    let mut keys = Vec::with_capacity(count);
+    while keys.len() < count {
+        let p = if keys.len() < shared {
+            common_p.clone()
+        } else {
+            gen_prime(bits, PrimeKind::Random, rng)
+        };
+        let q = gen_prime(bits, PrimeKind::Random, rng);
+        if let Ok(rsa) = RSA::from_primes(&p, &q, &e_65537()) {
+            keys.push(rsa);
+        }
+    }
+    keys.shuffle(rng);
+    keys
+}
+
+/*
+p - 1 is 2 times primes below `smoothness` picked at random, repeats allowed,
+so p has very little entropy and falls to Pollard's p-1. Err with fewer than
+two odd primes below `smoothness`, powers of 3 alone give one candidate per
+size and that one is hardly ever prime.
+*/
+pub fn gen_smooth_key<R: Rng + ?Sized>(
+    bits: usize,
+    smoothness: u64,
+    rng: &mut R,
+) -> Result<RSA, ()> {
+    // skip 2, it is added once explicitly
+    let small: Vec<u64> = primes_below(smoothness).into_iter().skip(1).collect();
+    if small.len() < 2 {
+        return Err(());
+    }
+    let p = loop {
+        let mut p_minus_1 = 2.to_biguint().unwrap();
+        while (p_minus_1.bits() as usize) < bits {
+            p_minus_1 *= *small.choose(rng).unwrap();
+        }
+        let candidate = p_minus_1 + 1_u32;
+        if candidate.bits() as usize == bits && is_probable_prime(&candidate) {
+            break candidate;
+        }
+    };
+    loop {
+        let q = gen_prime(bits, PrimeKind::Random, rng);
+        if let Ok(rsa) = RSA::from_primes(&p, &q, &e_65537()) {
+            return Ok(rsa);
+        }
+    }
+}
+
+/*
+one factor is only `small_bits` wide, in reach of Pollard's rho
+*/
+pub fn gen_unbalanced_key<R: Rng + ?Sized>(bits: usize, small_bits: usize, rng: &mut R) -> RSA {
+    loop {
+        let p = gen_prime(small_bits, PrimeKind::Random, rng);
+        let q = gen_prime(bits * 2 - small_bits, PrimeKind::Random, rng);
+        if let Ok(rsa) = RSA::from_primes(&p, &q, &e_65537()) {
+            return rsa;
+        }
+    }
+}
+
+fn key_from_factor(pubkey: &(BigUint, BigUint), p: &BigUint) -> Option<RSA> {
+    let (e, n) = pubkey;
+    if p.is_zero() || p.is_one() || p == n || !(n % p).is_zero() {
+        return None;
+    }
+    RSA::from_primes(p, &(n / p), e).ok()
+}
+
+pub fn fermat_factor(pubkey: &(BigUint, BigUint), max_steps: usize) -> Option<RSA> {
+    let n = &pubkey.1;
+    let mut a = n.sqrt();
+    if &(&a * &a) < n {
+        a += 1_u32;
+    }
+    for _ in 0..max_steps {
+        let b2 = &a * &a - n;
+        let b = b2.sqrt();
+        if b2 == &b * &b {
+            return key_from_factor(pubkey, &(&a - &b));
+        }
+        a += 1_u32;
+    }
+    None
+}
+
+/*
+walks the continued fraction convergents k/d of e/n, one of them is the real k/d
+when d is small enough
+*/
+pub fn wiener_attack(pubkey: &(BigUint, BigUint)) -> Option<RSA> {
+    let (e, n) = pubkey;
+    let big_1 = BigUint::one();
+
+    let (mut num, mut den) = (e.clone(), n.clone());
+    let (mut h_prev, mut h) = (BigUint::zero(), BigUint::one());
+    let (mut k_prev, mut k) = (BigUint::one(), BigUint::zero());
+    while !den.is_zero() {
+        let (a, rem) = num.div_rem(&den);
+        let h_next = &a * &h + &h_prev;
+        let k_next = &a * &k + &k_prev;
+        h_prev = std::mem::replace(&mut h, h_next);
+        k_prev = std::mem::replace(&mut k, k_next);
+        num = std::mem::replace(&mut den, rem);
+
+        // h is the guess for the secret k, k the guess for d
+        let (guess_k, guess_d) = (&h, &k);
+        if guess_k.is_zero() {
+            continue;
+        }
+        let (et, rem) = (e * guess_d - &big_1).div_rem(guess_k);
+        if !rem.is_zero() || &et >= n {
+            continue;
+        }
+        // p + q = n - et + 1, p and q are the roots of x^2 - (p+q)x + n
+        let s = n - &et + &big_1;
+        let s2 = &s * &s;
+        let n4 = n << 2;
+        if s2 < n4 {
+            continue;
+        }
+        let disc = s2 - n4;
+        let r = disc.sqrt();
+        if &r * &r != disc {
+            continue;
+        }
+        if let Some(rsa) = key_from_factor(pubkey, &((&s + &r) >> 1)) {
+            return Some(rsa);
+        }
+    }
+    None
+}
+
+/*
+Bernstein's batch gcd: product tree over all moduli, then a remainder tree of
+P mod n_i^2, gcd(n_i, (P mod n_i^2) / n_i) is the prime shared with someone else
+*/
+pub fn batch_gcd(pubkeys: &[(BigUint, BigUint)]) -> Vec<(usize, RSA)> {
+    if pubkeys.is_empty() {
+        return vec![];
+    }
+    let mut tree = vec![pubkeys.iter().map(|(_, n)| n.clone()).collect::<Vec<_>>()];
+    while tree.last().unwrap().len() > 1 {
+        let level = tree
+            .last()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| pair.iter().product::<BigUint>())
+            .collect::<Vec<_>>();
+        tree.push(level);
+    }
+
+    let mut remainders = tree.pop().unwrap();
+    while let Some(level) = tree.pop() {
+        remainders = level
+            .iter()
+            .enumerate()
+            .map(|(i, node)| &remainders[i / 2] % (node * node))
+            .collect();
+    }
+
+    let mut recovered = vec![];
+    for (i, ((_, n), rem)) in pubkeys.iter().zip(remainders.iter()).enumerate() {
+        let mut g = n.gcd(&(rem / n));
+        if &g == n {
+            // both primes are shared, fall back to pairwise gcds for this one
+            g = pubkeys
+                .iter()
+                .map(|(_, other)| n.gcd(other))
+                .find(|g| !g.is_one() && g != n)
+                .unwrap_or_else(BigUint::one);
+        }
+        if let Some(rsa) = key_from_factor(&pubkeys[i], &g) {
+            recovered.push((i, rsa));
+        }
+    }
+    recovered
+}
+
+/*
+a = 2^(product of all prime powers <= bound) mod n, gcd(a - 1, n) is p
+when p - 1 is bound-smooth
+*/
+pub fn pollard_p_minus_1(pubkey: &(BigUint, BigUint), bound: u64) -> Option<RSA> {
+    let n = &pubkey.1;
+    let mut a = 2.to_biguint().unwrap();
+    for (i, q) in primes_below(bound).into_iter().enumerate() {
+        let mut q_power = q;
+        while q_power * q <= bound {
+            q_power *= q;
+        }
+        a = a.modpow(&q_power.to_biguint().unwrap(), n);
+
+        if i % 64 == 0 {
+            let g = (&a - 1_u32).gcd(n);
+            if &g == n {
+                return None;
+            }
+            if !g.is_one() {
+                return key_from_factor(pubkey, &g);
+            }
+        }
+    }
+    let g = (&a - 1_u32).gcd(n);
+    key_from_factor(pubkey, &g)
+}
+
+// steps of rho between two gcds
+const RHO_BATCH: usize = 128;
+
+/*
+Brent's variant of rho, the gcd is taken over a product of many |x - y| at
+once. A run whose gcd comes out as n starts over with a fresh c.
+*/
+pub fn pollard_rho(pubkey: &(BigUint, BigUint), max_iterations: usize) -> Option<RSA> {
+    pollard_rho_with_rng(pubkey, max_iterations, &mut rand::thread_rng())
+}
+
+pub fn pollard_rho_with_rng<R: Rng + ?Sized>(
+    pubkey: &(BigUint, BigUint),
+    max_iterations: usize,
+    rng: &mut R,
+) -> Option<RSA> {
+    let n = &pubkey.1;
+    let mut iterations = 0;
+    while iterations < max_iterations {
+        let c = rng.gen_biguint_below(n);
+        let y = rng.gen_biguint_below(n);
+        let g = brent_rho(n, &c, y, &mut iterations, max_iterations)?;
+        if &g != n {
+            return key_from_factor(pubkey, &g);
+        }
+    }
+    None
+}
+
+/*
+one run of x -> x^2 + c from y, None once the iterations are used up. When a
+batch multiplies up to 0 mod n its steps are redone one gcd at a time from
+the y saved before it.
+*/
+fn brent_rho(
+    n: &BigUint,
+    c: &BigUint,
+    mut y: BigUint,
+    iterations: &mut usize,
+    max_iterations: usize,
+) -> Option<BigUint> {
+    let f = |x: &BigUint| (x * x + c) % n;
+    let distance = |x: &BigUint, y: &BigUint| if x > y { x - y } else { y - x };
+
+    let mut x = y.clone();
+    let mut saved = y.clone();
+    let mut g = BigUint::one();
+    let mut r = 1;
+    while g.is_one() {
+        if *iterations >= max_iterations {
+            return None;
+        }
+        x = y.clone();
+        for _ in 0..r {
+            y = f(&y);
+        }
+        let mut k = 0;
+        while k < r && g.is_one() {
+            saved = y.clone();
+            let mut product = BigUint::one();
+            for _ in 0..RHO_BATCH.min(r - k) {
+                y = f(&y);
+                product = product * distance(&x, &y) % n;
+            }
+            g = product.gcd(n);
+            k += RHO_BATCH;
+            *iterations += RHO_BATCH;
+        }
+        r *= 2;
+    }
+    if &g == n {
+        loop {
+            saved = f(&saved);
+            g = distance(&x, &saved).gcd(n);
+            if !g.is_one() {
+                break;
+            }
+        }
+    }
+    Some(g)
+}
+
+#[test]
+fn weak_rsa_attacks_work() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let m = 0xdead_beef_u32.to_biguint().unwrap();
+    let roundtrip = |original: &RSA, recovered: &RSA| {
+        assert_eq!(original.n, recovered.n);
+        assert_eq!(recovered.decrypt(&original.encrypt(&m)), m);
+    };
+
+    let key = gen_close_primes_key(256, &mut rng);
+    roundtrip(&key, &fermat_factor(&key.pubkey, 1000).unwrap());
+
+    let key = gen_small_d_key(256, &mut rng);
+    roundtrip(&key, &wiener_attack(&key.pubkey).unwrap());
+
+    let keys = gen_shared_prime_corpus(128, 8, 3, &mut rng);
+    let pubkeys = keys.iter().map(|k| k.pubkey.clone()).collect::<Vec<_>>();
+    let recovered = batch_gcd(&pubkeys);
+    assert_eq!(recovered.len(), 3);
+    for (i, rsa) in recovered.iter() {
+        roundtrip(&keys[*i], rsa);
+    }
+
+    let key = gen_smooth_key(256, 1 << 12, &mut rng).unwrap();
+    roundtrip(&key, &pollard_p_minus_1(&key.pubkey, 1 << 12).unwrap());
+    assert!(gen_smooth_key(256, 3, &mut rng).is_err());
+    assert!(gen_smooth_key(256, 5, &mut rng).is_err());
+    // the distinct primes below 100 only make about 120 bits, repeats do the rest
+    let key = gen_smooth_key(512, 100, &mut rng).unwrap();
+    assert!(key.pubkey.1.bits() >= 1023);
+
+    let key = gen_unbalanced_key(128, 32, &mut rng);
+    roundtrip(
+        &key,
+        &pollard_rho_with_rng(&key.pubkey, 1 << 24, &mut rng).unwrap(),
+    );
+    // with factors this close in size both cycles tend to close in the same batch
+    for _ in 0..20 {
+        let key = gen_unbalanced_key(20, 20, &mut rng);
+        roundtrip(
+            &key,
+            &pollard_rho_with_rng(&key.pubkey, 1 << 20, &mut rng).unwrap(),
+        );
+    }
+}
@@ -1,16 +1,28 @@
 #![allow(non_snake_case)]
 
 use std::collections::HashMap;
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::thread;
+use std::net::TcpStream;
+use std::sync::{Mutex, RwLock};
 
 use num_bigint::{BigUint, RandBigInt, ToBigUint};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 use sha2::{Digest, Sha256};
 
 use crate::dh::DiffieHellmanState;
-use crate::{bytes_to_hexbytes, hexbytes_to_bytes};
-
+use crate::framing::{FieldReader, Message};
+use crate::offline_cracker::{crack, Rule, WeakenedSrpTranscript};
+use crate::server::{
+    serve_commands, ServerHandle, DISCONNECTED, MALFORMED, UNKNOWN_COMMAND, UNKNOWN_USER,
+    USER_EXISTS,
+};
+use crate::srp::{request_ok, SharedRng};
+
+// what the evil server mangles and tries, see offline_cracker for real wordlists
+const WORDLIST: [&str; 5] = ["alpha", "beta", "gamma", "password", "zulu"];
+const RULES: [Rule; 3] = [Rule::Capitalize, Rule::Leet, Rule::AppendDigits(1)];
+
+#[derive(Clone)]
 struct UserSRP {
     v: BigUint,
     salt: BigUint,
@@ -20,8 +32,9 @@ pub struct WeakenedSRPServer {
     N: BigUint,
     g: BigUint,
     k: BigUint,
-    users_srp: HashMap<String, UserSRP>,
+    users_srp: RwLock<HashMap<String, UserSRP>>,
     attack: bool,
+    rng: SharedRng,
 }
 
 impl WeakenedSRPServer {
@@ -32,30 +45,81 @@ impl WeakenedSRPServer {
         k: BigUint,
         users: HashMap<String, String>,
         attack: bool,
-    ) {
+    ) -> Result<ServerHandle, ()> {
+        WeakenedSRPServer::start_with_rng(
+            connstring,
+            N,
+            g,
+            k,
+            users,
+            attack,
+            StdRng::from_entropy(),
+        )
+    }
+
+    pub fn start_with_rng<R: RngCore + Send + 'static>(
+        connstring: String,
+        N: BigUint,
+        g: BigUint,
+        k: BigUint,
+        users: HashMap<String, String>,
+        attack: bool,
+        mut rng: R,
+    ) -> Result<ServerHandle, ()> {
         let mut users_srp: HashMap<String, UserSRP> = HashMap::new();
         for (username, password) in users.iter() {
-            let srp_params = WeakenedSRPServer::calc_user_srp(&g, &N, password);
+            let srp_params = WeakenedSRPServer::calc_user_srp(&g, &N, password, &mut rng);
             users_srp.insert(username.to_string(), srp_params);
         }
         let server = WeakenedSRPServer {
             N,
             g,
             k,
-            users_srp,
+            users_srp: RwLock::new(users_srp),
             attack,
+            rng: Mutex::new(Box::new(rng)),
         };
-        thread::spawn(move || {
-            let listener = TcpListener::bind(connstring).unwrap();
-            for stream in listener.incoming() {
-                server.handle_connection(stream.unwrap());
-            }
-        });
+        serve_commands(connstring, move |stream, command, fields| match command {
+            "AUTH" => server.authenticate(stream, fields),
+            "REGISTER" => server.register(stream, fields),
+            _ => Err(UNKNOWN_COMMAND),
+        })
+    }
+
+    fn register(
+        &self,
+        stream: &mut TcpStream,
+        mut fields: FieldReader<'_>,
+    ) -> Result<(), &'static str> {
+        let username = fields.read::<String>().map_err(|_| MALFORMED)?;
+        let password = fields.read::<String>().map_err(|_| MALFORMED)?;
+        fields.finish().map_err(|_| MALFORMED)?;
+
+        let srp_params = WeakenedSRPServer::calc_user_srp(
+            &self.g,
+            &self.N,
+            &password,
+            &mut *self.rng.lock().unwrap(),
+        );
+        let mut users_srp = self.users_srp.write().unwrap();
+        if users_srp.contains_key(&username) {
+            return Err(USER_EXISTS);
+        }
+        users_srp.insert(username, srp_params);
+        drop(users_srp);
+        Message::new()
+            .with("OK")
+            .send(stream)
+            .map_err(|_| DISCONNECTED)
     }
 
-    fn handle_connection(&self, mut stream: TcpStream) {
+    fn authenticate(
+        &self,
+        stream: &mut TcpStream,
+        mut fields: FieldReader<'_>,
+    ) -> Result<(), &'static str> {
         let dh = if !self.attack {
-            DiffieHellmanState::new(&self.g, &self.N)
+            DiffieHellmanState::new_with_rng(&self.g, &self.N, &mut *self.rng.lock().unwrap())
         } else {
             let secret = 2.to_biguint().unwrap();
             DiffieHellmanState::new_static(&self.g, &self.N, &secret)
@@ -65,81 +129,63 @@ impl WeakenedSRPServer {
         read username+DH_pubkey
         */
 
-        let mut buffer = [0; 1024];
-        let read_bytes = stream.read(&mut buffer).unwrap();
-        let data = buffer[..read_bytes].to_vec();
-        let mut tokens = data.splitn(2, |x| x == &0x00_u8);
-
-        let username = String::from_utf8(tokens.next().unwrap().to_vec()).unwrap();
-        let A = BigUint::from_bytes_be(&hexbytes_to_bytes(&tokens.next().unwrap()));
+        let username = fields.read::<String>().map_err(|_| MALFORMED)?;
+        let A = fields.read::<BigUint>().map_err(|_| MALFORMED)?;
+        fields.finish().map_err(|_| MALFORMED)?;
 
-        let user_srp = self.users_srp.get(&username).unwrap();
+        let user_srp = self.users_srp.read().unwrap().get(&username).cloned();
+        let user_srp = user_srp.ok_or(UNKNOWN_USER)?;
         let user_v = &user_srp.v;
         let user_salt = &user_srp.salt;
 
         let B = &dh.pubkey;
 
-        let u = rand::thread_rng().gen_biguint_below(&self.N);
+        let u = self.rng.lock().unwrap().gen_biguint_below(&self.N);
 
         /*
         attack: write faked values
         */
 
-        let payload = if !self.attack {
-            vec![
-                bytes_to_hexbytes(&user_salt.to_bytes_be()),
-                [0x00].to_vec(),
-                bytes_to_hexbytes(&B.to_bytes_be()), // B
-                [0x00].to_vec(),
-                bytes_to_hexbytes(&u.to_bytes_be()), // u
-            ]
-            .concat()
+        let reply = if !self.attack {
+            Message::new().with(user_salt).with(B).with(&u)
         } else {
-            vec![
-                bytes_to_hexbytes(&[0]), // salt
-                [0x00].to_vec(),
-                bytes_to_hexbytes(&B.to_bytes_be()), // B
-                [0x00].to_vec(),
-                bytes_to_hexbytes(&[1]), // u
-            ]
-            .concat()
+            Message::new()
+                .with(&BigUint::from(0_u32)) // salt
+                .with(B)
+                .with(&BigUint::from(1_u32)) // u
         };
-        stream.write_all(&payload).unwrap();
+        reply.send(stream).map_err(|_| DISCONNECTED)?;
 
         /*
         read KEX result from client
         */
 
-        let mut buffer = [0; 1024];
-        let read_bytes = stream.read(&mut buffer).unwrap();
-        let client_hmac = hexbytes_to_bytes(&buffer[..read_bytes]);
+        let client_hmac = Message::recv(stream)
+            .and_then(|reply| reply.fields().read::<Vec<u8>>())
+            .map_err(|_| MALFORMED)?;
 
         /*
         mount a brute-force crack attack
         */
 
         if self.attack {
-            for password in ["alpha", "beta", "gamma", "p@55w0rd", "zulu"].iter() {
-                /*
-                the client sends us his HMAC, let's see if we can find a passwordd
-                that generates that HMAC - by doing our side of the calculation with
-                v's derived from different passwords
-                */
-
-                let fake_u: &[u8] = &[1];
-                let fake_salt: &[u8] = &[0];
-                let x: &BigUint = &BigUint::from_bytes_be(&Sha256::digest(
-                    &vec![fake_salt, &password.as_bytes()].concat(),
-                ));
-                let v = &self.g.modpow(x, &self.N);
-
-                let S = (&A * v.modpow(&BigUint::from_bytes_be(fake_u), &self.N))
-                    .modpow(&dh.secret, &self.N);
-                let K: &[u8] = &Sha256::digest(&S.to_bytes_be());
-                let guessed_hmac: &[u8] = &Sha256::digest(&vec![K, fake_salt].concat());
-                if guessed_hmac == client_hmac {
-                    println!("\n!!!!cracked password: {}\n", &password);
-                }
+            let transcript = WeakenedSrpTranscript {
+                N: self.N.clone(),
+                g: self.g.clone(),
+                A: A.clone(),
+                b: dh.secret.clone(),
+                salt: vec![0],
+                u: BigUint::from(1_u32),
+                hmac: client_hmac.clone(),
+            };
+            let report = crack(
+                WORDLIST.iter().map(|word| word.to_string()),
+                &RULES,
+                &transcript,
+                4,
+            );
+            if let Some(password) = &report.password {
+                println!("\n!!!!cracked password: {}\n", password);
             }
         }
 
@@ -151,15 +197,24 @@ impl WeakenedSRPServer {
         let K: &[u8] = &Sha256::digest(&S.to_bytes_be());
 
         let server_hmac = Sha256::digest(&vec![K, &user_salt.to_bytes_be()].concat());
-        if client_hmac == server_hmac.as_slice() {
-            stream.write_all(b"OK").unwrap();
+        let result = if client_hmac == server_hmac.as_slice() {
+            "OK"
         } else {
-            stream.write_all(b"FAIL").unwrap();
-        }
+            "FAIL"
+        };
+        Message::new()
+            .with(result)
+            .send(stream)
+            .map_err(|_| DISCONNECTED)
     }
 
-    fn calc_user_srp(g: &BigUint, N: &BigUint, password: &str) -> UserSRP {
-        let salt = rand::thread_rng().gen_biguint_below(N);
+    fn calc_user_srp<R: Rng + ?Sized>(
+        g: &BigUint,
+        N: &BigUint,
+        password: &str,
+        rng: &mut R,
+    ) -> UserSRP {
+        let salt = rng.gen_biguint_below(N);
 
         let mut hasher = Sha256::new();
         hasher.update(salt.to_bytes_be());
@@ -175,6 +230,16 @@ impl WeakenedSRPServer {
 pub struct WeakenedSRPClient {}
 
 impl WeakenedSRPClient {
+    pub fn register(connstring: String, username: String, password: String) -> bool {
+        request_ok(
+            &connstring,
+            Message::new()
+                .with("REGISTER")
+                .with(&username)
+                .with(&password),
+        )
+    }
+
     pub fn auth(
         connstring: String,
         N: BigUint,
@@ -183,37 +248,62 @@ impl WeakenedSRPClient {
         username: String,
         password: String,
     ) -> bool {
-        let mut stream = TcpStream::connect(connstring).unwrap();
+        WeakenedSRPClient::auth_with_rng(
+            connstring,
+            N,
+            g,
+            k,
+            username,
+            password,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    pub fn auth_with_rng<R: Rng + ?Sized>(
+        connstring: String,
+        N: BigUint,
+        g: BigUint,
+        k: BigUint,
+        username: String,
+        password: String,
+        rng: &mut R,
+    ) -> bool {
+        let mut stream = match TcpStream::connect(connstring) {
+            Ok(stream) => stream,
+            Err(_) => return false,
+        };
 
         /*
         send username and DH pubkey
         */
 
-        let dh = DiffieHellmanState::new(&g, &N);
+        let dh = DiffieHellmanState::new_with_rng(&g, &N, rng);
         let A = dh.pubkey;
-        stream
-            .write_all(
-                &vec![
-                    username.as_bytes(),
-                    &[0x00],
-                    &bytes_to_hexbytes(&A.to_bytes_be()),
-                ]
-                .concat(),
-            )
-            .unwrap();
+        if Message::new()
+            .with("AUTH")
+            .with(&username)
+            .with(&A)
+            .send(&mut stream)
+            .is_err()
+        {
+            return false;
+        }
 
         /*
         read salt+B from server
         */
 
-        let mut buffer = [0; 2048];
-        let read_bytes = stream.read(&mut buffer).unwrap();
-        let data = buffer[..read_bytes].to_vec();
-        let mut tokens = data.splitn(3, |x| x == &0x00_u8);
-
-        let server_salt = BigUint::from_bytes_be(&hexbytes_to_bytes(&tokens.next().unwrap()));
-        let B = BigUint::from_bytes_be(&hexbytes_to_bytes(&tokens.next().unwrap()));
-        let u = BigUint::from_bytes_be(&hexbytes_to_bytes(&tokens.next().unwrap()));
+        let (server_salt, B, u) = match Message::recv(&mut stream).and_then(|reply| {
+            let mut fields = reply.fields();
+            Ok((
+                fields.read::<BigUint>()?,
+                fields.read::<BigUint>()?,
+                fields.read::<BigUint>()?,
+            ))
+        }) {
+            Ok(values) => values,
+            Err(_) => return false,
+        };
 
         /*
         compute stuff
@@ -231,14 +321,17 @@ impl WeakenedSRPClient {
         */
 
         let hmac: &[u8] = &Sha256::digest(&vec![K, &server_salt.to_bytes_be()].concat());
-        stream.write_all(&bytes_to_hexbytes(hmac)).unwrap();
+        if Message::new().with(hmac).send(&mut stream).is_err() {
+            return false;
+        }
 
         /*
         read final "OK" or "FAIL"
         */
 
-        let mut buffer = [0_u8; 2048];
-        let _ = stream.read(&mut buffer).unwrap();
-        buffer[0] == 'O' as u8
+        match Message::recv(&mut stream).and_then(|reply| reply.fields().read::<String>()) {
+            Ok(result) => result == "OK",
+            Err(_) => false,
+        }
     }
 }
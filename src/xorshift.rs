@@ -0,0 +1,309 @@
+use rand::{Error, RngCore, SeedableRng};
+
+use crate::gf2::{BitRow, Solver};
+
+/*
+xorshift128+ the way V8 runs it behind Math.random(). The generator proper
+outputs state0 + state1 after a step, V8 instead turns the top 52 bits of
+state0 into a double in [0, 1). Everything but the sum is linear over GF(2) in
+the 128 state bits, so the doubles are what we recover the state from.
+*/
+
+// V8 fills this many doubles at once and hands them out last one first
+pub const CACHE_SIZE: usize = 64;
+
+const STATE_BITS: usize = 128;
+const MANTISSA_BITS: u32 = 52;
+
+// MurmurHash3's finalizer, V8 runs the seed through it
+fn murmur3(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xC4CE_B9FE_1A85_EC53);
+    h ^ (h >> 33)
+}
+
+fn to_double(state0: u64) -> f64 {
+    f64::from_bits(state0 >> (64 - MANTISSA_BITS) | 0x3FF0_0000_0000_0000) - 1.0
+}
+
+// the top 52 bits of state0 back from a to_double() value
+fn from_double(double: f64) -> Option<u64> {
+    if !(0.0..1.0).contains(&double) {
+        return None;
+    }
+    Some((double + 1.0).to_bits() & ((1 << MANTISSA_BITS) - 1))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Xorshift128Plus {
+    state0: u64,
+    state1: u64,
+}
+
+impl Xorshift128Plus {
+    // what node --random_seed=seed starts Math.random() with
+    pub fn from_seed(seed: u64) -> Xorshift128Plus {
+        Xorshift128Plus::from_state(murmur3(seed), murmur3(!seed))
+    }
+
+    pub fn from_state(state0: u64, state1: u64) -> Xorshift128Plus {
+        Xorshift128Plus { state0, state1 }
+    }
+
+    pub fn state(&self) -> (u64, u64) {
+        (self.state0, self.state1)
+    }
+
+    fn step(&mut self) {
+        let mut s1 = self.state0;
+        let s0 = self.state1;
+        self.state0 = s0;
+        s1 ^= s1 << 23;
+        s1 ^= s1 >> 17;
+        s1 ^= s0;
+        s1 ^= s0 >> 26;
+        self.state1 = s1;
+    }
+
+    pub fn extract_number(&mut self) -> u64 {
+        self.step();
+        self.state0.wrapping_add(self.state1)
+    }
+
+    /*
+    one step turned into a double the way V8 fills its cache
+    */
+    pub fn next_double(&mut self) -> f64 {
+        self.step();
+        to_double(self.state0)
+    }
+
+    /*
+    A generator that continues after the given next_double() values, three
+    usually pin the state down. Math.random() hands them out in a different
+    order, see MathRandom::clone_from_outputs.
+    */
+    pub fn clone_from_doubles(doubles: &[f64]) -> Result<Xorshift128Plus, ()> {
+        let steps: Vec<(usize, f64)> = doubles.iter().cloned().enumerate().collect();
+        let (state0, state1) = recover_state(&steps)?;
+        let mut rng = Xorshift128Plus::from_state(state0, state1);
+        for &double in doubles {
+            if rng.next_double() != double {
+                return Err(());
+            }
+        }
+        Ok(rng)
+    }
+}
+
+/*
+The state bits are variables 0..64 for state0 and 64..128 for state1, a
+symbolic word keeps the row for each of its bits.
+*/
+type Word = Vec<BitRow>;
+
+fn symbolic_word(offset: usize) -> Word {
+    (0..64)
+        .map(|i| BitRow::unit(STATE_BITS, offset + i))
+        .collect()
+}
+
+fn shl(word: &[BitRow], n: usize) -> Word {
+    (0..64)
+        .map(|i| {
+            if i >= n {
+                word[i - n].clone()
+            } else {
+                BitRow::zero(STATE_BITS)
+            }
+        })
+        .collect()
+}
+
+fn shr(word: &[BitRow], n: usize) -> Word {
+    (0..64)
+        .map(|i| {
+            if i + n < 64 {
+                word[i + n].clone()
+            } else {
+                BitRow::zero(STATE_BITS)
+            }
+        })
+        .collect()
+}
+
+fn xor(a: &[BitRow], b: &[BitRow]) -> Word {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| {
+            let mut bit = a.clone();
+            bit ^= b;
+            bit
+        })
+        .collect()
+}
+
+/*
+the state before step 0, given the double produced by some of the steps after it
+*/
+fn recover_state(steps: &[(usize, f64)]) -> Result<(u64, u64), ()> {
+    let mut state0 = symbolic_word(0);
+    let mut state1 = symbolic_word(64);
+    let mut solver = Solver::new(STATE_BITS);
+    let last = steps.iter().map(|&(step, _)| step).max().ok_or(())?;
+    for step in 0..=last {
+        let s1 = xor(&state0, &shl(&state0, 23));
+        let s1 = xor(&s1, &shr(&s1, 17));
+        let s1 = xor(&xor(&s1, &state1), &shr(&state1, 26));
+        state0 = std::mem::replace(&mut state1, s1);
+
+        for &(_, double) in steps.iter().filter(|&&(at, _)| at == step) {
+            let mantissa = from_double(double).ok_or(())?;
+            for j in 0..MANTISSA_BITS as usize {
+                let bit = 64 - MANTISSA_BITS as usize + j;
+                solver.add(state0[bit].clone(), mantissa >> j & 1 == 1);
+            }
+        }
+    }
+    let x = solver.solve().ok_or(())?;
+    let word = |offset: usize| (0..64).fold(0, |w, i| w | (x.get(offset + i) as u64) << i);
+    Ok((word(0), word(64)))
+}
+
+/*
+Math.random(): CACHE_SIZE doubles are made at once and given out from the
+last one back.
+*/
+#[derive(Clone, Debug)]
+pub struct MathRandom {
+    rng: Xorshift128Plus,
+    cache: Vec<f64>,
+}
+
+impl MathRandom {
+    pub fn new(rng: Xorshift128Plus) -> MathRandom {
+        MathRandom { rng, cache: vec![] }
+    }
+
+    pub fn from_seed(seed: u64) -> MathRandom {
+        MathRandom::new(Xorshift128Plus::from_seed(seed))
+    }
+
+    pub fn random(&mut self) -> f64 {
+        if self.cache.is_empty() {
+            for _ in 0..CACHE_SIZE {
+                let double = self.rng.next_double();
+                self.cache.push(double);
+            }
+        }
+        self.cache.pop().unwrap()
+    }
+
+    /*
+    A MathRandom that continues after the given consecutive Math.random()
+    values. Where they start in a cache isn't known, so every position is
+    tried, the wrong ones don't solve or don't reproduce the outputs.
+    */
+    pub fn clone_from_outputs(outputs: &[f64]) -> Result<MathRandom, ()> {
+        for taken in 0..CACHE_SIZE {
+            // output i came from step (block * CACHE_SIZE + CACHE_SIZE - 1 - position)
+            let steps: Vec<(usize, f64)> = outputs
+                .iter()
+                .enumerate()
+                .map(|(i, &output)| {
+                    let (block, position) = ((taken + i) / CACHE_SIZE, (taken + i) % CACHE_SIZE);
+                    (block * CACHE_SIZE + CACHE_SIZE - 1 - position, output)
+                })
+                .collect();
+            let (state0, state1) = match recover_state(&steps) {
+                Ok(state) => state,
+                Err(()) => continue,
+            };
+            let mut clone = MathRandom::new(Xorshift128Plus::from_state(state0, state1));
+            for _ in 0..taken {
+                clone.random();
+            }
+            if outputs.iter().all(|&output| clone.random() == output) {
+                return Ok(clone);
+            }
+        }
+        Err(())
+    }
+}
+
+impl RngCore for Xorshift128Plus {
+    fn next_u32(&mut self) -> u32 {
+        // the high half
+        (self.extract_number() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.extract_number()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.extract_number().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for Xorshift128Plus {
+    type Seed = [u8; 16];
+
+    // the raw state, state0 first, both little-endian
+    fn from_seed(seed: [u8; 16]) -> Xorshift128Plus {
+        let mut state0 = [0_u8; 8];
+        let mut state1 = [0_u8; 8];
+        state0.copy_from_slice(&seed[..8]);
+        state1.copy_from_slice(&seed[8..]);
+        Xorshift128Plus::from_state(u64::from_le_bytes(state0), u64::from_le_bytes(state1))
+    }
+
+    fn seed_from_u64(seed: u64) -> Xorshift128Plus {
+        Xorshift128Plus::from_seed(seed)
+    }
+}
+
+#[test]
+fn xorshift_works() {
+    // node --random_seed=42 -e 'for (...) console.log(Math.random())'
+    let mut math = MathRandom::from_seed(42);
+    let outputs: Vec<f64> = (0..130).map(|_| math.random()).collect();
+    assert_eq!(outputs[0], 0.7939112874678715);
+    assert_eq!(outputs[1], 0.5254990606499601);
+    assert_eq!(outputs[63], 0.08156904043271651);
+    assert_eq!(outputs[64], 0.4706713645501157);
+    assert_eq!(outputs[65], 0.2603201442309373);
+
+    // in generation order
+    let mut rng = Xorshift128Plus::seed_from_u64(42);
+    let doubles: Vec<f64> = (0..5).map(|_| rng.next_double()).collect();
+    assert_eq!(doubles[0], outputs[63]);
+    let mut clone = Xorshift128Plus::clone_from_doubles(&doubles).unwrap();
+    for _ in 0..10 {
+        assert_eq!(clone.extract_number(), rng.extract_number());
+    }
+    assert!(Xorshift128Plus::clone_from_doubles(&doubles[..1]).is_err());
+    assert!(Xorshift128Plus::clone_from_doubles(&[0.5, 1.5, 0.25]).is_err());
+
+    // as a page sees them, across a cache refill
+    let mut clone = MathRandom::clone_from_outputs(&outputs[60..66]).unwrap();
+    for &output in outputs[66..].iter() {
+        assert_eq!(clone.random(), output);
+    }
+    for _ in 0..100 {
+        assert_eq!(clone.random(), math.random());
+    }
+    let mut tampered = outputs[60..66].to_vec();
+    tampered[2] = 0.5;
+    assert!(MathRandom::clone_from_outputs(&tampered).is_err());
+}